@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use prompt_dedup::{Database, Prompt, PromptFilter};
+use rusqlite::Result;
+
+/// Prompts read from the database at a time
+const PAGE_SIZE: usize = 200;
+/// Pages kept loaded; the ones furthest from the rows on screen are dropped first
+const MAX_PAGES: usize = 6;
+/// Height given to rows that haven't been laid out yet
+const ESTIMATED_ROW_HEIGHT: f32 = 36.0;
+
+/// The prompts Browse lists, read a page at a time as the list scrolls so a
+/// large library is never held in memory. Row heights are kept once laid out,
+/// so only the rows on screen need laying out each frame.
+#[derive(Default)]
+pub struct BrowseList {
+    filter: PromptFilter,
+    /// Prompts matching `filter`
+    len: usize,
+    pages: HashMap<usize, Vec<Prompt>>,
+    /// Tags of the prompts in `pages`
+    tags: HashMap<i64, Vec<String>>,
+    /// Height of every row including the spacing below it, estimated until laid out
+    heights: Vec<f32>,
+    /// List width the heights were measured at
+    width: f32,
+}
+
+impl BrowseList {
+    /// Count the prompts matching `filter` and forget the loaded pages. Row
+    /// heights are kept, rows that changed are measured again when shown.
+    /// The list is left empty when the count fails.
+    pub fn reload(&mut self, db: &Database, filter: &PromptFilter) -> Result<()> {
+        self.filter = filter.clone();
+        self.pages.clear();
+        self.tags.clear();
+        let count = db.count_matching(filter);
+        self.len = *count.as_ref().unwrap_or(&0);
+        self.heights.resize(self.len, ESTIMATED_ROW_HEIGHT);
+        count.map(|_| ())
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Rows overlapping `viewport`, a range of heights within a list `width` wide
+    pub fn visible_rows(&mut self, width: f32, viewport: Range<f32>) -> Range<usize> {
+        // Wrapped rows change height with the width
+        if (width - self.width).abs() > 1.0 {
+            self.width = width;
+            self.heights.fill(ESTIMATED_ROW_HEIGHT);
+        }
+        let mut top = 0.0;
+        let mut first = 0;
+        while first < self.len && top + self.heights[first] <= viewport.start {
+            top += self.heights[first];
+            first += 1;
+        }
+        let mut last = first;
+        while last < self.len && top < viewport.end {
+            top += self.heights[last];
+            last += 1;
+        }
+        first..last
+    }
+
+    /// Combined height of `rows`
+    pub fn height_of(&self, rows: Range<usize>) -> f32 {
+        self.heights[rows].iter().sum()
+    }
+
+    /// Remember how tall row `index` turned out
+    pub fn set_height(&mut self, index: usize, height: f32) {
+        if let Some(row) = self.heights.get_mut(index) {
+            *row = height;
+        }
+    }
+
+    /// Rows `range` with their index, reading the pages that aren't loaded
+    /// yet. Fails when a page can't be read; it is tried again next time.
+    pub fn rows(&mut self, db: &Database, range: Range<usize>) -> Result<Vec<(usize, Prompt)>> {
+        if range.is_empty() {
+            return Ok(Vec::new());
+        }
+        let needed = range.start / PAGE_SIZE..range.end.div_ceil(PAGE_SIZE);
+        for page in needed.clone() {
+            if !self.pages.contains_key(&page) {
+                let prompts = db.query_page(&self.filter, page * PAGE_SIZE, PAGE_SIZE)?;
+                let ids: Vec<i64> = prompts.iter().map(|prompt| prompt.id).collect();
+                self.tags.extend(db.tag_memberships_of(&ids)?);
+                self.pages.insert(page, prompts);
+            }
+        }
+
+        let distance = |page: usize| {
+            if page < needed.start { needed.start - page } else { page.saturating_sub(needed.end - 1) }
+        };
+        while self.pages.len() > MAX_PAGES.max(needed.len()) {
+            let Some(furthest) = self.pages.keys().copied().max_by_key(|&page| distance(page)) else {
+                break;
+            };
+            for prompt in self.pages.remove(&furthest).into_iter().flatten() {
+                self.tags.remove(&prompt.id);
+            }
+        }
+
+        Ok(range
+            .filter_map(|index| {
+                let prompt = self.pages.get(&(index / PAGE_SIZE))?.get(index % PAGE_SIZE)?;
+                Some((index, prompt.clone()))
+            })
+            .collect())
+    }
+
+    /// Tags of a prompt on a loaded page
+    pub fn tags(&self, id: i64) -> &[String] {
+        self.tags.get(&id).map(Vec::as_slice).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn library(prompts: usize) -> Database {
+        let db = Database::open(":memory:").unwrap();
+        for i in 0..prompts {
+            db.insert_prompt(&format!("prompt number {}", i), None).unwrap();
+        }
+        db
+    }
+
+    #[test]
+    fn reads_rows_a_page_at_a_time() {
+        let db = library(PAGE_SIZE + 10);
+        let mut list = BrowseList::default();
+        list.reload(&db, &PromptFilter::default()).unwrap();
+        assert_eq!(list.len(), PAGE_SIZE + 10);
+
+        let rows = list.rows(&db, PAGE_SIZE - 2..PAGE_SIZE + 2).unwrap();
+        let indices: Vec<usize> = rows.iter().map(|(i, _)| *i).collect();
+        assert_eq!(indices, [PAGE_SIZE - 2, PAGE_SIZE - 1, PAGE_SIZE, PAGE_SIZE + 1]);
+        assert_eq!(list.pages.len(), 2);
+    }
+
+    #[test]
+    fn drops_pages_furthest_from_view() {
+        let pages = MAX_PAGES + 2;
+        let db = library(PAGE_SIZE * pages);
+        let mut list = BrowseList::default();
+        list.reload(&db, &PromptFilter::default()).unwrap();
+
+        assert_eq!(list.rows(&db, 0..PAGE_SIZE * pages).unwrap().len(), PAGE_SIZE * pages);
+        assert_eq!(list.pages.len(), pages);
+        // Nothing in view, e.g. a list scrolled to the top with no room for rows
+        assert!(list.rows(&db, 0..0).unwrap().is_empty());
+
+        list.rows(&db, 0..1).unwrap();
+        assert_eq!(list.pages.len(), MAX_PAGES);
+        assert!(list.pages.contains_key(&0));
+        assert!(!list.pages.contains_key(&(pages - 1)));
+    }
+
+    #[test]
+    fn visible_rows_follow_row_heights() {
+        let db = library(10);
+        let mut list = BrowseList::default();
+        list.reload(&db, &PromptFilter::default()).unwrap();
+
+        assert_eq!(list.visible_rows(300.0, 0.0..ESTIMATED_ROW_HEIGHT * 2.5), 0..3);
+        list.set_height(0, ESTIMATED_ROW_HEIGHT * 3.0);
+        assert_eq!(list.visible_rows(300.0, ESTIMATED_ROW_HEIGHT * 3.0..ESTIMATED_ROW_HEIGHT * 4.0), 1..2);
+        // A new width measures every row again
+        assert_eq!(list.visible_rows(500.0, 0.0..ESTIMATED_ROW_HEIGHT), 0..1);
+        assert_eq!(list.height_of(0..10), ESTIMATED_ROW_HEIGHT * 10.0);
+    }
+}
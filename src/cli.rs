@@ -0,0 +1,843 @@
+use serde_json::{Value, json};
+use std::collections::HashSet;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use prompt_dedup::export::ExportFilter;
+use prompt_dedup::db::{is_date, parse_tags};
+use prompt_dedup::similarity;
+use prompt_dedup::{
+    Decision, ExportColumn, ExportFormat, ImportMode, ImportOptions, Library, PromptFilter, PromptSort, RuleKind, TagMatch, TagRule,
+    TextEncoding,
+};
+
+/// Exit codes for scripted runs
+const EXIT_OK: i32 = 0;
+const EXIT_FAILURE: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+/// `scan --check` found similar pairs
+const EXIT_DUPLICATES: i32 = 3;
+
+const DEFAULT_THRESHOLD: f32 = 0.80;
+
+/// Invalid line numbers listed per imported file, the count covers the rest
+const MAX_LISTED_LINES: usize = 100;
+
+const USAGE: &str = "\
+Usage: prompt-dedup <command> [options]
+Run without arguments to open the app.
+
+Commands:
+  import <path>...          Import text files, or wildcard folders
+      --mode <mode>         lines, blank, delimiter, regex or positive-negative
+      --delimiter <text>    Separator for --mode delimiter
+      --pattern <regex>     Separator for --mode regex
+      --encoding <name>     auto, utf-8, utf-16le, utf-16be or windows-1252
+      --tags <list>         Comma-separated tags for every prompt in the files
+      --no-rules            Don't apply the tag rules to imported prompts
+  scan                      List similar pairs
+      --threshold <0-1>     Minimum similarity (default 0.8)
+      --check               Exit with 3 when any pair is found
+      --negatives           Pairs must have similar negative prompts too
+  dedupe                    Delete the second prompt of every similar pair
+      --threshold <0-1>     Minimum similarity (default 0.8)
+      --negatives           Pairs must have similar negative prompts too
+      --dry-run             Report what would be deleted
+  export <file>             Export prompts
+      --format <format>     txt, csv, json or jsonl (default: from extension)
+      --columns <list>      Comma-separated: id,text,source_file,created_at,tags,
+                            collections,wildcards,cluster_id,negative_prompt,
+                            model,settings,rating,favorite,notes
+      --sample <n>          Export n random prompts
+      (and the filter options below)
+  tag <tag>                 Tag every prompt matching the filter options
+      --remove              Take the tag off instead
+  rules                     List the tag rules
+  rules add <pattern> <tag> Tag prompts matching the |-separated keywords
+      --regex               Treat the pattern as a regular expression
+  rules remove <id>         Delete a tag rule
+  rules enable <id>         Turn a tag rule back on
+  rules disable <id>        Keep a tag rule but stop applying it
+  categorize                Apply the tag rules to prompts matching the filter options
+      --dry-run             Report what each rule would tag and any conflicts
+  edit <id> <text>          Change a prompt's text
+      --merge               If another prompt has that text, merge this one into it
+  sources <id>              Every file and line a prompt was imported from
+  history <id>              Every change to a prompt's text, newest first
+  history restore <version> Set a prompt back to its text before that change
+  imports                   List past imports, newest first
+  imports undo <id>         Delete the prompts an import added, except edited ones
+                            and ones another import found too
+  stats                     Counts for the library
+  replace <find> <replace>  Find & Replace across all prompts
+      --case-sensitive
+      --dry-run             Report what would change
+
+Filter options:
+  --search <text>           Only prompts containing this text
+  --history                 Let --search match text from before an edit too
+  --wildcard <name>         Only entries of this wildcard
+  --collection <name>       Only prompts in this collection
+  --tags <list>             Only prompts with these comma-separated tags
+  --match <any|all>         Whether prompts need any (default) or all of --tags
+  --favorites               Only favorite prompts
+  --min-rating <1-5>        Only prompts rated at least this many stars
+  --model <name>            Only prompts for this model
+  --source <file>           Only prompts imported from this file
+  --imported-from <date>    Only prompts first imported on or after YYYY-MM-DD
+  --imported-to <date>      Only prompts first imported on or before YYYY-MM-DD
+  --min-length <n>          Only prompts at least n characters long
+  --max-length <n>          Only prompts at most n characters long
+  --lora                    Only prompts loading a LoRA (<lora:...>)
+  --weights                 Only prompts with attention weights ((text:1.2))
+  --in-cluster              Only prompts in a duplicate cluster
+  --threshold <0-1>         Similarity for --in-cluster, --sort cluster and the
+                            cluster_id export column (default 0.8)
+  --sort <order>            default, newest, oldest, length, words, alphabetical,
+                            source, rating, model, favorites or cluster
+
+Global options:
+  --db <path>               Library file (default: prompts.db in the app data folder);
+                            only import creates it when missing
+  --migrate                 Upgrade a library made by an older version. It is backed
+                            up first, and prompts that now count as duplicates are
+                            merged, listed under \"upgrade\" in the output
+
+Results are printed to stdout as JSON. Errors are printed to stderr as
+{\"error\": ...} with exit code 1, or 2 for invalid arguments.";
+
+enum CliError {
+    Usage(String),
+    Failed(String),
+}
+
+impl From<prompt_dedup::Error> for CliError {
+    fn from(e: prompt_dedup::Error) -> Self {
+        CliError::Failed(e.to_string())
+    }
+}
+
+impl From<rusqlite::Error> for CliError {
+    fn from(e: rusqlite::Error) -> Self {
+        prompt_dedup::Error::from(e).into()
+    }
+}
+
+type CliResult = Result<(Value, i32), CliError>;
+
+/// Parsed arguments of one command: positionals, `--flag value` options and bare switches
+struct Args {
+    positional: Vec<String>,
+    options: Vec<(String, String)>,
+    switches: HashSet<String>,
+}
+
+/// Options that are followed by a value
+const VALUE_OPTIONS: [&str; 22] = [
+    "db", "mode", "delimiter", "pattern", "encoding", "threshold", "format", "columns", "search", "wildcard", "sample",
+    "collection", "tags", "match", "min-rating", "model", "sort", "source", "imported-from", "imported-to", "min-length",
+    "max-length",
+];
+
+/// Options read by `prompt_filter`
+const FILTER_OPTIONS: [&str; 19] = [
+    "search", "wildcard", "collection", "tags", "match", "favorites", "min-rating", "model", "sort", "history", "source",
+    "imported-from", "imported-to", "min-length", "max-length", "lora", "weights", "in-cluster", "threshold",
+];
+
+impl Args {
+    fn parse(args: &[String]) -> Result<Args, CliError> {
+        let mut parsed = Args {
+            positional: Vec::new(),
+            options: Vec::new(),
+            switches: HashSet::new(),
+        };
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            let Some(name) = arg.strip_prefix("--") else {
+                parsed.positional.push(arg.clone());
+                continue;
+            };
+            if let Some((name, value)) = name.split_once('=') {
+                parsed.options.push((name.to_string(), value.to_string()));
+            } else if VALUE_OPTIONS.contains(&name) {
+                let value = iter.next()
+                    .ok_or_else(|| CliError::Usage(format!("--{} needs a value", name)))?;
+                parsed.options.push((name.to_string(), value.clone()));
+            } else {
+                parsed.switches.insert(name.to_string());
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn option(&self, name: &str) -> Option<&str> {
+        self.options.iter().rev().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+
+    fn switch(&self, name: &str) -> bool {
+        self.switches.contains(name)
+    }
+
+    fn threshold(&self) -> Result<f32, CliError> {
+        match self.option("threshold") {
+            None => Ok(DEFAULT_THRESHOLD),
+            Some(value) => value.parse::<f32>()
+                .ok()
+                .filter(|t| (0.0..=1.0).contains(t))
+                .ok_or_else(|| CliError::Usage(format!("Invalid threshold '{}', use a number from 0 to 1", value))),
+        }
+    }
+
+    /// Reject options that don't belong to the command, so typos don't go unnoticed
+    fn only(&self, allowed: &[&str]) -> Result<(), CliError> {
+        let names = self.options.iter().map(|(n, _)| n).chain(self.switches.iter());
+        for name in names {
+            if name != "db" && name != "migrate" && !allowed.contains(&name.as_str()) {
+                return Err(CliError::Usage(format!("Unknown option --{}", name)));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Run a command line. Returns the process exit code.
+pub fn run(args: &[String]) -> i32 {
+    let Some(command) = args.first() else {
+        return EXIT_USAGE;
+    };
+    if matches!(command.as_str(), "help" | "--help" | "-h") {
+        println!("{}", USAGE);
+        return EXIT_OK;
+    }
+
+    let outcome = Args::parse(&args[1..]).and_then(|args| {
+        let db_path = args.option("db")
+            .map(str::to_string)
+            .unwrap_or_else(|| prompt_dedup::default_library_path().to_string_lossy().to_string());
+        let handler: fn(&Args, &str) -> CliResult = match command.as_str() {
+            "import" => import,
+            "scan" => scan,
+            "dedupe" => dedupe,
+            "export" => export,
+            "tag" => tag,
+            "rules" => rules,
+            "categorize" => categorize,
+            "edit" => edit,
+            "sources" => sources,
+            "history" => history,
+            "imports" => imports,
+            "stats" => stats,
+            "replace" => replace,
+            other => return Err(CliError::Usage(format!("Unknown command '{}'", other))),
+        };
+        // Only an import starts a new library; anything else would just
+        // leave an empty one behind at a mistyped --db path
+        if command != "import" && !Path::new(&db_path).is_file() {
+            return Err(CliError::Failed(format!("No library at {}", db_path)));
+        }
+        let upgrade = upgrade_library(&args, &db_path)?;
+        let (mut output, code) = handler(&args, &db_path)?;
+        if let Some(upgrade) = upgrade {
+            output["upgrade"] = upgrade;
+        }
+        Ok((output, code))
+    });
+
+    match outcome {
+        Ok((output, code)) => {
+            // A closed pipe (e.g. `| head`) is not worth a panic
+            let _ = writeln!(io::stdout(), "{}", serde_json::to_string_pretty(&output).unwrap_or_default());
+            code
+        }
+        Err(CliError::Usage(message)) => {
+            eprintln!("{}", json!({ "error": message }));
+            eprintln!("\n{}", USAGE);
+            EXIT_USAGE
+        }
+        Err(CliError::Failed(message)) => {
+            eprintln!("{}", json!({ "error": message }));
+            EXIT_FAILURE
+        }
+    }
+}
+
+/// Upgrade a library made by an older version, only when `--migrate` asks for
+/// it since the upgrade can merge prompts. Returns what the upgrade did.
+fn upgrade_library(args: &Args, path: &str) -> Result<Option<Value>, CliError> {
+    let Some(version) = Library::pending_upgrade(path)? else {
+        return Ok(None);
+    };
+    if !args.switch("migrate") {
+        return Err(CliError::Failed(format!(
+            "Database {} has schema version {} and needs upgrading to {}, which can merge prompts \
+             that now count as duplicates. Run again with --migrate to back it up and upgrade it",
+            path, version, prompt_dedup::SCHEMA_VERSION
+        )));
+    }
+
+    let mut library = open_library(path)?;
+    let Some(report) = library.db_mut().take_upgrade_report() else {
+        return Ok(None);
+    };
+    // Prompts merged by the upgrade are handed out separately
+    let merged = library.db_mut().take_migration_report().unwrap_or_default();
+    let merged: Vec<Value> = merged.collisions.iter()
+        .map(|collision| json!({
+            "kept": collision.kept.id,
+            "removed": collision.removed.iter().map(|p| p.id).collect::<Vec<_>>(),
+        }))
+        .collect();
+    Ok(Some(json!({
+        "from": report.from,
+        "to": report.to,
+        "backup": report.backup.map(|path| path.to_string_lossy().to_string()),
+        "steps": report.steps,
+        "merged": merged,
+    })))
+}
+
+fn open_library(path: &str) -> Result<Library, CliError> {
+    Library::open(path).map_err(|e| CliError::Failed(format!("Failed to open database {}: {}", path, e)))
+}
+
+/// For commands that only read, so they never create or change the file
+fn open_library_read_only(path: &str) -> Result<Library, CliError> {
+    Library::open_read_only(path).map_err(|e| CliError::Failed(format!("Failed to open database {}: {}", path, e)))
+}
+
+fn import_options(args: &Args) -> Result<(ImportOptions, TextEncoding), CliError> {
+    let mut options = ImportOptions::default();
+    if let Some(mode) = args.option("mode") {
+        options.mode = match mode {
+            "lines" => ImportMode::OnePerLine,
+            "blank" => ImportMode::BlankLineSeparated,
+            "delimiter" => ImportMode::Delimiter,
+            "regex" => ImportMode::Regex,
+            "positive-negative" => ImportMode::PositiveNegative,
+            other => return Err(CliError::Usage(format!("Unknown import mode '{}'", other))),
+        };
+    }
+    if let Some(delimiter) = args.option("delimiter") {
+        options.delimiter = delimiter.to_string();
+    }
+    if let Some(pattern) = args.option("pattern") {
+        options.pattern = pattern.to_string();
+    }
+    if let Some(tags) = args.option("tags") {
+        options.tags = parse_tags(tags);
+    }
+    options.apply_rules = !args.switch("no-rules");
+
+    let encoding = match args.option("encoding").map(str::to_lowercase).as_deref() {
+        None | Some("auto") => TextEncoding::Auto,
+        Some("utf-8" | "utf8") => TextEncoding::Utf8,
+        Some("utf-16le" | "utf16le") => TextEncoding::Utf16Le,
+        Some("utf-16be" | "utf16be") => TextEncoding::Utf16Be,
+        Some("windows-1252" | "latin-1" | "latin1") => TextEncoding::Windows1252,
+        Some(other) => return Err(CliError::Usage(format!("Unknown encoding '{}'", other))),
+    };
+    Ok((options, encoding))
+}
+
+fn import(args: &Args, db_path: &str) -> CliResult {
+    args.only(&["mode", "delimiter", "pattern", "encoding", "tags", "no-rules"])?;
+    if args.positional.is_empty() {
+        return Err(CliError::Usage("import needs at least one file or folder".to_string()));
+    }
+    let (options, encoding) = import_options(args)?;
+    // Fail on a bad database before reading anything
+    let mut library = open_library(db_path)?;
+
+    let mut results = Vec::new();
+    for path in &args.positional {
+        let path = Path::new(path);
+        if path.is_dir() {
+            let import = library.import_wildcards(path)?;
+            results.push(json!({
+                "path": path.to_string_lossy(),
+                "kind": "wildcards",
+                "wildcards": import.summary.wildcards,
+                "entries": import.summary.entries,
+                "added": import.summary.added,
+                "templates": import.summary.templates,
+                "errors": import.errors,
+            }));
+            continue;
+        }
+
+        let summary = library.import_file(path, encoding, &options)?;
+        results.push(json!({
+            "path": path.to_string_lossy(),
+            "kind": "file",
+            "records": summary.total,
+            "added": summary.added,
+            "duplicates": summary.total - summary.added,
+            "invalid_lines": summary.invalid_lines.len(),
+            "invalid_line_numbers": &summary.invalid_lines[..summary.invalid_lines.len().min(MAX_LISTED_LINES)],
+            "batch": summary.batch_id,
+            "previously_imported": summary.previous_import,
+        }));
+    }
+
+    Ok((json!({ "imported": results, "total_prompts": library.count()? }), EXIT_OK))
+}
+
+fn prompt_filter(args: &Args) -> Result<PromptFilter, CliError> {
+    let tag_match = match args.option("match") {
+        None | Some("any") => TagMatch::Any,
+        Some("all") => TagMatch::All,
+        Some(other) => return Err(CliError::Usage(format!("Unknown --match '{}', use any or all", other))),
+    };
+    let min_rating = args.option("min-rating")
+        .map(|value| value.parse::<u8>().ok()
+            .filter(|n| (1..=5).contains(n))
+            .ok_or_else(|| CliError::Usage(format!("Invalid rating '{}', use 1 to 5", value))))
+        .transpose()?;
+    let sort = match args.option("sort") {
+        None => PromptSort::Default,
+        Some(key) => PromptSort::from_key(key)
+            .ok_or_else(|| CliError::Usage(format!("Unknown sort '{}'", key)))?,
+    };
+    let date = |name: &str| {
+        args.option(name)
+            .map(|value| match is_date(value) {
+                true => Ok(value.to_string()),
+                false => Err(CliError::Usage(format!("Invalid --{} '{}', use YYYY-MM-DD", name, value))),
+            })
+            .transpose()
+    };
+    let length = |name: &str| {
+        args.option(name)
+            .map(|value| value.parse::<usize>()
+                .map_err(|_| CliError::Usage(format!("Invalid --{} '{}'", name, value))))
+            .transpose()
+    };
+    Ok(PromptFilter {
+        search: args.option("search").unwrap_or("").to_string(),
+        wildcard: args.option("wildcard").map(str::to_string),
+        collection: args.option("collection").map(str::to_string),
+        tags: args.option("tags").map(parse_tags).unwrap_or_default(),
+        tag_match,
+        favorites_only: args.switch("favorites"),
+        min_rating,
+        model: args.option("model").map(str::to_string),
+        sort,
+        search_history: args.switch("history"),
+        source_file: args.option("source").map(str::to_string),
+        imported_from: date("imported-from")?,
+        imported_to: date("imported-to")?,
+        min_length: length("min-length")?,
+        max_length: length("max-length")?,
+        has_lora: args.switch("lora"),
+        has_weights: args.switch("weights"),
+        in_cluster: args.switch("in-cluster"),
+    })
+}
+
+/// Scan for duplicates first when the filter needs clusters, see `PromptFilter::uses_clusters`
+fn load_clusters(library: &mut Library, filter: &PromptFilter, args: &Args) -> Result<(), CliError> {
+    if filter.uses_clusters() {
+        let pairs = library.scan(args.threshold()?, false)?;
+        library.db_mut().set_clusters(&similarity::clusters_from_pairs(&pairs))?;
+    }
+    Ok(())
+}
+
+/// Two decimals, without the f32 to f64 noise (0.7 instead of 0.699999988)
+fn rounded(value: f32) -> f64 {
+    (value as f64 * 100.0).round() / 100.0
+}
+
+fn scan(args: &Args, db_path: &str) -> CliResult {
+    args.only(&["threshold", "check", "negatives"])?;
+    let threshold = args.threshold()?;
+    let library = open_library_read_only(db_path)?;
+    let pairs = library.scan(threshold, args.switch("negatives"))?;
+
+    let code = if args.switch("check") && !pairs.is_empty() {
+        EXIT_DUPLICATES
+    } else {
+        EXIT_OK
+    };
+    let pairs: Vec<Value> = pairs.iter()
+        .map(|p| json!({
+            "id_a": p.id_a,
+            "text_a": p.text_a,
+            "id_b": p.id_b,
+            "text_b": p.text_b,
+            "similarity": rounded(p.similarity),
+        }))
+        .collect();
+    Ok((json!({ "threshold": rounded(threshold), "pair_count": pairs.len(), "pairs": pairs }), code))
+}
+
+fn dedupe(args: &Args, db_path: &str) -> CliResult {
+    args.only(&["threshold", "dry-run", "negatives"])?;
+    let threshold = args.threshold()?;
+    let dry_run = args.switch("dry-run");
+    let mut library = if dry_run { open_library_read_only(db_path)? } else { open_library(db_path)? };
+    let pairs = library.scan(threshold, args.switch("negatives"))?;
+
+    // Same rule as "Remove All": keep the first prompt of each pair
+    let removed = if dry_run {
+        let mut ids: Vec<i64> = pairs.iter().map(|p| p.id_b).collect();
+        ids.sort_unstable();
+        ids.dedup();
+        ids
+    } else {
+        let decisions: Vec<Decision> = pairs.iter().map(Decision::keep_first).collect();
+        library.resolve(&decisions)?.deleted
+    };
+
+    Ok((json!({
+        "threshold": rounded(threshold),
+        "dry_run": dry_run,
+        "pair_count": pairs.len(),
+        "removed": removed.len(),
+        "removed_ids": removed,
+        "total_prompts": library.count()?,
+    }), EXIT_OK))
+}
+
+fn export(args: &Args, db_path: &str) -> CliResult {
+    args.only(&[&["format", "columns", "sample"][..], &FILTER_OPTIONS].concat())?;
+    let [path] = args.positional.as_slice() else {
+        return Err(CliError::Usage("export needs exactly one output file".to_string()));
+    };
+    let path = PathBuf::from(path);
+
+    let format_name = args.option("format")
+        .map(str::to_string)
+        .or_else(|| path.extension().map(|e| e.to_string_lossy().to_lowercase()))
+        .unwrap_or_else(|| "txt".to_string());
+    let format = ExportFormat::ALL.into_iter()
+        .find(|f| f.extension() == format_name)
+        .ok_or_else(|| CliError::Usage(format!("Unknown export format '{}'", format_name)))?;
+
+    let columns = match args.option("columns") {
+        None => vec![ExportColumn::Id, ExportColumn::Text, ExportColumn::SourceFile],
+        Some(list) => list.split(',')
+            .map(|name| ExportColumn::ALL.into_iter()
+                .find(|c| c.name() == name.trim())
+                .ok_or_else(|| CliError::Usage(format!("Unknown column '{}'", name.trim()))))
+            .collect::<Result<_, _>>()?,
+    };
+
+    let mut filter = ExportFilter::default();
+    if let Some(sample) = args.option("sample") {
+        let size = sample.parse::<usize>().ok().filter(|n| *n > 0)
+            .ok_or_else(|| CliError::Usage(format!("Invalid sample size '{}'", sample)))?;
+        filter.sample = Some(size);
+    }
+
+    let view = prompt_filter(args)?;
+    let mut library = open_library_read_only(db_path)?;
+    load_clusters(&mut library, &view, args)?;
+    let prompts = library.query(&view)?;
+    let total = prompts.len();
+    let prompts = filter.sample(prompts);
+
+    let pairs = if columns.contains(&ExportColumn::ClusterId) {
+        library.scan(args.threshold()?, false)?
+    } else {
+        Vec::new()
+    };
+    let count = library.export(&prompts, &path, format, &columns, &pairs)?;
+    Ok((json!({
+        "path": path.to_string_lossy(),
+        "format": format.extension(),
+        "exported": count,
+        "matched": total,
+        "description": filter.describe(&view, total),
+    }), EXIT_OK))
+}
+
+fn tag(args: &Args, db_path: &str) -> CliResult {
+    args.only(&[&["remove"][..], &FILTER_OPTIONS].concat())?;
+    let [name] = args.positional.as_slice() else {
+        return Err(CliError::Usage("tag needs exactly one tag name".to_string()));
+    };
+    let [name] = parse_tags(name).try_into().map_err(|_| {
+        CliError::Usage(format!("Invalid tag name '{}', it can't be empty or contain commas", name))
+    })?;
+    let view = prompt_filter(args)?;
+    let remove = args.switch("remove");
+    let mut library = open_library(db_path)?;
+    load_clusters(&mut library, &view, args)?;
+
+    let ids: Vec<i64> = library.query(&view)?.iter().map(|p| p.id).collect();
+    let changed = if remove {
+        library.db_mut().remove_tag(&ids, &name)?
+    } else {
+        library.db_mut().add_tag(&ids, &name)?
+    };
+    Ok((json!({
+        "tag": name,
+        "removed": remove,
+        "matched": ids.len(),
+        "changed": changed,
+    }), EXIT_OK))
+}
+
+fn rule_json(rule: &TagRule) -> Value {
+    json!({
+        "id": rule.id,
+        "kind": rule.kind.key(),
+        "pattern": rule.pattern,
+        "tag": rule.tag,
+        "enabled": rule.enabled,
+    })
+}
+
+fn rules(args: &Args, db_path: &str) -> CliResult {
+    args.only(&["regex"])?;
+    let library = open_library(db_path)?;
+    let rule_id = |id: &str| {
+        id.parse::<i64>().map_err(|_| CliError::Usage(format!("Invalid rule id '{}'", id)))
+    };
+    let missing = |id: i64| CliError::Failed(format!("No tag rule with id {}", id));
+
+    let positional: Vec<&str> = args.positional.iter().map(String::as_str).collect();
+    match positional.as_slice() {
+        [] => {
+            let rules: Vec<Value> = library.db().tag_rules()?.iter().map(rule_json).collect();
+            Ok((json!({ "rules": rules }), EXIT_OK))
+        }
+        ["add", pattern, tag] => {
+            let kind = if args.switch("regex") { RuleKind::Regex } else { RuleKind::Keywords };
+            let id = library.add_tag_rule(kind, pattern, tag)?;
+            let rule = library.db().tag_rules()?.into_iter().find(|rule| rule.id == id);
+            Ok((json!({ "added": rule.as_ref().map(rule_json) }), EXIT_OK))
+        }
+        ["remove", id] => {
+            let id = rule_id(id)?;
+            if !library.db().delete_tag_rule(id)? {
+                return Err(missing(id));
+            }
+            Ok((json!({ "removed": id }), EXIT_OK))
+        }
+        [action @ ("enable" | "disable"), id] => {
+            let id = rule_id(id)?;
+            let enabled = *action == "enable";
+            if !library.db().set_tag_rule_enabled(id, enabled)? {
+                return Err(missing(id));
+            }
+            Ok((json!({ "id": id, "enabled": enabled }), EXIT_OK))
+        }
+        _ => Err(CliError::Usage("Use rules, rules add <pattern> <tag>, or rules remove|enable|disable <id>".to_string())),
+    }
+}
+
+fn categorize(args: &Args, db_path: &str) -> CliResult {
+    args.only(&[&["dry-run"][..], &FILTER_OPTIONS].concat())?;
+    if !args.positional.is_empty() {
+        return Err(CliError::Usage("categorize takes no arguments".to_string()));
+    }
+    let view = prompt_filter(args)?;
+    let mut library = open_library(db_path)?;
+    load_clusters(&mut library, &view, args)?;
+
+    let report = library.categorize(&view, args.switch("dry-run"))?;
+    let rules: Vec<Value> = report.rules.iter()
+        .map(|outcome| json!({
+            "id": outcome.rule.id,
+            "pattern": outcome.rule.pattern,
+            "tag": outcome.rule.tag,
+            "matched": outcome.matched,
+            "added": outcome.added,
+        }))
+        .collect();
+    let conflicts: Vec<Value> = report.conflicts.iter()
+        .map(|conflict| json!({
+            "namespace": conflict.namespace,
+            "tags": conflict.tags,
+            "prompts": conflict.prompt_ids.len(),
+            "example_ids": conflict.prompt_ids.iter().take(10).collect::<Vec<_>>(),
+        }))
+        .collect();
+    Ok((json!({
+        "dry_run": report.dry_run,
+        "checked": report.checked,
+        "tagged_prompts": report.tagged_prompts(),
+        "tags_added": report.assignments.len(),
+        "rules": rules,
+        "conflicts": conflicts,
+    }), EXIT_OK))
+}
+
+fn sources(args: &Args, db_path: &str) -> CliResult {
+    args.only(&[])?;
+    let [id] = args.positional.as_slice() else {
+        return Err(CliError::Usage("sources needs exactly one prompt id".to_string()));
+    };
+    let id: i64 = id.parse().map_err(|_| CliError::Usage(format!("Invalid prompt id '{}'", id)))?;
+    let library = open_library_read_only(db_path)?;
+    let Some(prompt) = library.db().get_prompt(id)? else {
+        return Err(CliError::Failed(format!("No prompt with id {}", id)));
+    };
+
+    let sources = library.db().prompt_sources(id)?;
+    let files: HashSet<&str> = sources.iter().map(|s| s.source_file.as_str()).collect();
+    let occurrences: Vec<Value> = sources.iter()
+        .map(|source| json!({
+            "file": source.source_file,
+            "line": source.line,
+            "batch": source.batch_id,
+            "imported_at": source.imported_at,
+        }))
+        .collect();
+    Ok((json!({
+        "id": prompt.id,
+        "text": prompt.text,
+        "files": files.len(),
+        "sources": occurrences,
+    }), EXIT_OK))
+}
+
+fn edit(args: &Args, db_path: &str) -> CliResult {
+    args.only(&["merge"])?;
+    let [id, text] = args.positional.as_slice() else {
+        return Err(CliError::Usage("Use edit <prompt id> <text>".to_string()));
+    };
+    let id: i64 = id.parse().map_err(|_| CliError::Usage(format!("Invalid prompt id '{}'", id)))?;
+    let mut library = open_library(db_path)?;
+    if library.db().get_prompt(id)?.is_none() {
+        return Err(CliError::Failed(format!("No prompt with id {}", id)));
+    }
+    match library.edit_prompt(id, text) {
+        Ok(()) => {
+            let text = library.db().get_prompt(id)?.map(|prompt| prompt.text);
+            Ok((json!({ "id": id, "text": text }), EXIT_OK))
+        }
+        Err(prompt_dedup::Error::DuplicateText(existing)) if args.switch("merge") => {
+            library.merge_prompt(id, existing)?;
+            Ok((json!({ "id": id, "merged_into": existing }), EXIT_OK))
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn history(args: &Args, db_path: &str) -> CliResult {
+    args.only(&[])?;
+    let number = |value: &str, what: &str| {
+        value.parse::<i64>().map_err(|_| CliError::Usage(format!("Invalid {} id '{}'", what, value)))
+    };
+    let positional: Vec<&str> = args.positional.iter().map(String::as_str).collect();
+    match positional.as_slice() {
+        [id] => {
+            let id = number(id, "prompt")?;
+            let library = open_library_read_only(db_path)?;
+            let Some(prompt) = library.db().get_prompt(id)? else {
+                return Err(CliError::Failed(format!("No prompt with id {}", id)));
+            };
+            let versions: Vec<Value> = library.db().prompt_versions(id)?.iter()
+                .map(|version| json!({
+                    "version": version.id,
+                    "reason": version.reason.key(),
+                    "label": version.reason.label(),
+                    "changed_at": version.created_at,
+                    "before": version.old_text,
+                    "after": version.new_text,
+                }))
+                .collect();
+            Ok((json!({ "id": prompt.id, "text": prompt.text, "versions": versions }), EXIT_OK))
+        }
+        ["restore", version] => {
+            let version = number(version, "version")?;
+            let mut library = open_library(db_path)?;
+            let id = library.restore_version(version)?;
+            let text = library.db().get_prompt(id)?.map(|prompt| prompt.text);
+            Ok((json!({ "restored": version, "id": id, "text": text }), EXIT_OK))
+        }
+        _ => Err(CliError::Usage("Use history <prompt id>, or history restore <version id>".to_string())),
+    }
+}
+
+fn imports(args: &Args, db_path: &str) -> CliResult {
+    args.only(&[])?;
+    let positional: Vec<&str> = args.positional.iter().map(String::as_str).collect();
+    match positional.as_slice() {
+        [] => {
+            let library = open_library_read_only(db_path)?;
+            let batches: Vec<Value> = library.db().import_batches()?.iter()
+                .map(|batch| json!({
+                    "id": batch.id,
+                    "file": batch.source_file,
+                    "hash": batch.file_hash,
+                    "imported_at": batch.imported_at,
+                    "records": batch.records,
+                    "added": batch.added,
+                    "remaining": batch.remaining,
+                    "edited": batch.edited,
+                    "seen_later": batch.seen_later,
+                    "undone_at": batch.undone_at,
+                }))
+                .collect();
+            Ok((json!({ "imports": batches }), EXIT_OK))
+        }
+        ["undo", id] => {
+            let id: i64 = id.parse().map_err(|_| CliError::Usage(format!("Invalid import id '{}'", id)))?;
+            let mut library = open_library(db_path)?;
+            let summary = library.undo_import(id)?;
+            Ok((json!({
+                "import": id,
+                "removed": summary.removed,
+                "kept_edited": summary.kept_edited,
+                "kept_seen_later": summary.kept_seen_later,
+                "total_prompts": library.count()?,
+            }), EXIT_OK))
+        }
+        _ => Err(CliError::Usage("Use imports, or imports undo <id>".to_string())),
+    }
+}
+
+fn stats(args: &Args, db_path: &str) -> CliResult {
+    args.only(&[])?;
+    let library = open_library_read_only(db_path)?;
+    let prompts = library.search("")?;
+    let sources: HashSet<&str> = prompts.iter().filter_map(|p| p.source_file.as_deref()).collect();
+
+    Ok((json!({
+        "database": db_path,
+        "prompts": prompts.len(),
+        "templates": prompts.iter().filter(|p| p.is_template).count(),
+        "titled": prompts.iter().filter(|p| p.title.is_some()).count(),
+        "source_files": sources.len(),
+        "wildcards": library.db().wildcards()?.len(),
+        "tags": library.db().tags()?.len(),
+        "collections": library.db().collections()?.len(),
+        "tag_rules": library.db().tag_rules()?.len(),
+        "favorites": prompts.iter().filter(|p| p.metadata.favorite).count(),
+        "rated": prompts.iter().filter(|p| p.metadata.rating.is_some()).count(),
+        "with_negative_prompt": prompts.iter().filter(|p| p.metadata.negative_prompt.is_some()).count(),
+        "models": library.db().models()?.len(),
+        "normalization": library.db().normalize_options().settings()
+            .into_iter()
+            .map(|(key, value)| (key.trim_start_matches("normalize.").to_string(), Value::from(value)))
+            .collect::<serde_json::Map<_, _>>(),
+    }), EXIT_OK))
+}
+
+fn replace(args: &Args, db_path: &str) -> CliResult {
+    args.only(&["case-sensitive", "dry-run"])?;
+    let [find, replacement] = args.positional.as_slice() else {
+        return Err(CliError::Usage("replace needs <find> and <replace>".to_string()));
+    };
+    if find.is_empty() {
+        return Err(CliError::Usage("Nothing to find".to_string()));
+    }
+    let dry_run = args.switch("dry-run");
+    let mut library = open_library(db_path)?;
+
+    let summary = library.replace(find, replacement, args.switch("case-sensitive"), dry_run)?;
+    let changes: Vec<Value> = summary.changed.iter()
+        .map(|c| json!({ "id": c.id, "before": c.original, "after": c.replaced }))
+        .collect();
+    Ok((json!({
+        "dry_run": dry_run,
+        "changed": changes.len(),
+        "changes": changes,
+        "skipped_duplicates": summary.collisions,
+    }), EXIT_OK))
+}
@@ -0,0 +1,61 @@
+use serde_json::{Value, json};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Libraries listed under File > Open Recent
+const MAX_RECENT: usize = 10;
+
+/// App settings that live outside any one library
+#[derive(Debug, Clone, Default)]
+pub struct AppConfig {
+    /// Library that was open when the app last closed or switched
+    pub last_library: Option<PathBuf>,
+    /// Most recent first
+    pub recent: Vec<PathBuf>,
+}
+
+fn config_path() -> PathBuf {
+    prompt_dedup::data_dir().join("config.json")
+}
+
+impl AppConfig {
+    /// Saved config, or an empty one when there is none or it can't be read
+    pub fn load() -> AppConfig {
+        let Some(value) = fs::read_to_string(config_path())
+            .ok()
+            .and_then(|text| serde_json::from_str::<Value>(&text).ok())
+        else {
+            return AppConfig::default();
+        };
+
+        let path = |v: &Value| v.as_str().map(PathBuf::from);
+        AppConfig {
+            last_library: value.get("last_library").and_then(path),
+            recent: value.get("recent")
+                .and_then(Value::as_array)
+                .map(|items| items.iter().filter_map(path).collect())
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = config_path();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let value = json!({
+            "last_library": self.last_library.as_ref().map(|p| p.to_string_lossy()),
+            "recent": self.recent.iter().map(|p| p.to_string_lossy()).collect::<Vec<_>>(),
+        });
+        fs::write(path, serde_json::to_string_pretty(&value)?)
+    }
+
+    /// Remember `path` as the open library and move it to the top of the recent list
+    pub fn opened(&mut self, path: &Path) {
+        self.recent.retain(|p| p != path);
+        self.recent.insert(0, path.to_path_buf());
+        self.recent.truncate(MAX_RECENT);
+        self.last_library = Some(path.to_path_buf());
+    }
+}
@@ -1,55 +1,1034 @@
-use rusqlite::{Connection, Result, params};
+use regex::{NoExpand, Regex, RegexBuilder};
+use rusqlite::functions::FunctionFlags;
+use rusqlite::{Connection, OpenFlags, OptionalExtension, Result, Transaction, params};
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::error::Error;
+use crate::migrations::{self, SCHEMA_VERSION, UpgradeReport};
+use crate::normalize::{self, NormalizeOptions};
+use crate::rules::{RuleKind, RuleSet, TagRule};
+use crate::syntax;
+use crate::wildcards::{self, WildcardSet};
 
 #[derive(Debug, Clone)]
 pub struct Prompt {
     pub id: i64,
     pub text: String,
     pub source_file: Option<String>,
+    /// Uses Dynamic Prompts syntax, see `wildcards::is_template`
+    pub is_template: bool,
+    /// SQLite timestamp, `YYYY-MM-DD HH:MM:SS` in UTC
+    pub created_at: String,
+    /// Short user-given name, used when exporting as styles
+    pub title: Option<String>,
+    pub metadata: PromptMetadata,
+}
+
+/// Optional details kept alongside a prompt's text
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PromptMetadata {
+    pub negative_prompt: Option<String>,
+    /// Checkpoint the prompt was written for
+    pub model: Option<String>,
+    pub sampler: Option<String>,
+    pub steps: Option<i64>,
+    pub cfg_scale: Option<f64>,
+    pub seed: Option<i64>,
+    /// 1 to 5 stars
+    pub rating: Option<u8>,
+    pub favorite: bool,
+    pub notes: Option<String>,
+}
+
+impl PromptMetadata {
+    /// Sampler settings as an A1111 parameters line, e.g.
+    /// `Steps: 20, Sampler: Euler a, CFG scale: 7, Seed: 42`. Empty when none are set.
+    pub fn settings_text(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(steps) = self.steps {
+            parts.push(format!("Steps: {}", steps));
+        }
+        if let Some(sampler) = &self.sampler {
+            parts.push(format!("Sampler: {}", sampler));
+        }
+        if let Some(cfg_scale) = self.cfg_scale {
+            parts.push(format!("CFG scale: {}", cfg_scale));
+        }
+        if let Some(seed) = self.seed {
+            parts.push(format!("Seed: {}", seed));
+        }
+        parts.join(", ")
+    }
+
+    /// Has any of the fields an image generator records: negative prompt,
+    /// model or sampler settings
+    pub fn has_generation_info(&self) -> bool {
+        self.negative_prompt.is_some()
+            || self.model.is_some()
+            || self.sampler.is_some()
+            || self.steps.is_some()
+            || self.cfg_scale.is_some()
+            || self.seed.is_some()
+    }
+
+    /// Fill the settings from an A1111 parameters line. Keys it doesn't know,
+    /// and values that don't parse, are ignored.
+    pub fn read_settings(&mut self, line: &str) {
+        for part in line.split(',') {
+            let Some((key, value)) = part.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            match key.trim().to_lowercase().as_str() {
+                "steps" => self.steps = value.parse().ok().or(self.steps),
+                "sampler" if !value.is_empty() => self.sampler = Some(value.to_string()),
+                "cfg scale" => self.cfg_scale = value.parse().ok().or(self.cfg_scale),
+                "seed" => self.seed = value.parse().ok().or(self.seed),
+                "model" if !value.is_empty() => self.model = Some(value.to_string()),
+                _ => {}
+            }
+        }
+    }
+}
+
+const PROMPT_COLUMNS: &str = "id, text, source_file, is_template, created_at, title, \
+    negative_prompt, model, sampler, steps, cfg_scale, seed, rating, favorite, notes";
+/// `PROMPT_COLUMNS` for queries that join other tables to `prompts p`
+const PROMPT_COLUMNS_P: &str = "p.id, p.text, p.source_file, p.is_template, p.created_at, p.title, \
+    p.negative_prompt, p.model, p.sampler, p.steps, p.cfg_scale, p.seed, p.rating, p.favorite, p.notes";
+
+fn prompt_from_row(row: &rusqlite::Row) -> Result<Prompt> {
+    Ok(Prompt {
+        id: row.get(0)?,
+        text: row.get(1)?,
+        source_file: row.get(2)?,
+        is_template: row.get(3)?,
+        created_at: row.get::<_, Option<String>>(4)?.unwrap_or_default(),
+        title: row.get(5)?,
+        metadata: PromptMetadata {
+            negative_prompt: row.get(6)?,
+            model: row.get(7)?,
+            sampler: row.get(8)?,
+            steps: row.get(9)?,
+            cfg_scale: row.get(10)?,
+            seed: row.get(11)?,
+            rating: row.get(12)?,
+            favorite: row.get(13)?,
+            notes: row.get(14)?,
+        },
+    })
+}
+
+/// One import of a prompt: a file and line it was found in. Skipped
+/// duplicates are recorded too, so a prompt can have many.
+#[derive(Debug, Clone)]
+pub struct PromptSource {
+    pub source_file: String,
+    /// 1-based line the prompt starts on, when known
+    pub line: Option<i64>,
+    /// Shared by every prompt read in the same import
+    pub batch_id: Option<i64>,
+    /// SQLite timestamp, `YYYY-MM-DD HH:MM:SS` in UTC
+    pub imported_at: String,
+}
+
+/// Why a prompt's text changed
+#[derive(Debug, Clone, PartialEq)]
+pub enum EditReason {
+    /// Edited by hand
+    Manual,
+    /// Find & Replace, with the rule that was applied, e.g. `"cat" → "dog"`
+    Replace(String),
+    /// Another prompt was merged into this one; the old text is the merged prompt's
+    Merge,
+    /// An earlier version was restored
+    Restore,
+}
+
+impl EditReason {
+    /// Find & Replace of `find` with `replace`
+    pub fn replace(find: &str, replace: &str) -> EditReason {
+        EditReason::Replace(format!("\"{}\" → \"{}\"", find, replace))
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            EditReason::Manual => "Manual edit".to_string(),
+            EditReason::Replace(rule) => format!("Replace {}", rule),
+            EditReason::Merge => "Merged".to_string(),
+            EditReason::Restore => "Restored".to_string(),
+        }
+    }
+
+    /// Name stored in the database and used on the command line
+    pub fn key(&self) -> &'static str {
+        match self {
+            EditReason::Manual => "manual",
+            EditReason::Replace(_) => "replace",
+            EditReason::Merge => "merge",
+            EditReason::Restore => "restore",
+        }
+    }
+
+    fn detail(&self) -> Option<&str> {
+        match self {
+            EditReason::Replace(rule) => Some(rule),
+            _ => None,
+        }
+    }
+
+    fn from_parts(key: &str, detail: Option<String>) -> EditReason {
+        match key {
+            "replace" => EditReason::Replace(detail.unwrap_or_default()),
+            "merge" => EditReason::Merge,
+            "restore" => EditReason::Restore,
+            _ => EditReason::Manual,
+        }
+    }
+}
+
+/// One change to a prompt's text
+#[derive(Debug, Clone)]
+pub struct PromptVersion {
+    pub id: i64,
+    pub prompt_id: i64,
+    pub old_text: String,
+    pub new_text: String,
+    pub reason: EditReason,
+    /// SQLite timestamp, `YYYY-MM-DD HH:MM:SS` in UTC
+    pub created_at: String,
+}
+
+/// One run of importing a file or wildcard folder
+#[derive(Debug, Clone)]
+pub struct ImportBatch {
+    pub id: i64,
+    pub source_file: String,
+    /// SHA-256 of the file, in hex. Missing for wildcard folders and for
+    /// imports from before batches were recorded.
+    pub file_hash: Option<String>,
+    /// SQLite timestamp, `YYYY-MM-DD HH:MM:SS` in UTC
+    pub imported_at: String,
+    /// Records read, duplicates included
+    pub records: i64,
+    pub added: i64,
+    /// Prompts the batch added that are still in the library
+    pub remaining: i64,
+    /// Remaining prompts that were edited after the import
+    pub edited: i64,
+    /// Remaining unedited prompts that a later import found again
+    pub seen_later: i64,
+    pub undone_at: Option<String>,
+}
+
+/// What undoing an import removed
+#[derive(Debug, Clone, Default)]
+pub struct UndoImportSummary {
+    pub removed: usize,
+    /// Prompts of the batch that were kept because they were edited since
+    pub kept_edited: usize,
+    /// Prompts of the batch that were kept because a later import found them
+    /// too; they now belong to that import
+    pub kept_seen_later: usize,
+}
+
+/// A wildcard collection and how many entries it holds
+#[derive(Debug, Clone)]
+pub struct WildcardInfo {
+    pub name: String,
+    pub entry_count: i64,
+}
+
+/// A tag and how many prompts carry it
+#[derive(Debug, Clone)]
+pub struct TagInfo {
+    pub name: String,
+    pub prompt_count: i64,
+}
+
+/// A named collection and how many prompts it holds
+#[derive(Debug, Clone)]
+pub struct CollectionInfo {
+    pub name: String,
+    pub prompt_count: i64,
+}
+
+/// How several tags in a filter combine
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum TagMatch {
+    /// Prompts with at least one of the tags
+    #[default]
+    Any,
+    /// Prompts with every one of the tags
+    All,
+}
+
+impl TagMatch {
+    pub fn label(&self) -> &'static str {
+        match self {
+            TagMatch::Any => "Any",
+            TagMatch::All => "All",
+        }
+    }
+}
+
+/// Order of a prompt listing
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum PromptSort {
+    /// Collection or wildcard order when filtering by one, otherwise oldest first
+    #[default]
+    Default,
+    /// Highest rated first, unrated last
+    Rating,
+    /// Grouped by model, prompts without one last
+    Model,
+    FavoritesFirst,
+    /// Most recently imported first
+    Newest,
+    Oldest,
+    /// Longest text first
+    Length,
+    /// Most words first, see `syntax::word_count`
+    WordCount,
+    Alphabetical,
+    /// Grouped by the file first imported from
+    SourceFile,
+    /// Biggest duplicate cluster of the last scan first, see `Database::set_clusters`
+    ClusterSize,
+}
+
+impl PromptSort {
+    pub const ALL: [PromptSort; 11] = [
+        PromptSort::Default,
+        PromptSort::Newest,
+        PromptSort::Oldest,
+        PromptSort::Length,
+        PromptSort::WordCount,
+        PromptSort::Alphabetical,
+        PromptSort::SourceFile,
+        PromptSort::Rating,
+        PromptSort::Model,
+        PromptSort::FavoritesFirst,
+        PromptSort::ClusterSize,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PromptSort::Default => "List order",
+            PromptSort::Rating => "Rating",
+            PromptSort::Model => "Model",
+            PromptSort::FavoritesFirst => "Favorites first",
+            PromptSort::Newest => "Newest",
+            PromptSort::Oldest => "Oldest",
+            PromptSort::Length => "Longest",
+            PromptSort::WordCount => "Most words",
+            PromptSort::Alphabetical => "A to Z",
+            PromptSort::SourceFile => "Source file",
+            PromptSort::ClusterSize => "Cluster size",
+        }
+    }
+
+    /// Name used on the command line
+    pub fn key(&self) -> &'static str {
+        match self {
+            PromptSort::Default => "default",
+            PromptSort::Rating => "rating",
+            PromptSort::Model => "model",
+            PromptSort::FavoritesFirst => "favorites",
+            PromptSort::Newest => "newest",
+            PromptSort::Oldest => "oldest",
+            PromptSort::Length => "length",
+            PromptSort::WordCount => "words",
+            PromptSort::Alphabetical => "alphabetical",
+            PromptSort::SourceFile => "source",
+            PromptSort::ClusterSize => "cluster",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<PromptSort> {
+        PromptSort::ALL.into_iter().find(|sort| sort.key() == key)
+    }
+
+    /// `ORDER BY` terms that come before the default order
+    fn order_by(&self) -> Option<&'static str> {
+        match self {
+            PromptSort::Default => None,
+            PromptSort::Rating => Some("p.rating IS NULL, p.rating DESC"),
+            PromptSort::Model => Some("p.model IS NULL, p.model COLLATE NOCASE"),
+            PromptSort::FavoritesFirst => Some("p.favorite DESC"),
+            PromptSort::Newest => Some("p.created_at DESC, p.id DESC"),
+            PromptSort::Oldest => Some("p.created_at"),
+            PromptSort::Length => Some("LENGTH(p.text) DESC"),
+            PromptSort::WordCount => Some("word_count(p.text) DESC"),
+            PromptSort::Alphabetical => Some("p.text COLLATE NOCASE"),
+            PromptSort::SourceFile => Some("p.source_file IS NULL, p.source_file COLLATE NOCASE"),
+            PromptSort::ClusterSize => Some("pc.size IS NULL, pc.size DESC, pc.cluster"),
+        }
+    }
+}
+
+/// Which prompts to list. Empty fields don't filter.
+#[derive(Debug, Clone, Default)]
+pub struct PromptFilter {
+    /// Text the prompt, its negative prompt, model or notes must contain
+    pub search: String,
+    /// Only entries of this wildcard, in file order
+    pub wildcard: Option<String>,
+    /// Only prompts in this collection, in collection order
+    pub collection: Option<String>,
+    pub tags: Vec<String>,
+    pub tag_match: TagMatch,
+    pub favorites_only: bool,
+    /// Only prompts rated at least this many stars
+    pub min_rating: Option<u8>,
+    /// Only prompts for this model
+    pub model: Option<String>,
+    pub sort: PromptSort,
+    /// Let `search` also match text prompts had before they were edited
+    pub search_history: bool,
+    /// Only prompts imported from this file, counting imports skipped as duplicates
+    pub source_file: Option<String>,
+    /// Only prompts first imported on or after this day, `YYYY-MM-DD` in UTC
+    pub imported_from: Option<String>,
+    /// Only prompts first imported on or before this day
+    pub imported_to: Option<String>,
+    /// Only prompts at least this many characters long
+    pub min_length: Option<usize>,
+    pub max_length: Option<usize>,
+    /// Only prompts loading a LoRA, see `syntax::has_lora`
+    pub has_lora: bool,
+    /// Only prompts with attention weights, see `syntax::has_weights`
+    pub has_weights: bool,
+    /// Only prompts in a duplicate cluster of the last scan, see `Database::set_clusters`
+    pub in_cluster: bool,
+}
+
+/// One of the optional narrowing conditions of a `PromptFilter`, shown as a
+/// removable chip in Browse
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Facet {
+    SourceFile,
+    ImportDate,
+    Length,
+    Lora,
+    Weights,
+    Cluster,
+}
+
+impl PromptFilter {
+    pub fn is_empty(&self) -> bool {
+        self.search.is_empty()
+            && self.wildcard.is_none()
+            && self.collection.is_none()
+            && self.tags.is_empty()
+            && !self.favorites_only
+            && self.min_rating.is_none()
+            && self.model.is_none()
+            && self.facets().is_empty()
+    }
+
+    /// Facets that are set, each with a short description like `"100 to 400 characters"`
+    pub fn facets(&self) -> Vec<(Facet, String)> {
+        let mut facets = Vec::new();
+        if let Some(file) = &self.source_file {
+            facets.push((Facet::SourceFile, format!("from {}", file)));
+        }
+        let dates = match (&self.imported_from, &self.imported_to) {
+            (Some(from), Some(to)) => Some(format!("imported {} to {}", from, to)),
+            (Some(from), None) => Some(format!("imported since {}", from)),
+            (None, Some(to)) => Some(format!("imported until {}", to)),
+            (None, None) => None,
+        };
+        if let Some(dates) = dates {
+            facets.push((Facet::ImportDate, dates));
+        }
+        let lengths = match (self.min_length, self.max_length) {
+            (Some(min), Some(max)) => Some(format!("{} to {} characters", min, max)),
+            (Some(min), None) => Some(format!("at least {} characters", min)),
+            (None, Some(max)) => Some(format!("at most {} characters", max)),
+            (None, None) => None,
+        };
+        if let Some(lengths) = lengths {
+            facets.push((Facet::Length, lengths));
+        }
+        if self.has_lora {
+            facets.push((Facet::Lora, "with a LoRA".to_string()));
+        }
+        if self.has_weights {
+            facets.push((Facet::Weights, "with weights".to_string()));
+        }
+        if self.in_cluster {
+            facets.push((Facet::Cluster, "in a duplicate cluster".to_string()));
+        }
+        facets
+    }
+
+    pub fn clear_facet(&mut self, facet: Facet) {
+        match facet {
+            Facet::SourceFile => self.source_file = None,
+            Facet::ImportDate => {
+                self.imported_from = None;
+                self.imported_to = None;
+            }
+            Facet::Length => {
+                self.min_length = None;
+                self.max_length = None;
+            }
+            Facet::Lora => self.has_lora = false,
+            Facet::Weights => self.has_weights = false,
+            Facet::Cluster => self.in_cluster = false,
+        }
+    }
+
+    /// The filter or its sort depends on the clusters of the last duplicate scan
+    pub fn uses_clusters(&self) -> bool {
+        self.in_cluster || self.sort == PromptSort::ClusterSize
+    }
+
+    /// Phrases describing the filter, e.g. `["tagged portrait or anime", "matching \"cat\""]`
+    pub fn describe(&self) -> Vec<String> {
+        let mut parts = Vec::new();
+        if let Some(name) = &self.collection {
+            parts.push(format!("collection \"{}\"", name));
+        }
+        if let Some(name) = &self.wildcard {
+            parts.push(format!("wildcard __{}__", name));
+        }
+        if !self.tags.is_empty() {
+            let joiner = match self.tag_match {
+                TagMatch::Any => " or ",
+                TagMatch::All => " and ",
+            };
+            parts.push(format!("tagged {}", self.tags.join(joiner)));
+        }
+        if self.favorites_only {
+            parts.push("favorites".to_string());
+        }
+        if let Some(rating) = self.min_rating {
+            parts.push(format!("rated {}+", rating));
+        }
+        if let Some(model) = &self.model {
+            parts.push(format!("for model \"{}\"", model));
+        }
+        parts.extend(self.facets().into_iter().map(|(_, description)| description));
+        if !self.search.is_empty() && self.search_history {
+            parts.push(format!("matching \"{}\" now or before an edit", self.search));
+        } else if !self.search.is_empty() {
+            parts.push(format!("matching \"{}\"", self.search));
+        }
+        parts
+    }
+}
+
+/// The `FROM ... WHERE ...` and `ORDER BY` clauses that select a filter's
+/// prompts, with the values of their placeholders
+struct FilterSql {
+    from_where: String,
+    values: Vec<rusqlite::types::Value>,
+    order_by: String,
+}
+
+impl FilterSql {
+    fn new(filter: &PromptFilter) -> FilterSql {
+        use rusqlite::types::Value;
+
+        let mut from_where = "FROM prompts p".to_string();
+        let mut conditions: Vec<String> = Vec::new();
+        let mut values: Vec<Value> = Vec::new();
+        let mut order = Vec::new();
+
+        if let Some(name) = &filter.collection {
+            from_where.push_str(
+                " JOIN collection_prompts cp ON cp.prompt_id = p.id
+                  JOIN collections c ON c.id = cp.collection_id",
+            );
+            conditions.push("c.name = ?".to_string());
+            values.push(Value::from(name.clone()));
+            order.push("cp.position");
+        }
+        if let Some(name) = &filter.wildcard {
+            from_where.push_str(
+                " JOIN wildcard_entries e ON e.prompt_id = p.id
+                  JOIN wildcards w ON w.id = e.wildcard_id",
+            );
+            conditions.push("w.name = ?".to_string());
+            values.push(Value::from(name.clone()));
+            order.push("e.position");
+        }
+        if filter.uses_clusters() {
+            from_where.push_str(" LEFT JOIN temp.prompt_clusters pc ON pc.prompt_id = p.id");
+        }
+        if filter.in_cluster {
+            conditions.push("pc.prompt_id IS NOT NULL".to_string());
+        }
+        if !filter.search.is_empty() {
+            let columns = ["p.text", "p.negative_prompt", "p.model", "p.notes"];
+            let mut matches: Vec<String> = columns.iter().map(|column| format!("{} LIKE ? ESCAPE '\\'", column)).collect();
+            if filter.search_history {
+                matches.push("p.id IN (SELECT prompt_id FROM prompt_versions WHERE old_text LIKE ? ESCAPE '\\')".to_string());
+            }
+            let pattern = format!("%{}%", escape_like(&filter.search));
+            values.extend(matches.iter().map(|_| Value::from(pattern.clone())));
+            conditions.push(format!("({})", matches.join(" OR ")));
+        }
+        if filter.favorites_only {
+            conditions.push("p.favorite = 1".to_string());
+        }
+        if let Some(rating) = filter.min_rating {
+            conditions.push("p.rating >= ?".to_string());
+            values.push(Value::from(rating as i64));
+        }
+        if let Some(model) = &filter.model {
+            conditions.push("p.model = ? COLLATE NOCASE".to_string());
+            values.push(Value::from(model.clone()));
+        }
+        if let Some(file) = &filter.source_file {
+            conditions.push("p.id IN (SELECT prompt_id FROM prompt_sources WHERE source_file = ?)".to_string());
+            values.push(Value::from(file.clone()));
+        }
+        if let Some(day) = &filter.imported_from {
+            conditions.push("date(p.created_at) >= ?".to_string());
+            values.push(Value::from(day.clone()));
+        }
+        if let Some(day) = &filter.imported_to {
+            conditions.push("date(p.created_at) <= ?".to_string());
+            values.push(Value::from(day.clone()));
+        }
+        if let Some(length) = filter.min_length {
+            conditions.push("LENGTH(p.text) >= ?".to_string());
+            values.push(Value::from(length as i64));
+        }
+        if let Some(length) = filter.max_length {
+            conditions.push("LENGTH(p.text) <= ?".to_string());
+            values.push(Value::from(length as i64));
+        }
+        if filter.has_lora {
+            conditions.push("has_lora(p.text)".to_string());
+        }
+        if filter.has_weights {
+            conditions.push("has_weights(p.text)".to_string());
+        }
+
+        let tags = parse_tags(&filter.tags.join(","));
+        if !tags.is_empty() {
+            let placeholders = vec!["?"; tags.len()].join(", ");
+            let having = match filter.tag_match {
+                TagMatch::Any => String::new(),
+                TagMatch::All => format!(" GROUP BY pt.prompt_id HAVING COUNT(*) = {}", tags.len()),
+            };
+            conditions.push(format!(
+                "p.id IN (SELECT pt.prompt_id FROM prompt_tags pt JOIN tags t ON t.id = pt.tag_id
+                          WHERE t.name IN ({}){})",
+                placeholders, having
+            ));
+            values.extend(tags.into_iter().map(Value::from));
+        }
+
+        if !conditions.is_empty() {
+            from_where.push_str(" WHERE ");
+            from_where.push_str(&conditions.join(" AND "));
+        }
+        if let Some(terms) = filter.sort.order_by() {
+            order.insert(0, terms);
+        }
+        order.push("p.id");
+
+        FilterSql { from_where, values, order_by: order.join(", ") }
+    }
+}
+
+/// `text` with the LIKE wildcards `%` and `_` (and the `\` escaping them)
+/// escaped, for a pattern used with `ESCAPE '\'`
+fn escape_like(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// True for a calendar day written `YYYY-MM-DD`, the form import date filters take
+pub fn is_date(text: &str) -> bool {
+    let parts: Vec<&str> = text.split('-').collect();
+    let [year, month, day] = parts.as_slice() else {
+        return false;
+    };
+    let number = |part: &str, len: usize| {
+        (part.len() == len && part.bytes().all(|b| b.is_ascii_digit())).then(|| part.parse::<u32>().unwrap_or(0))
+    };
+    let (Some(year), Some(month), Some(day)) = (number(year, 4), number(month, 2), number(day, 2)) else {
+        return false;
+    };
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days = match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if leap => 29,
+        2 => 28,
+        _ => return false,
+    };
+    (1..=days).contains(&day)
+}
+
+/// Split comma-separated tag names, trimming them and dropping empty and
+/// repeated (ignoring case) names
+pub fn parse_tags(text: &str) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+    for name in text.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+        if !tags.iter().any(|t| t.eq_ignore_ascii_case(name)) {
+            tags.push(name.to_string());
+        }
+    }
+    tags
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct WildcardImportSummary {
+    pub wildcards: usize,
+    pub entries: usize,
+    pub added: usize,
+    pub templates: usize,
+}
+
+/// Prompts that ended up with the same normalized text.
+/// The oldest row is kept, the rest are removed.
+#[derive(Debug, Clone)]
+pub struct Collision {
+    pub kept: Prompt,
+    pub removed: Vec<Prompt>,
+}
+
+/// Result of rebuilding the normalized dedup keys
+#[derive(Debug, Clone, Default)]
+pub struct NormalizeReport {
+    pub collisions: Vec<Collision>,
+}
+
+impl NormalizeReport {
+    pub fn removed_count(&self) -> usize {
+        self.collisions.iter().map(|c| c.removed.len()).sum()
+    }
+}
+
+/// A prompt whose text would change under Find & Replace
+#[derive(Debug, Clone)]
+pub struct Replacement {
+    pub id: i64,
+    pub original: String,
+    pub replaced: String,
+}
+
+/// Matches `find` literally, ignoring case unless `case_sensitive`
+fn find_pattern(find: &str, case_sensitive: bool) -> Option<Regex> {
+    if find.is_empty() {
+        return None;
+    }
+    RegexBuilder::new(&regex::escape(find)).case_insensitive(!case_sensitive).build().ok()
+}
+
+/// `text` with every match of `pattern` replaced, or `None` when nothing matches
+fn replace_matches(text: &str, pattern: &Regex, replace: &str) -> Option<String> {
+    pattern.is_match(text).then(|| pattern.replace_all(text, NoExpand(replace)).into_owned())
+}
+
+/// `text` with every occurrence of `find` replaced, or `None` when it doesn't occur
+pub fn replace_text(text: &str, find: &str, replace: &str, case_sensitive: bool) -> Option<String> {
+    replace_matches(text, &find_pattern(find, case_sensitive)?, replace)
+}
+
+/// Tables copied by `Database::recover`, parents before children
+const RECOVER_TABLES: [&str; 12] = [
+    "import_batches",
+    "prompts",
+    "settings",
+    "wildcards",
+    "wildcard_entries",
+    "tags",
+    "prompt_tags",
+    "collections",
+    "collection_prompts",
+    "tag_rules",
+    "prompt_sources",
+    "prompt_versions",
+];
+
+/// What `Database::recover` managed to copy out of a damaged file
+#[derive(Debug, Clone, Default)]
+pub struct RecoveryReport {
+    /// Rows copied per table
+    pub copied: Vec<(String, usize)>,
+    /// Tables that could only be read in part, or not at all
+    pub errors: Vec<String>,
 }
 
 pub struct Database {
     conn: Connection,
+    normalize_options: NormalizeOptions,
+    migration_report: Option<NormalizeReport>,
+    upgrade_report: Option<UpgradeReport>,
+    read_only: bool,
 }
 
 impl Database {
-    pub fn open(path: &str) -> Result<Self> {
+    /// Open or create the database at `path`, upgrading an older schema.
+    /// Databases made by a newer version are refused.
+    pub fn open(path: &str) -> crate::Result<Self> {
         let conn = Connection::open(path)?;
-        let db = Database { conn };
+        // Background imports write through their own connection
+        conn.busy_timeout(Duration::from_secs(10))?;
+        prepare_connection(&conn)?;
+        let mut db = Database {
+            conn,
+            normalize_options: NormalizeOptions::default(),
+            migration_report: None,
+            upgrade_report: None,
+            read_only: false,
+        };
         db.init()?;
         Ok(db)
     }
 
-    fn init(&self) -> Result<()> {
-        self.conn.execute(
-            "CREATE TABLE IF NOT EXISTS prompts (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                text TEXT NOT NULL UNIQUE,
-                source_file TEXT,
-                created_at DATETIME DEFAULT CURRENT_TIMESTAMP
-            )",
-            [],
+    /// Open without writing anything, not even schema upgrades.
+    /// Use `needs_upgrade` to check the schema is current before querying.
+    pub fn open_read_only(path: &str) -> crate::Result<Self> {
+        let conn = Connection::open_with_flags(
+            path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_URI | OpenFlags::SQLITE_OPEN_NO_MUTEX,
         )?;
+        conn.busy_timeout(Duration::from_secs(10))?;
+        let version = migrations::schema_version(&conn)?;
+        if version > SCHEMA_VERSION {
+            return Err(Error::NewerSchema { version, supported: SCHEMA_VERSION });
+        }
+        prepare_connection(&conn)?;
+        let mut db = Database {
+            conn,
+            normalize_options: NormalizeOptions::default(),
+            migration_report: None,
+            upgrade_report: None,
+            read_only: true,
+        };
+        if has_table(&db.conn, "settings")? {
+            db.normalize_options = load_normalize_options(&db.conn)?;
+        }
+        Ok(db)
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Schema is older than this version expects, so opening it writable would migrate it
+    pub fn needs_upgrade(&self) -> Result<bool> {
+        Ok(self.schema_version()? < SCHEMA_VERSION)
+    }
+
+    /// Nothing has been created in the file yet, so opening it writable starts a new library
+    pub fn is_blank(&self) -> Result<bool> {
+        migrations::is_blank(&self.conn)
+    }
+
+    /// Version of the schema on disk, see `migrations::SCHEMA_VERSION`
+    pub fn schema_version(&self) -> Result<i64> {
+        migrations::schema_version(&self.conn)
+    }
+
+    /// Problems reported by `PRAGMA integrity_check`, empty when the file is healthy
+    pub fn integrity_check(path: &str) -> Result<Vec<String>> {
+        let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        let mut stmt = conn.prepare("PRAGMA integrity_check")?;
+        let problems = stmt.query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>>>()?;
+        Ok(problems.into_iter().filter(|p| p != "ok").collect())
+    }
+
+    /// Dump every readable row of `source` into a new library at `target`.
+    /// Rows after a damaged page are lost; everything before it is kept.
+    pub fn recover(source: &str, target: &str) -> crate::Result<RecoveryReport> {
+        let mut report = RecoveryReport::default();
+        let mut db = Database::open(target)?;
+        db.conn.execute("ATTACH DATABASE ?1 AS damaged", params![source])?;
+
+        for table in RECOVER_TABLES {
+            match db.copy_damaged_table(table) {
+                Ok((copied, None)) => report.copied.push((table.to_string(), copied)),
+                Ok((copied, Some(e))) => {
+                    report.copied.push((table.to_string(), copied));
+                    report.errors.push(format!("{}: some rows were unreadable: {}", table, e));
+                }
+                Err(e) => report.errors.push(format!("{}: {}", table, e)),
+            }
+        }
+        db.conn.execute("DETACH DATABASE damaged", [])?;
+
+        // Keys and flags may be missing or stale in what was copied
+        db.normalize_options = load_normalize_options(&db.conn)?;
+        db.mark_templates()?;
+        db.rebuild_normalized()?;
+        Ok(report)
+    }
+
+    /// Copy rows of `table` from the attached damaged file, using the columns
+    /// both schemas share. Unreadable stretches are skipped by seeking to ever
+    /// larger rowids past them. Returns how many rows made it and the first
+    /// read error, if any.
+    fn copy_damaged_table(&mut self, table: &str) -> Result<(usize, Option<rusqlite::Error>)> {
+        let target_columns = table_columns(&self.conn, "main", table)?;
+        let columns: Vec<String> = table_columns(&self.conn, "damaged", table)?
+            .into_iter()
+            .filter(|c| target_columns.contains(c))
+            .collect();
+        if columns.is_empty() {
+            return Ok((0, None));
+        }
+        let list = columns.join(", ");
+
+        // Read everything first: a corrupt page aborts whatever transaction
+        // is open, so reads and writes can't share one
+        let mut rows_read = Vec::new();
+        let mut read_error = None;
+        {
+            let mut select = self.conn.prepare(&format!(
+                "SELECT rowid, {} FROM damaged.{} WHERE rowid > ?1 ORDER BY rowid",
+                list, table
+            ))?;
+            let mut last_rowid: i64 = 0;
+            let mut gap: i64 = 0;
+            while let Some(after) = last_rowid.checked_add(gap) {
+                let mut rows = select.query(params![after])?;
+                let mut progressed = false;
+                let finished = loop {
+                    match rows.next() {
+                        Ok(Some(row)) => {
+                            last_rowid = row.get(0)?;
+                            progressed = true;
+                            let values = (1..=columns.len())
+                                .map(|i| row.get::<_, rusqlite::types::Value>(i))
+                                .collect::<Result<Vec<_>>>()?;
+                            rows_read.push(values);
+                        }
+                        Ok(None) => break true,
+                        Err(e) => {
+                            read_error.get_or_insert(e);
+                            break false;
+                        }
+                    }
+                };
+                if finished {
+                    break;
+                }
+                // Seek past the damage, further each time nothing could be read
+                gap = if progressed { 1 } else { gap.saturating_mul(2).max(1) };
+            }
+        }
+
+        let placeholders = vec!["?"; columns.len()].join(", ");
+        let tx = self.conn.transaction()?;
+        let mut copied = 0;
+        {
+            let mut insert = tx.prepare(&format!(
+                "INSERT OR IGNORE INTO main.{} ({}) VALUES ({})",
+                table, list, placeholders
+            ))?;
+            for values in rows_read {
+                // Rows pointing at prompts that didn't survive are dropped
+                match insert.execute(rusqlite::params_from_iter(values)) {
+                    Ok(n) => copied += n,
+                    Err(rusqlite::Error::SqliteFailure(e, _))
+                        if e.code == rusqlite::ErrorCode::ConstraintViolation => {}
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        tx.commit()?;
+        Ok((copied, read_error))
+    }
+
+    /// File the database was opened from
+    pub fn path(&self) -> Option<&str> {
+        self.conn.path()
+    }
+
+    fn init(&mut self) -> crate::Result<()> {
+        self.conn.pragma_update(None, "foreign_keys", true)?;
+        if let Some(mut report) = migrations::upgrade(&mut self.conn)? {
+            self.migration_report = report.merged.take();
+            self.upgrade_report = Some(report);
+        }
+        self.normalize_options = load_normalize_options(&self.conn)?;
         Ok(())
     }
 
-    pub fn insert_prompt(&self, text: &str, source_file: Option<&str>) -> Result<i64> {
-        self.conn.execute(
-            "INSERT OR IGNORE INTO prompts (text, source_file) VALUES (?1, ?2)",
-            params![text, source_file],
-        )?;
-        Ok(self.conn.last_insert_rowid())
+    /// Flag existing prompts that use Dynamic Prompts syntax
+    fn mark_templates(&mut self) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        mark_template_rows(&tx)?;
+        tx.commit()
     }
 
-    pub fn insert_prompts(&self, prompts: &[(String, Option<String>)]) -> Result<usize> {
-        let mut count = 0;
-        for (text, source) in prompts {
-            let rows = self.conn.execute(
-                "INSERT OR IGNORE INTO prompts (text, source_file) VALUES (?1, ?2)",
-                params![text, source.as_deref()],
+    /// Report from the one-time migration that added normalized keys, if it ran on open
+    pub fn take_migration_report(&mut self) -> Option<NormalizeReport> {
+        self.migration_report.take()
+    }
+
+    /// Versions and backup of the schema upgrade that ran on open, if any
+    pub fn take_upgrade_report(&mut self) -> Option<UpgradeReport> {
+        self.upgrade_report.take()
+    }
+
+    pub fn normalize_options(&self) -> NormalizeOptions {
+        self.normalize_options
+    }
+
+    /// Persist new normalization options and rebuild every dedup key with them
+    pub fn set_normalize_options(&mut self, options: NormalizeOptions) -> Result<NormalizeReport> {
+        for (key, value) in options.settings() {
+            self.conn.execute(
+                "INSERT OR REPLACE INTO settings (key, value) VALUES (?1, ?2)",
+                params![key, if value { "1" } else { "0" }],
             )?;
-            count += rows;
         }
-        Ok(count)
+        self.normalize_options = options;
+        self.rebuild_normalized()
+    }
+
+    /// Prompts that would be merged if `options` were applied, without changing anything
+    pub fn preview_normalize(&self, options: &NormalizeOptions) -> Result<NormalizeReport> {
+        Ok(find_collisions(self.get_all()?, options))
+    }
+
+    /// Recompute `normalized_text` for every row, removing rows whose key collides with an older one
+    fn rebuild_normalized(&mut self) -> Result<NormalizeReport> {
+        let tx = self.conn.transaction()?;
+        let report = rebuild_normalized_keys(&tx, &self.normalize_options)?;
+        tx.commit()?;
+        Ok(report)
+    }
+
+    pub fn insert_prompt(&self, text: &str, source_file: Option<&str>) -> Result<i64> {
+        insert_prompt_row(&self.conn, text, source_file, None, &self.normalize_options)?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    /// Start importing prompts read from `source_file`, inside one transaction
+    /// and recorded as one import batch
+    pub fn begin_batch_insert(&mut self, source_file: &str) -> Result<BatchInsert<'_>> {
+        let tx = self.conn.transaction()?;
+        Ok(BatchInsert {
+            batch_id: begin_import_batch(&tx, source_file)?,
+            source_file: source_file.to_string(),
+            records: 0,
+            added: 0,
+            tx,
+            normalize_options: self.normalize_options,
+            tag_ids: Vec::new(),
+            rules: RuleSet::default(),
+            rule_tag_ids: HashMap::new(),
+        })
     }
 
     pub fn delete_prompt(&self, id: i64) -> Result<()> {
@@ -57,42 +1036,1140 @@ impl Database {
         Ok(())
     }
 
-    pub fn update_prompt(&self, id: i64, new_text: &str) -> Result<bool> {
-        let rows = self.conn.execute(
-            "UPDATE prompts SET text = ?1 WHERE id = ?2",
-            params![new_text, id],
+    /// Delete prompt `id` as a duplicate of prompt `kept`, which takes over its
+    /// sources and records its text as merged, see `merge_records`
+    pub fn delete_duplicate(&mut self, id: i64, kept: i64) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        merge_records(&tx, id, kept)?;
+        tx.execute("DELETE FROM prompts WHERE id = ?1", params![id])?;
+        tx.commit()
+    }
+
+    /// Fold prompt `from` into prompt `into`: `into` gains its tags, collections
+    /// and sources, records its text as merged, and keeps its own metadata where
+    /// both have a value.
+    /// `from` is then deleted.
+    pub fn merge_prompt(&mut self, from: i64, into: i64) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "INSERT OR IGNORE INTO prompt_tags (prompt_id, tag_id) SELECT ?2, tag_id FROM prompt_tags WHERE prompt_id = ?1",
+            params![from, into],
+        )?;
+        tx.execute(
+            "INSERT OR IGNORE INTO collection_prompts (collection_id, prompt_id, position)
+             SELECT collection_id, ?2, position FROM collection_prompts WHERE prompt_id = ?1",
+            params![from, into],
+        )?;
+        tx.execute(
+            "UPDATE prompts SET
+                title = COALESCE(title, (SELECT title FROM prompts WHERE id = ?1)),
+                negative_prompt = COALESCE(negative_prompt, (SELECT negative_prompt FROM prompts WHERE id = ?1)),
+                model = COALESCE(model, (SELECT model FROM prompts WHERE id = ?1)),
+                sampler = COALESCE(sampler, (SELECT sampler FROM prompts WHERE id = ?1)),
+                steps = COALESCE(steps, (SELECT steps FROM prompts WHERE id = ?1)),
+                cfg_scale = COALESCE(cfg_scale, (SELECT cfg_scale FROM prompts WHERE id = ?1)),
+                seed = COALESCE(seed, (SELECT seed FROM prompts WHERE id = ?1)),
+                rating = NULLIF(MAX(COALESCE(rating, 0), COALESCE((SELECT rating FROM prompts WHERE id = ?1), 0)), 0),
+                favorite = favorite OR (SELECT favorite FROM prompts WHERE id = ?1),
+                notes = COALESCE(notes, (SELECT notes FROM prompts WHERE id = ?1)),
+                edited_at = CURRENT_TIMESTAMP
+             WHERE id = ?2",
+            params![from, into],
         )?;
-        Ok(rows > 0)
+        merge_records(&tx, from, into)?;
+        tx.execute("DELETE FROM prompts WHERE id = ?1", params![from])?;
+        tx.commit()
     }
 
-    pub fn get_all(&self) -> Result<Vec<Prompt>> {
-        let mut stmt = self.conn.prepare("SELECT id, text, source_file FROM prompts ORDER BY id")?;
+    /// Every file and line the prompt was imported from, oldest first
+    pub fn prompt_sources(&self, id: i64) -> Result<Vec<PromptSource>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT source_file, line, batch_id, imported_at FROM prompt_sources
+             WHERE prompt_id = ?1 ORDER BY id",
+        )?;
+        let rows = stmt.query_map(params![id], |row| {
+            Ok(PromptSource {
+                source_file: row.get(0)?,
+                line: row.get(1)?,
+                batch_id: row.get(2)?,
+                imported_at: row.get::<_, Option<String>>(3)?.unwrap_or_default(),
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// Every recorded import, newest first
+    pub fn import_batches(&self) -> Result<Vec<ImportBatch>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT b.id, b.source_file, b.file_hash, b.imported_at, b.records, b.added,
+                COUNT(p.id), COUNT(p.edited_at), b.undone_at,
+                COALESCE(SUM(p.edited_at IS NULL AND EXISTS (
+                    SELECT 1 FROM prompt_sources s JOIN import_batches o ON o.id = s.batch_id
+                    WHERE s.prompt_id = p.id AND o.id <> b.id AND o.undone_at IS NULL
+                )), 0)
+             FROM import_batches b
+             LEFT JOIN prompts p ON p.batch_id = b.id
+             GROUP BY b.id ORDER BY b.id DESC",
+        )?;
         let rows = stmt.query_map([], |row| {
-            Ok(Prompt {
+            Ok(ImportBatch {
                 id: row.get(0)?,
-                text: row.get(1)?,
-                source_file: row.get(2)?,
+                source_file: row.get(1)?,
+                file_hash: row.get(2)?,
+                imported_at: row.get::<_, Option<String>>(3)?.unwrap_or_default(),
+                records: row.get(4)?,
+                added: row.get(5)?,
+                remaining: row.get(6)?,
+                edited: row.get(7)?,
+                seen_later: row.get(9)?,
+                undone_at: row.get(8)?,
             })
         })?;
         rows.collect()
     }
 
+    /// Delete the prompts an import added and the sources it recorded. Prompts
+    /// edited since are kept with their sources; prompts another import found
+    /// too are kept and handed to that import. Returns `None` when there is no
+    /// such batch or it was already undone.
+    pub fn undo_import(&mut self, batch_id: i64) -> Result<Option<UndoImportSummary>> {
+        let tx = self.conn.transaction()?;
+        let undone: Option<Option<String>> = tx.query_row(
+            "SELECT undone_at FROM import_batches WHERE id = ?1",
+            params![batch_id],
+            |row| row.get(0),
+        ).optional()?;
+        if !matches!(undone, Some(None)) {
+            return Ok(None);
+        }
+
+        let kept_edited: i64 = tx.query_row(
+            "SELECT COUNT(*) FROM prompts WHERE batch_id = ?1 AND edited_at IS NOT NULL",
+            params![batch_id],
+            |row| row.get(0),
+        )?;
+        // Deleting these would take the other import's sources with them
+        let kept_seen_later = tx.execute(
+            "UPDATE prompts SET (batch_id, source_file) = (
+                SELECT s.batch_id, s.source_file FROM prompt_sources s JOIN import_batches o ON o.id = s.batch_id
+                WHERE s.prompt_id = prompts.id AND o.id <> ?1 AND o.undone_at IS NULL
+                ORDER BY s.id LIMIT 1
+             )
+             WHERE batch_id = ?1 AND edited_at IS NULL AND EXISTS (
+                SELECT 1 FROM prompt_sources s JOIN import_batches o ON o.id = s.batch_id
+                WHERE s.prompt_id = prompts.id AND o.id <> ?1 AND o.undone_at IS NULL
+             )",
+            params![batch_id],
+        )?;
+        let removed = tx.execute(
+            "DELETE FROM prompts WHERE batch_id = ?1 AND edited_at IS NULL",
+            params![batch_id],
+        )?;
+        tx.execute(
+            "DELETE FROM prompt_sources WHERE batch_id = ?1
+             AND prompt_id NOT IN (SELECT id FROM prompts WHERE batch_id = ?1)",
+            params![batch_id],
+        )?;
+        tx.execute(
+            "UPDATE import_batches SET undone_at = CURRENT_TIMESTAMP WHERE id = ?1",
+            params![batch_id],
+        )?;
+        tx.commit()?;
+        Ok(Some(UndoImportSummary { removed, kept_edited: kept_edited as usize, kept_seen_later }))
+    }
+
+    /// Change a prompt's text, keeping the old text as a version. Fails with a
+    /// constraint violation when another prompt already has the same dedup key.
+    pub fn update_prompt(&mut self, id: i64, new_text: &str, reason: &EditReason) -> Result<bool> {
+        let tx = self.conn.transaction()?;
+        let Some(old_text) = tx.query_row("SELECT text FROM prompts WHERE id = ?1", params![id], |row| row.get::<_, String>(0))
+            .optional()?
+        else {
+            return Ok(false);
+        };
+        // Saving the same text again is not an edit, so it doesn't keep the
+        // prompt when its import is undone
+        if old_text == new_text {
+            return Ok(true);
+        }
+        let key = normalize::normalize(new_text, &self.normalize_options);
+        tx.execute(
+            "UPDATE prompts SET text = ?1, normalized_text = ?2, is_template = ?3, edited_at = CURRENT_TIMESTAMP
+             WHERE id = ?4",
+            params![new_text, key, wildcards::is_template(new_text), id],
+        )?;
+        add_version(&tx, id, &old_text, new_text, reason)?;
+        tx.commit()?;
+        Ok(true)
+    }
+
+    /// Every change to a prompt's text, newest first
+    pub fn prompt_versions(&self, id: i64) -> Result<Vec<PromptVersion>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, prompt_id, old_text, new_text, reason, detail, created_at FROM prompt_versions
+             WHERE prompt_id = ?1 ORDER BY id DESC",
+        )?;
+        let rows = stmt.query_map(params![id], |row| {
+            Ok(PromptVersion {
+                id: row.get(0)?,
+                prompt_id: row.get(1)?,
+                old_text: row.get(2)?,
+                new_text: row.get(3)?,
+                reason: EditReason::from_parts(&row.get::<_, String>(4)?, row.get(5)?),
+                created_at: row.get::<_, Option<String>>(6)?.unwrap_or_default(),
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// Prompt id and text before the change of version `version_id`
+    pub fn version_text(&self, version_id: i64) -> Result<Option<(i64, String)>> {
+        self.conn.query_row(
+            "SELECT prompt_id, old_text FROM prompt_versions WHERE id = ?1",
+            params![version_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        ).optional()
+    }
+
+    /// The prompt other than `except` that `text` would be a duplicate of, if any
+    pub fn find_duplicate(&self, text: &str, except: i64) -> Result<Option<Prompt>> {
+        let key = normalize::normalize(text, &self.normalize_options);
+        self.conn.query_row(
+            &format!("SELECT {} FROM prompts WHERE normalized_text = ?1 AND id != ?2", PROMPT_COLUMNS),
+            params![key, except],
+            prompt_from_row,
+        ).optional()
+    }
+
+    /// Set or clear (with an empty string) a prompt's title. Like the other
+    /// metadata this doesn't count as an edit of the prompt (see `update_prompt`).
+    pub fn set_title(&self, id: i64, title: &str) -> Result<()> {
+        let title = title.trim();
+        self.conn.execute(
+            "UPDATE prompts SET title = ?1 WHERE id = ?2",
+            params![(!title.is_empty()).then_some(title), id],
+        )?;
+        Ok(())
+    }
+
+    /// Replace every metadata field of a prompt. Empty text fields are stored as missing.
+    /// Only text changes mark a prompt as edited, so starring or rating one doesn't.
+    pub fn set_metadata(&self, id: i64, metadata: &PromptMetadata) -> Result<()> {
+        let text = |value: &Option<String>| value.as_deref().map(str::trim).filter(|v| !v.is_empty()).map(str::to_string);
+        self.conn.execute(
+            "UPDATE prompts SET negative_prompt = ?2, model = ?3, sampler = ?4, steps = ?5, cfg_scale = ?6,
+                seed = ?7, rating = ?8, favorite = ?9, notes = ?10
+             WHERE id = ?1",
+            params![
+                id,
+                text(&metadata.negative_prompt),
+                text(&metadata.model),
+                text(&metadata.sampler),
+                metadata.steps,
+                metadata.cfg_scale,
+                metadata.seed,
+                metadata.rating,
+                metadata.favorite,
+                text(&metadata.notes),
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Remember which duplicate cluster each prompt is in, as numbered by
+    /// `similarity::clusters_from_pairs`, for `PromptFilter::in_cluster` and
+    /// `PromptSort::ClusterSize`. Kept for this connection only.
+    pub fn set_clusters(&mut self, clusters: &HashMap<i64, usize>) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        tx.execute("DELETE FROM temp.prompt_clusters", [])?;
+        {
+            let mut sizes: HashMap<usize, usize> = HashMap::new();
+            for cluster in clusters.values() {
+                *sizes.entry(*cluster).or_default() += 1;
+            }
+            let mut insert = tx.prepare("INSERT INTO temp.prompt_clusters (prompt_id, cluster, size) VALUES (?1, ?2, ?3)")?;
+            for (id, cluster) in clusters {
+                insert.execute(params![id, *cluster as i64, sizes[cluster] as i64])?;
+            }
+        }
+        tx.commit()
+    }
+
+    /// Every file prompts were imported from, with how many prompts each
+    pub fn source_files(&self) -> Result<Vec<(String, i64)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT source_file, COUNT(DISTINCT prompt_id) FROM prompt_sources
+             GROUP BY source_file ORDER BY source_file COLLATE NOCASE",
+        )?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect()
+    }
+
+    /// Models prompts are written for, with how many prompts each
+    pub fn models(&self) -> Result<Vec<(String, i64)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT model, COUNT(*) FROM prompts WHERE model IS NOT NULL
+             GROUP BY model COLLATE NOCASE ORDER BY model COLLATE NOCASE",
+        )?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect()
+    }
+
+    pub fn get_all(&self) -> Result<Vec<Prompt>> {
+        let mut stmt = self.conn.prepare(&format!("SELECT {} FROM prompts ORDER BY id", PROMPT_COLUMNS))?;
+        let rows = stmt.query_map([], prompt_from_row)?;
+        rows.collect()
+    }
+
+    pub fn get_prompt(&self, id: i64) -> Result<Option<Prompt>> {
+        self.conn.query_row(
+            &format!("SELECT {} FROM prompts WHERE id = ?1", PROMPT_COLUMNS),
+            params![id],
+            prompt_from_row,
+        ).optional()
+    }
+
+    /// Every prompt Find & Replace would change, without changing anything
+    pub fn preview_replace(&self, find: &str, replace: &str, case_sensitive: bool) -> Result<Vec<Replacement>> {
+        let Some(pattern) = find_pattern(find, case_sensitive) else {
+            return Ok(Vec::new());
+        };
+        Ok(self.get_all()?
+            .into_iter()
+            .filter_map(|prompt| {
+                replace_matches(&prompt.text, &pattern, replace).map(|replaced| Replacement {
+                    id: prompt.id,
+                    original: prompt.text,
+                    replaced,
+                })
+            })
+            .collect())
+    }
+
     pub fn search(&self, query: &str) -> Result<Vec<Prompt>> {
         let pattern = format!("%{}%", query);
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM prompts WHERE text LIKE ?1 ORDER BY id",
+            PROMPT_COLUMNS
+        ))?;
+        let rows = stmt.query_map(params![pattern], prompt_from_row)?;
+        rows.collect()
+    }
+
+    /// Store wildcard collections, replacing the entries of any wildcard with the same name.
+    /// Entries are stored as prompts so they can be searched and deduplicated like any other.
+    /// The import is recorded as one batch named after `folder`.
+    pub fn import_wildcards(&mut self, sets: &[WildcardSet], folder: &str) -> Result<WildcardImportSummary> {
+        let mut summary = WildcardImportSummary::default();
+        let tx = self.conn.transaction()?;
+        let batch_id = begin_import_batch(&tx, folder)?;
+
+        for set in sets {
+            tx.execute(
+                "INSERT INTO wildcards (name, source_file) VALUES (?1, ?2)
+                 ON CONFLICT(name) DO UPDATE SET source_file = excluded.source_file",
+                params![set.name, set.source_file],
+            )?;
+            let wildcard_id: i64 = tx.query_row(
+                "SELECT id FROM wildcards WHERE name = ?1",
+                params![set.name],
+                |row| row.get(0),
+            )?;
+            tx.execute("DELETE FROM wildcard_entries WHERE wildcard_id = ?1", params![wildcard_id])?;
+
+            for (position, entry) in set.entries.iter().enumerate() {
+                if insert_prompt_row(&tx, entry, Some(&set.source_file), Some(batch_id), &self.normalize_options)? {
+                    summary.added += 1;
+                }
+                if wildcards::is_template(entry) {
+                    summary.templates += 1;
+                }
+
+                // The entry may already exist as a prompt from another file
+                let key = normalize::normalize(entry, &self.normalize_options);
+                add_source(&tx, &key, &set.source_file, None, batch_id)?;
+                tx.execute(
+                    "INSERT OR IGNORE INTO wildcard_entries (wildcard_id, prompt_id, position)
+                     SELECT ?1, id, ?2 FROM prompts WHERE normalized_text = ?3",
+                    params![wildcard_id, position as i64, key],
+                )?;
+                summary.entries += 1;
+            }
+            summary.wildcards += 1;
+        }
+
+        tx.execute(
+            "UPDATE import_batches SET records = ?2, added = ?3 WHERE id = ?1",
+            params![batch_id, summary.entries as i64, summary.added as i64],
+        )?;
+        tx.commit()?;
+        Ok(summary)
+    }
+
+    pub fn wildcards(&self) -> Result<Vec<WildcardInfo>> {
         let mut stmt = self.conn.prepare(
-            "SELECT id, text, source_file FROM prompts WHERE text LIKE ?1 ORDER BY id"
+            "SELECT w.name, COUNT(e.prompt_id) FROM wildcards w
+             LEFT JOIN wildcard_entries e ON e.wildcard_id = w.id
+             GROUP BY w.id ORDER BY w.name",
         )?;
-        let rows = stmt.query_map(params![pattern], |row| {
-            Ok(Prompt {
+        let rows = stmt.query_map([], |row| {
+            Ok(WildcardInfo {
+                name: row.get(0)?,
+                entry_count: row.get(1)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// Names of the wildcards each prompt belongs to, keyed by prompt id
+    pub fn wildcard_memberships(&self) -> Result<HashMap<i64, Vec<String>>> {
+        self.memberships(
+            "SELECT e.prompt_id, w.name FROM wildcard_entries e
+             JOIN wildcards w ON w.id = e.wildcard_id
+             ORDER BY w.name",
+            [],
+        )
+    }
+
+    /// Tags of each tagged prompt, keyed by prompt id
+    pub fn tag_memberships(&self) -> Result<HashMap<i64, Vec<String>>> {
+        self.memberships(
+            "SELECT pt.prompt_id, t.name FROM prompt_tags pt
+             JOIN tags t ON t.id = pt.tag_id
+             ORDER BY t.name",
+            [],
+        )
+    }
+
+    /// `tag_memberships` of just these prompts
+    pub fn tag_memberships_of(&self, ids: &[i64]) -> Result<HashMap<i64, Vec<String>>> {
+        if ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+        self.memberships(
+            &format!(
+                "SELECT pt.prompt_id, t.name FROM prompt_tags pt
+                 JOIN tags t ON t.id = pt.tag_id
+                 WHERE pt.prompt_id IN ({})
+                 ORDER BY t.name",
+                vec!["?"; ids.len()].join(", ")
+            ),
+            rusqlite::params_from_iter(ids),
+        )
+    }
+
+    /// Names of the collections each prompt is in, keyed by prompt id
+    pub fn collection_memberships(&self) -> Result<HashMap<i64, Vec<String>>> {
+        self.memberships(
+            "SELECT cp.prompt_id, c.name FROM collection_prompts cp
+             JOIN collections c ON c.id = cp.collection_id
+             ORDER BY c.name",
+            [],
+        )
+    }
+
+    /// Group `(prompt_id, name)` rows by prompt
+    fn memberships(&self, sql: &str, params: impl rusqlite::Params) -> Result<HashMap<i64, Vec<String>>> {
+        let mut stmt = self.conn.prepare(sql)?;
+        let rows = stmt.query_map(params, |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))?;
+        let mut memberships: HashMap<i64, Vec<String>> = HashMap::new();
+        for row in rows {
+            let (id, name) = row?;
+            memberships.entry(id).or_default().push(name);
+        }
+        Ok(memberships)
+    }
+
+    /// Every tag with its prompt count, by name
+    pub fn tags(&self) -> Result<Vec<TagInfo>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT t.name, COUNT(pt.prompt_id) FROM tags t
+             LEFT JOIN prompt_tags pt ON pt.tag_id = t.id
+             GROUP BY t.id ORDER BY t.name",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(TagInfo {
+                name: row.get(0)?,
+                prompt_count: row.get(1)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// Tag the prompts in `ids`, creating the tag if needed.
+    /// Returns how many weren't tagged with it before.
+    pub fn add_tag(&mut self, ids: &[i64], name: &str) -> Result<usize> {
+        let tx = self.conn.transaction()?;
+        let tag_id = ensure_tag(&tx, name)?;
+        let mut added = 0;
+        {
+            let mut insert = tx.prepare("INSERT OR IGNORE INTO prompt_tags (prompt_id, tag_id) VALUES (?1, ?2)")?;
+            for id in ids {
+                added += insert.execute(params![id, tag_id])?;
+            }
+        }
+        tx.commit()?;
+        Ok(added)
+    }
+
+    /// Take the tag off the prompts in `ids`. The tag itself is kept.
+    /// Returns how many had it.
+    pub fn remove_tag(&mut self, ids: &[i64], name: &str) -> Result<usize> {
+        let tx = self.conn.transaction()?;
+        let mut removed = 0;
+        {
+            let mut delete = tx.prepare(
+                "DELETE FROM prompt_tags WHERE prompt_id = ?1
+                 AND tag_id = (SELECT id FROM tags WHERE name = ?2)",
+            )?;
+            for id in ids {
+                removed += delete.execute(params![id, name])?;
+            }
+        }
+        tx.commit()?;
+        Ok(removed)
+    }
+
+    /// Delete a tag and take it off every prompt
+    pub fn delete_tag(&self, name: &str) -> Result<()> {
+        self.conn.execute("DELETE FROM tags WHERE name = ?1", params![name])?;
+        Ok(())
+    }
+
+    /// Every collection with its prompt count, by name
+    pub fn collections(&self) -> Result<Vec<CollectionInfo>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT c.name, COUNT(cp.prompt_id) FROM collections c
+             LEFT JOIN collection_prompts cp ON cp.collection_id = c.id
+             GROUP BY c.id ORDER BY c.name",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(CollectionInfo {
+                name: row.get(0)?,
+                prompt_count: row.get(1)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// Append the prompts in `ids` to a collection, creating it if needed.
+    /// Prompts already in it keep their place. Returns how many were added.
+    pub fn add_to_collection(&mut self, ids: &[i64], name: &str) -> Result<usize> {
+        let tx = self.conn.transaction()?;
+        tx.execute("INSERT OR IGNORE INTO collections (name) VALUES (?1)", params![name])?;
+        let collection_id: i64 = tx.query_row(
+            "SELECT id FROM collections WHERE name = ?1",
+            params![name],
+            |row| row.get(0),
+        )?;
+        let mut added = 0;
+        {
+            let mut insert = tx.prepare(
+                "INSERT OR IGNORE INTO collection_prompts (collection_id, prompt_id, position)
+                 SELECT ?1, ?2, COALESCE(MAX(position), -1) + 1 FROM collection_prompts WHERE collection_id = ?1",
+            )?;
+            for id in ids {
+                added += insert.execute(params![collection_id, id])?;
+            }
+        }
+        tx.commit()?;
+        Ok(added)
+    }
+
+    /// Returns how many of `ids` were in the collection
+    pub fn remove_from_collection(&mut self, ids: &[i64], name: &str) -> Result<usize> {
+        let tx = self.conn.transaction()?;
+        let mut removed = 0;
+        {
+            let mut delete = tx.prepare(
+                "DELETE FROM collection_prompts WHERE prompt_id = ?1
+                 AND collection_id = (SELECT id FROM collections WHERE name = ?2)",
+            )?;
+            for id in ids {
+                removed += delete.execute(params![id, name])?;
+            }
+        }
+        tx.commit()?;
+        Ok(removed)
+    }
+
+    /// Delete a collection. Its prompts stay in the library.
+    pub fn delete_collection(&self, name: &str) -> Result<()> {
+        self.conn.execute("DELETE FROM collections WHERE name = ?1", params![name])?;
+        Ok(())
+    }
+
+    /// Every tag rule, oldest first
+    pub fn tag_rules(&self) -> Result<Vec<TagRule>> {
+        let mut stmt = self.conn.prepare("SELECT id, kind, pattern, tag, enabled FROM tag_rules ORDER BY id")?;
+        let rows = stmt.query_map([], |row| {
+            let kind: String = row.get(1)?;
+            Ok(TagRule {
                 id: row.get(0)?,
-                text: row.get(1)?,
-                source_file: row.get(2)?,
+                kind: RuleKind::from_key(&kind).unwrap_or(RuleKind::Keywords),
+                pattern: row.get(2)?,
+                tag: row.get(3)?,
+                enabled: row.get(4)?,
             })
         })?;
         rows.collect()
     }
 
+    /// Store a rule without checking its pattern, see `Library::add_tag_rule`
+    pub fn add_tag_rule(&self, kind: RuleKind, pattern: &str, tag: &str) -> Result<i64> {
+        self.conn.execute(
+            "INSERT INTO tag_rules (kind, pattern, tag) VALUES (?1, ?2, ?3)",
+            params![kind.key(), pattern, tag],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    /// Returns false when there is no rule with that id
+    pub fn delete_tag_rule(&self, id: i64) -> Result<bool> {
+        Ok(self.conn.execute("DELETE FROM tag_rules WHERE id = ?1", params![id])? > 0)
+    }
+
+    /// Returns false when there is no rule with that id
+    pub fn set_tag_rule_enabled(&self, id: i64, enabled: bool) -> Result<bool> {
+        Ok(self.conn.execute("UPDATE tag_rules SET enabled = ?2 WHERE id = ?1", params![id, enabled])? > 0)
+    }
+
+    /// Add `(prompt id, tag)` pairs in one transaction, creating tags as needed.
+    /// Returns how many were new.
+    pub fn add_tags(&mut self, assignments: &[(i64, String)]) -> Result<usize> {
+        let tx = self.conn.transaction()?;
+        let mut tag_ids: HashMap<String, i64> = HashMap::new();
+        let mut added = 0;
+        {
+            let mut insert = tx.prepare("INSERT OR IGNORE INTO prompt_tags (prompt_id, tag_id) VALUES (?1, ?2)")?;
+            for (id, name) in assignments {
+                let tag_id = match tag_ids.get(name) {
+                    Some(tag_id) => *tag_id,
+                    None => {
+                        let tag_id = ensure_tag(&tx, name)?;
+                        tag_ids.insert(name.clone(), tag_id);
+                        tag_id
+                    }
+                };
+                added += insert.execute(params![id, tag_id])?;
+            }
+        }
+        tx.commit()?;
+        Ok(added)
+    }
+
+    /// Prompts matching `filter`. Collections and wildcards list in their own
+    /// order, everything else by id.
+    pub fn query(&self, filter: &PromptFilter) -> Result<Vec<Prompt>> {
+        let sql = FilterSql::new(filter);
+        let mut stmt = self.conn.prepare(&format!("SELECT {} {} ORDER BY {}", PROMPT_COLUMNS_P, sql.from_where, sql.order_by))?;
+        let rows = stmt.query_map(rusqlite::params_from_iter(sql.values), prompt_from_row)?;
+        rows.collect()
+    }
+
+    /// `limit` prompts of `query` starting at row `offset`, for lists that load
+    /// as they scroll
+    pub fn query_page(&self, filter: &PromptFilter, offset: usize, limit: usize) -> Result<Vec<Prompt>> {
+        let mut sql = FilterSql::new(filter);
+        sql.values.push((limit as i64).into());
+        sql.values.push((offset as i64).into());
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} {} ORDER BY {} LIMIT ? OFFSET ?",
+            PROMPT_COLUMNS_P, sql.from_where, sql.order_by
+        ))?;
+        let rows = stmt.query_map(rusqlite::params_from_iter(sql.values), prompt_from_row)?;
+        rows.collect()
+    }
+
+    /// How many prompts `query` would return
+    pub fn count_matching(&self, filter: &PromptFilter) -> Result<usize> {
+        let sql = FilterSql::new(filter);
+        self.conn.query_row(
+            &format!("SELECT COUNT(*) {}", sql.from_where),
+            rusqlite::params_from_iter(sql.values),
+            |row| row.get::<_, i64>(0),
+        ).map(|count| count as usize)
+    }
+
+    /// Ids of the prompts `query` would return, in the same order
+    pub fn query_ids(&self, filter: &PromptFilter) -> Result<Vec<i64>> {
+        let sql = FilterSql::new(filter);
+        let mut stmt = self.conn.prepare(&format!("SELECT p.id {} ORDER BY {}", sql.from_where, sql.order_by))?;
+        let rows = stmt.query_map(rusqlite::params_from_iter(sql.values), |row| row.get(0))?;
+        rows.collect()
+    }
+
+    /// Entries of one wildcard in file order, optionally filtered by a search string
+    pub fn get_wildcard_prompts(&self, name: &str, query: &str) -> Result<Vec<Prompt>> {
+        let pattern = format!("%{}%", query);
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM prompts p
+             JOIN wildcard_entries e ON e.prompt_id = p.id
+             JOIN wildcards w ON w.id = e.wildcard_id
+             WHERE w.name = ?1 AND p.text LIKE ?2
+             ORDER BY e.position",
+            PROMPT_COLUMNS_P
+        ))?;
+        let rows = stmt.query_map(params![name, pattern], prompt_from_row)?;
+        rows.collect()
+    }
+
     pub fn count(&self) -> Result<i64> {
         self.conn.query_row("SELECT COUNT(*) FROM prompts", [], |row| row.get(0))
     }
 }
+
+/// Inserts prompts in a single transaction.
+/// Nothing is written unless `commit` is called; dropping it rolls back.
+pub struct BatchInsert<'a> {
+    tx: Transaction<'a>,
+    batch_id: i64,
+    source_file: String,
+    records: usize,
+    added: usize,
+    normalize_options: NormalizeOptions,
+    tag_ids: Vec<i64>,
+    rules: RuleSet,
+    /// Ids of tags the rules have used so far, by name
+    rule_tag_ids: HashMap<String, i64>,
+}
+
+impl BatchInsert<'_> {
+    /// Tag every prompt inserted from now on, including duplicates of existing prompts
+    pub fn set_tags(&mut self, names: &[String]) -> Result<()> {
+        self.tag_ids = names.iter()
+            .map(|name| ensure_tag(&self.tx, name))
+            .collect::<Result<_>>()?;
+        Ok(())
+    }
+
+    /// Tag every prompt inserted from now on with the tags `rules` give its text
+    pub fn set_rules(&mut self, rules: RuleSet) {
+        self.rules = rules;
+    }
+
+    /// Returns false when the prompt was skipped as a duplicate. Either way
+    /// `line` of the batch's file is recorded as one of the prompt's sources.
+    /// The negative prompt, model and sampler settings in `metadata` fill in
+    /// whichever of them the stored prompt is missing, so a duplicate can still add them.
+    pub fn insert(&mut self, text: &str, line: Option<usize>, metadata: &PromptMetadata) -> Result<bool> {
+        let added = insert_prompt_row(
+            &self.tx,
+            text,
+            Some(&self.source_file),
+            Some(self.batch_id),
+            &self.normalize_options,
+        )?;
+        self.records += 1;
+        if added {
+            self.added += 1;
+        }
+        let key = normalize::normalize(text, &self.normalize_options);
+        add_source(&self.tx, &key, &self.source_file, line, self.batch_id)?;
+        if metadata.has_generation_info() {
+            self.tx.prepare_cached(
+                "UPDATE prompts SET negative_prompt = COALESCE(negative_prompt, ?2), model = COALESCE(model, ?3),
+                    sampler = COALESCE(sampler, ?4), steps = COALESCE(steps, ?5),
+                    cfg_scale = COALESCE(cfg_scale, ?6), seed = COALESCE(seed, ?7)
+                 WHERE normalized_text = ?1",
+            )?.execute(params![
+                key,
+                metadata.negative_prompt,
+                metadata.model,
+                metadata.sampler,
+                metadata.steps,
+                metadata.cfg_scale,
+                metadata.seed,
+            ])?;
+        }
+
+        let mut tag_ids = self.tag_ids.clone();
+        for name in self.rules.tags_for(text) {
+            let tag_id = match self.rule_tag_ids.get(name) {
+                Some(tag_id) => *tag_id,
+                None => {
+                    let tag_id = ensure_tag(&self.tx, name)?;
+                    self.rule_tag_ids.insert(name.to_string(), tag_id);
+                    tag_id
+                }
+            };
+            tag_ids.push(tag_id);
+        }
+        if !tag_ids.is_empty() {
+            let mut tag = self.tx.prepare_cached(
+                "INSERT OR IGNORE INTO prompt_tags (prompt_id, tag_id)
+                 SELECT id, ?2 FROM prompts WHERE normalized_text = ?1",
+            )?;
+            for tag_id in tag_ids {
+                tag.execute(params![key, tag_id])?;
+            }
+        }
+        Ok(added)
+    }
+
+    /// Id of the import batch the prompts are recorded under
+    pub fn batch_id(&self) -> i64 {
+        self.batch_id
+    }
+
+    /// Store the counts and `file_hash` with the batch and commit. Returns when
+    /// a file with the same hash was last imported, if one was.
+    pub fn commit(self, file_hash: Option<&str>) -> Result<Option<String>> {
+        self.tx.execute(
+            "UPDATE import_batches SET file_hash = ?2, records = ?3, added = ?4 WHERE id = ?1",
+            params![self.batch_id, file_hash, self.records as i64, self.added as i64],
+        )?;
+        let previous = match file_hash {
+            Some(hash) => self.tx.query_row(
+                "SELECT imported_at FROM import_batches
+                 WHERE file_hash = ?1 AND id != ?2 AND undone_at IS NULL
+                 ORDER BY id DESC LIMIT 1",
+                params![hash, self.batch_id],
+                |row| row.get(0),
+            ).optional()?,
+            None => None,
+        };
+        self.tx.commit()?;
+        Ok(previous)
+    }
+}
+
+/// Insert one prompt unless its normalized text already exists.
+/// Returns false when it was skipped as a duplicate.
+fn insert_prompt_row(
+    conn: &Connection,
+    text: &str,
+    source_file: Option<&str>,
+    batch_id: Option<i64>,
+    options: &NormalizeOptions,
+) -> Result<bool> {
+    let key = normalize::normalize(text, options);
+    let mut stmt = conn.prepare_cached(
+        "INSERT OR IGNORE INTO prompts (text, normalized_text, source_file, is_template, batch_id)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+    )?;
+    let rows = stmt.execute(params![text, key, source_file, wildcards::is_template(text), batch_id])?;
+    Ok(rows > 0)
+}
+
+/// Record the start of an import from `source_file`, returning the batch id
+fn begin_import_batch(conn: &Connection, source_file: &str) -> Result<i64> {
+    conn.execute("INSERT INTO import_batches (source_file) VALUES (?1)", params![source_file])?;
+    Ok(conn.last_insert_rowid())
+}
+
+/// Record that the prompt with dedup key `key` was read from `source_file`
+fn add_source(conn: &Connection, key: &str, source_file: &str, line: Option<usize>, batch_id: i64) -> Result<()> {
+    conn.prepare_cached(
+        "INSERT INTO prompt_sources (prompt_id, source_file, line, batch_id)
+         SELECT id, ?2, ?3, ?4 FROM prompts WHERE normalized_text = ?1",
+    )?.execute(params![key, source_file, line.map(|line| line as i64), batch_id])?;
+    Ok(())
+}
+
+/// Id of the tag called `name`, created if it doesn't exist yet
+fn ensure_tag(conn: &Connection, name: &str) -> Result<i64> {
+    conn.execute("INSERT OR IGNORE INTO tags (name) VALUES (?1)", params![name])?;
+    conn.query_row("SELECT id FROM tags WHERE name = ?1", params![name], |row| row.get(0))
+}
+
+/// Group prompts (ordered by id) by normalized text, keeping the first of each group
+fn find_collisions(prompts: Vec<Prompt>, options: &NormalizeOptions) -> NormalizeReport {
+    let mut order: Vec<String> = Vec::new();
+    let mut groups: HashMap<String, Vec<Prompt>> = HashMap::new();
+
+    for prompt in prompts {
+        let key = normalize::normalize(&prompt.text, options);
+        let group = groups.entry(key.clone()).or_default();
+        if group.is_empty() {
+            order.push(key);
+        }
+        group.push(prompt);
+    }
+
+    let collisions = order
+        .into_iter()
+        .filter_map(|key| {
+            let mut group = groups.remove(&key)?;
+            if group.len() < 2 {
+                return None;
+            }
+            let kept = group.remove(0);
+            Some(Collision { kept, removed: group })
+        })
+        .collect();
+
+    NormalizeReport { collisions }
+}
+
+/// `PROMPT_COLUMNS`, reading columns a later schema version adds as empty.
+/// Migrations run before those columns exist.
+fn existing_prompt_columns(conn: &Connection) -> Result<String> {
+    let existing = table_columns(conn, "main", "prompts")?;
+    let columns: Vec<&str> = PROMPT_COLUMNS.split(',')
+        .map(str::trim)
+        .map(|column| match column {
+            _ if existing.iter().any(|name| name == column) => column,
+            "favorite" | "is_template" => "0",
+            _ => "NULL",
+        })
+        .collect();
+    Ok(columns.join(", "))
+}
+
+pub(crate) fn has_table(conn: &Connection, table: &str) -> Result<bool> {
+    let count: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?1",
+        params![table],
+        |row| row.get(0),
+    )?;
+    Ok(count > 0)
+}
+
+pub(crate) fn has_column(conn: &Connection, table: &str, column: &str) -> Result<bool> {
+    Ok(table_columns(conn, "main", table)?.iter().any(|name| name == column))
+}
+
+fn table_columns(conn: &Connection, schema: &str, table: &str) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(&format!("PRAGMA {}.table_info({})", schema, table))?;
+    let names = stmt.query_map([], |row| row.get::<_, String>(1))?;
+    names.collect()
+}
+
+pub(crate) fn load_normalize_options(conn: &Connection) -> Result<NormalizeOptions> {
+    let mut options = NormalizeOptions::default();
+    let mut stmt = conn.prepare("SELECT key, value FROM settings WHERE key LIKE 'normalize.%'")?;
+    let rows = stmt.query_map([], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+    })?;
+    for row in rows {
+        let (key, value) = row?;
+        options.apply_setting(&key, value == "1");
+    }
+    Ok(options)
+}
+
+/// Set `is_template` on every prompt that uses Dynamic Prompts syntax
+pub(crate) fn mark_template_rows(conn: &Connection) -> Result<()> {
+    let mut select = conn.prepare("SELECT id, text FROM prompts")?;
+    let mut update = conn.prepare("UPDATE prompts SET is_template = 1 WHERE id = ?1")?;
+    let rows: Vec<(i64, String)> = select
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_>>()?;
+    for (id, text) in rows {
+        if wildcards::is_template(&text) {
+            update.execute(params![id])?;
+        }
+    }
+    Ok(())
+}
+
+/// Register the SQL functions filters use and create the per-connection
+/// table of duplicate clusters, see `Database::set_clusters`
+fn prepare_connection(conn: &Connection) -> Result<()> {
+    let flags = FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC;
+    conn.create_scalar_function("has_lora", 1, flags, |ctx| Ok(syntax::has_lora(&ctx.get::<String>(0)?)))?;
+    conn.create_scalar_function("has_weights", 1, flags, |ctx| Ok(syntax::has_weights(&ctx.get::<String>(0)?)))?;
+    conn.create_scalar_function("word_count", 1, flags, |ctx| Ok(syntax::word_count(&ctx.get::<String>(0)?) as i64))?;
+    conn.execute_batch(
+        "CREATE TEMP TABLE IF NOT EXISTS prompt_clusters (
+            prompt_id INTEGER PRIMARY KEY,
+            cluster INTEGER NOT NULL,
+            size INTEGER NOT NULL
+        )",
+    )
+}
+
+/// Before prompt `from` is deleted as a duplicate of prompt `to`, hand `to`
+/// its sources and keep its text as a merged version. `from`'s own edits go
+/// with it: they were never `to`'s text, so `to` couldn't be restored to them.
+/// While migrating from before those tables existed, `from`'s text and
+/// source file are parked in `pending_merges` for the steps that add them.
+fn merge_records(conn: &Connection, from: i64, to: i64) -> Result<()> {
+    if !has_table(conn, "prompt_versions")? {
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS pending_merges (
+                prompt_id INTEGER NOT NULL,
+                old_text TEXT NOT NULL,
+                source_file TEXT,
+                created_at DATETIME
+            )",
+            [],
+        )?;
+        conn.execute(
+            "INSERT INTO pending_merges (prompt_id, old_text, source_file, created_at)
+             SELECT ?2, text, source_file, created_at FROM prompts WHERE id = ?1",
+            params![from, to],
+        )?;
+    }
+    if has_table(conn, "prompt_sources")? {
+        conn.execute("UPDATE prompt_sources SET prompt_id = ?2 WHERE prompt_id = ?1", params![from, to])?;
+    }
+    if has_table(conn, "prompt_versions")? {
+        conn.execute(
+            "INSERT INTO prompt_versions (prompt_id, old_text, new_text, reason)
+             SELECT t.id, f.text, t.text, ?3 FROM prompts f, prompts t WHERE f.id = ?1 AND t.id = ?2",
+            params![from, to, EditReason::Merge.key()],
+        )?;
+    }
+    Ok(())
+}
+
+fn add_version(conn: &Connection, prompt_id: i64, old_text: &str, new_text: &str, reason: &EditReason) -> Result<()> {
+    conn.execute(
+        "INSERT INTO prompt_versions (prompt_id, old_text, new_text, reason, detail) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![prompt_id, old_text, new_text, reason.key(), reason.detail()],
+    )?;
+    Ok(())
+}
+
+/// Recompute every dedup key with `options`, removing prompts whose key
+/// collides with an older one. Run inside a transaction.
+pub(crate) fn rebuild_normalized_keys(conn: &Connection, options: &NormalizeOptions) -> Result<NormalizeReport> {
+    let prompts = {
+        let mut stmt = conn.prepare(&format!("SELECT {} FROM prompts ORDER BY id", existing_prompt_columns(conn)?))?;
+        stmt.query_map([], prompt_from_row)?.collect::<Result<Vec<_>>>()?
+    };
+    let report = find_collisions(prompts, options);
+
+    conn.execute("DROP INDEX IF EXISTS idx_prompts_normalized", [])?;
+    for collision in &report.collisions {
+        for prompt in &collision.removed {
+            merge_records(conn, prompt.id, collision.kept.id)?;
+            conn.execute("DELETE FROM prompts WHERE id = ?1", params![prompt.id])?;
+        }
+    }
+    {
+        let mut select = conn.prepare("SELECT id, text FROM prompts")?;
+        let mut update = conn.prepare("UPDATE prompts SET normalized_text = ?1 WHERE id = ?2")?;
+        let rows: Vec<(i64, String)> = select
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<_>>()?;
+        for (id, text) in rows {
+            update.execute(params![normalize::normalize(&text, options), id])?;
+        }
+    }
+    conn.execute(
+        "CREATE UNIQUE INDEX idx_prompts_normalized ON prompts(normalized_text)",
+        [],
+    )?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn library() -> Database {
+        let mut db = Database::open(":memory:").unwrap();
+        for (text, source) in [
+            ("a red cat", "cats.txt"),
+            ("a very long prompt about a red dragon <lora:dragon:0.8>", "dragons.txt"),
+            ("(blue sky:1.2), clouds", "skies.txt"),
+        ] {
+            let id = db.insert_prompt(text, Some(source)).unwrap();
+            db.conn.execute("INSERT INTO prompt_sources (prompt_id, source_file) VALUES (?1, ?2)", params![id, source]).unwrap();
+        }
+        db.add_tag(&[1, 2], "red").unwrap();
+        db.add_tag(&[2], "fantasy").unwrap();
+        db
+    }
+
+    fn ids(db: &Database, filter: &PromptFilter) -> Vec<i64> {
+        let ids: Vec<i64> = db.query(filter).unwrap().iter().map(|prompt| prompt.id).collect();
+        assert_eq!(db.count_matching(filter).unwrap(), ids.len());
+        ids
+    }
+
+    #[test]
+    fn every_placeholder_gets_a_value() {
+        let filter = PromptFilter {
+            search: "red".to_string(),
+            search_history: true,
+            collection: Some("favorites".to_string()),
+            wildcard: Some("colors".to_string()),
+            tags: vec!["red".to_string(), "fantasy".to_string()],
+            tag_match: TagMatch::All,
+            min_rating: Some(3),
+            model: Some("sdxl".to_string()),
+            source_file: Some("cats.txt".to_string()),
+            imported_from: Some("2020-01-01".to_string()),
+            imported_to: Some("2030-01-01".to_string()),
+            min_length: Some(5),
+            max_length: Some(500),
+            ..Default::default()
+        };
+        let sql = FilterSql::new(&filter);
+        assert_eq!(sql.from_where.matches('?').count(), sql.values.len());
+        assert!(sql.order_by.starts_with("cp.position, e.position"));
+        assert!(sql.order_by.ends_with("p.id"));
+
+        let sql = FilterSql::new(&PromptFilter::default());
+        assert_eq!(sql.from_where, "FROM prompts p");
+        assert!(sql.values.is_empty());
+        assert_eq!(sql.order_by, "p.id");
+    }
+
+    #[test]
+    fn filters_combine() {
+        let db = library();
+        assert_eq!(ids(&db, &PromptFilter::default()), [1, 2, 3]);
+        assert_eq!(ids(&db, &PromptFilter { search: "RED".to_string(), ..Default::default() }), [1, 2]);
+
+        let red = PromptFilter { tags: vec!["red".to_string(), "fantasy".to_string()], ..Default::default() };
+        assert_eq!(ids(&db, &red), [1, 2]);
+        assert_eq!(ids(&db, &PromptFilter { tag_match: TagMatch::All, ..red }), [2]);
+
+        assert_eq!(ids(&db, &PromptFilter { source_file: Some("skies.txt".to_string()), ..Default::default() }), [3]);
+        assert_eq!(ids(&db, &PromptFilter { min_length: Some(10), max_length: Some(30), ..Default::default() }), [3]);
+        assert_eq!(ids(&db, &PromptFilter { has_lora: true, ..Default::default() }), [2]);
+        assert_eq!(ids(&db, &PromptFilter { has_weights: true, ..Default::default() }), [3]);
+        assert_eq!(ids(&db, &PromptFilter { imported_to: Some("2000-01-01".to_string()), ..Default::default() }), [0; 0]);
+        // Callers only pass days that exist
+        assert!(is_date("2024-02-29") && is_date("2000-02-29") && is_date("2026-12-31"));
+        assert!(!is_date("2026-02-31") && !is_date("1900-02-29") && !is_date("2026-04-31") && !is_date("2026-13-01"));
+        assert_eq!(
+            ids(&db, &PromptFilter { search: "red".to_string(), has_lora: true, ..Default::default() }),
+            [2]
+        );
+    }
+
+    #[test]
+    fn sorts_and_pages() {
+        let db = library();
+        let by_length = PromptFilter { sort: PromptSort::Length, ..Default::default() };
+        assert_eq!(ids(&db, &by_length), [2, 3, 1]);
+        let page: Vec<i64> = db.query_page(&by_length, 1, 5).unwrap().iter().map(|prompt| prompt.id).collect();
+        assert_eq!(page, [3, 1]);
+        assert_eq!(ids(&db, &PromptFilter { sort: PromptSort::Alphabetical, ..Default::default() }), [3, 1, 2]);
+    }
+
+    #[test]
+    fn cluster_filter_uses_the_last_scan() {
+        let mut db = library();
+        db.set_clusters(&HashMap::from([(1, 1), (3, 1)])).unwrap();
+        let filter = PromptFilter { in_cluster: true, sort: PromptSort::ClusterSize, ..Default::default() };
+        assert_eq!(ids(&db, &filter), [1, 3]);
+    }
+
+    #[test]
+    fn search_matches_like_wildcards_literally() {
+        let db = library();
+        let id = db.insert_prompt("a 100% red_cat \\ sketch", None).unwrap();
+        let search = |text: &str| PromptFilter { search: text.to_string(), ..Default::default() };
+        // Unescaped, `_` would also match the space in "a red cat"
+        assert_eq!(ids(&db, &search("d_c")), [id]);
+        assert_eq!(ids(&db, &search("%")), [id]);
+        assert_eq!(ids(&db, &search("\\")), [id]);
+        assert_eq!(ids(&db, &search("100%_")), [0; 0]);
+    }
+
+    #[test]
+    fn replaces_ignoring_case_beyond_ascii() {
+        // Lowercasing "İ" adds a byte, which used to shift every later match
+        assert_eq!(replace_text("İzmir GROẞE Straße", "große", "big", false).as_deref(), Some("İzmir big Straße"));
+        assert_eq!(replace_text("Cost: $5 (USD)", "$5 (usd)", "$1", false).as_deref(), Some("Cost: $1"));
+        assert_eq!(replace_text("Red Cat", "red", "blue", true), None);
+        assert_eq!(replace_text("red cat", "", "blue", false), None);
+    }
+}
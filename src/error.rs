@@ -0,0 +1,85 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// Everything that can go wrong in a library operation
+#[derive(Debug)]
+pub enum Error {
+    Database(rusqlite::Error),
+    /// Reading an import or decisions file failed
+    Read(io::Error),
+    /// Writing an export failed
+    Write(io::Error),
+    /// The regex separator of an import is not a valid pattern
+    InvalidPattern(regex::Error),
+    /// A tag name that is empty or contains commas
+    InvalidTag(String),
+    /// A decisions file that couldn't be parsed
+    InvalidDecisions(String),
+    /// Background imports need a database file to open a second connection to
+    NoDatabaseFile,
+    /// The import thread ended without reporting back
+    ImportStopped,
+    /// A read-only library that was made by an older version and needs migrating first
+    NeedsUpgrade,
+    /// Refused to overwrite an existing file
+    AlreadyExists(PathBuf),
+    /// The database was made by a newer version with a schema this one doesn't know
+    NewerSchema { version: i64, supported: i64 },
+    /// Copying the database before a schema upgrade failed, so it wasn't upgraded
+    BackupFailed(rusqlite::Error),
+    /// No import batch with this id, or it was already undone
+    NoSuchImport(i64),
+    /// No prompt version with this id
+    NoSuchVersion(i64),
+    /// An edit would give a prompt the same text as the prompt with this id
+    DuplicateText(i64),
+    /// An edit that would leave a prompt with no text
+    EmptyText,
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Database(e) => write!(f, "Database error: {}", e),
+            Error::Read(e) => write!(f, "Failed to read file: {}", e),
+            Error::Write(e) => write!(f, "Failed to export: {}", e),
+            Error::InvalidPattern(e) => write!(f, "Invalid pattern: {}", e),
+            Error::InvalidTag(name) => write!(f, "Invalid tag name '{}', it can't be empty or contain commas", name),
+            Error::InvalidDecisions(message) => write!(f, "Invalid decisions file: {}", message),
+            Error::NoDatabaseFile => write!(f, "Database has no file to import into"),
+            Error::ImportStopped => write!(f, "Import thread stopped unexpectedly"),
+            Error::AlreadyExists(path) => write!(f, "{} already exists", path.display()),
+            Error::NewerSchema { version, supported } => write!(
+                f,
+                "Library was made by a newer version of Prompt Deduplicator (schema {}, this version supports up to {}); update the app to open it",
+                version, supported
+            ),
+            Error::BackupFailed(e) => write!(f, "Couldn't back up the library before upgrading it: {}", e),
+            Error::NeedsUpgrade => write!(f, "Library was made by an older version and must be opened writable once to upgrade it"),
+            Error::NoSuchImport(id) => write!(f, "Import #{} doesn't exist or was already undone", id),
+            Error::NoSuchVersion(id) => write!(f, "No prompt version with id {}", id),
+            Error::DuplicateText(id) => write!(f, "Prompt #{} already has this text", id),
+            Error::EmptyText => write!(f, "A prompt can't be empty"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Database(e) | Error::BackupFailed(e) => Some(e),
+            Error::Read(e) | Error::Write(e) => Some(e),
+            Error::InvalidPattern(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(e: rusqlite::Error) -> Self {
+        Error::Database(e)
+    }
+}
@@ -1,15 +1,953 @@
-use crate::db::Prompt;
+use crate::db::{Prompt, PromptFilter};
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
-use std::io::{self, Write};
-use std::path::Path;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
 
-pub fn export_to_txt(prompts: &[Prompt], path: &Path) -> io::Result<usize> {
-    let mut file = File::create(path)?;
-    let count = prompts.len();
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Txt,
+    Csv,
+    Json,
+    Jsonl,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 4] = [
+        ExportFormat::Txt,
+        ExportFormat::Csv,
+        ExportFormat::Json,
+        ExportFormat::Jsonl,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ExportFormat::Txt => "Text (.txt)",
+            ExportFormat::Csv => "CSV (.csv)",
+            ExportFormat::Json => "JSON array (.json)",
+            ExportFormat::Jsonl => "JSON Lines (.jsonl)",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Txt => "txt",
+            ExportFormat::Csv => "csv",
+            ExportFormat::Json => "json",
+            ExportFormat::Jsonl => "jsonl",
+        }
+    }
+
+    /// Plain text only ever holds the prompt text
+    pub fn has_columns(&self) -> bool {
+        *self != ExportFormat::Txt
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportColumn {
+    Id,
+    Text,
+    SourceFile,
+    CreatedAt,
+    Tags,
+    Collections,
+    Wildcards,
+    ClusterId,
+    NegativePrompt,
+    Model,
+    /// Sampler settings as an A1111 parameters line
+    Settings,
+    Rating,
+    Favorite,
+    Notes,
+}
+
+impl ExportColumn {
+    pub const ALL: [ExportColumn; 14] = [
+        ExportColumn::Id,
+        ExportColumn::Text,
+        ExportColumn::SourceFile,
+        ExportColumn::CreatedAt,
+        ExportColumn::Tags,
+        ExportColumn::Collections,
+        ExportColumn::Wildcards,
+        ExportColumn::ClusterId,
+        ExportColumn::NegativePrompt,
+        ExportColumn::Model,
+        ExportColumn::Settings,
+        ExportColumn::Rating,
+        ExportColumn::Favorite,
+        ExportColumn::Notes,
+    ];
+
+    /// Header / key name in the exported file
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExportColumn::Id => "id",
+            ExportColumn::Text => "text",
+            ExportColumn::SourceFile => "source_file",
+            ExportColumn::CreatedAt => "created_at",
+            ExportColumn::Tags => "tags",
+            ExportColumn::Collections => "collections",
+            ExportColumn::Wildcards => "wildcards",
+            ExportColumn::ClusterId => "cluster_id",
+            ExportColumn::NegativePrompt => "negative_prompt",
+            ExportColumn::Model => "model",
+            ExportColumn::Settings => "settings",
+            ExportColumn::Rating => "rating",
+            ExportColumn::Favorite => "favorite",
+            ExportColumn::Notes => "notes",
+        }
+    }
+}
+
+/// Data that isn't stored on `Prompt` itself, keyed by prompt id
+#[derive(Debug, Clone, Default)]
+pub struct ExportMetadata {
+    pub tags: HashMap<i64, Vec<String>>,
+    pub collections: HashMap<i64, Vec<String>>,
+    /// Wildcards each prompt is an entry of
+    pub wildcards: HashMap<i64, Vec<String>>,
+    /// Similarity cluster from the last duplicate scan, see `similarity::clusters_from_pairs`
+    pub clusters: HashMap<i64, usize>,
+}
+
+impl ExportMetadata {
+    fn value(&self, prompt: &Prompt, column: ExportColumn) -> Value {
+        match column {
+            ExportColumn::Id => Value::from(prompt.id),
+            ExportColumn::Text => Value::from(prompt.text.as_str()),
+            ExportColumn::SourceFile => prompt.source_file.as_deref().map_or(Value::Null, Value::from),
+            ExportColumn::CreatedAt => Value::from(prompt.created_at.as_str()),
+            ExportColumn::Tags => Value::from(self.tags.get(&prompt.id).cloned().unwrap_or_default()),
+            ExportColumn::Collections => Value::from(self.collections.get(&prompt.id).cloned().unwrap_or_default()),
+            ExportColumn::Wildcards => Value::from(self.wildcards.get(&prompt.id).cloned().unwrap_or_default()),
+            ExportColumn::ClusterId => self.clusters.get(&prompt.id).map_or(Value::Null, |&id| Value::from(id)),
+            ExportColumn::NegativePrompt => prompt.metadata.negative_prompt.as_deref().map_or(Value::Null, Value::from),
+            ExportColumn::Model => prompt.metadata.model.as_deref().map_or(Value::Null, Value::from),
+            ExportColumn::Settings => match prompt.metadata.settings_text() {
+                settings if settings.is_empty() => Value::Null,
+                settings => Value::from(settings),
+            },
+            ExportColumn::Rating => prompt.metadata.rating.map_or(Value::Null, Value::from),
+            ExportColumn::Favorite => Value::from(prompt.metadata.favorite),
+            ExportColumn::Notes => prompt.metadata.notes.as_deref().map_or(Value::Null, Value::from),
+        }
+    }
+
+    /// Flat text for formats without nested values
+    fn text(&self, prompt: &Prompt, column: ExportColumn) -> String {
+        match self.value(prompt, column) {
+            Value::Null => String::new(),
+            Value::String(s) => s,
+            Value::Array(items) => items.iter()
+                .filter_map(|item| item.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+            other => other.to_string(),
+        }
+    }
+
+    fn object(&self, prompt: &Prompt, columns: &[ExportColumn]) -> Value {
+        let map: Map<String, Value> = columns.iter()
+            .map(|&column| (column.name().to_string(), self.value(prompt, column)))
+            .collect();
+        Value::Object(map)
+    }
+}
+
+/// Writes prompts in one file format
+pub trait Exporter {
+    fn write(
+        &self,
+        prompts: &[Prompt],
+        columns: &[ExportColumn],
+        metadata: &ExportMetadata,
+        out: &mut dyn Write,
+    ) -> io::Result<()>;
+}
+
+/// One prompt per line
+pub struct TxtExporter;
+
+impl Exporter for TxtExporter {
+    fn write(&self, prompts: &[Prompt], _: &[ExportColumn], _: &ExportMetadata, out: &mut dyn Write) -> io::Result<()> {
+        for prompt in prompts {
+            writeln!(out, "{}", one_line(&prompt.text))?;
+        }
+        Ok(())
+    }
+}
+
+/// Collapse line breaks so a multi-line prompt stays one entry in line-based files
+fn one_line(text: &str) -> String {
+    text.lines().map(str::trim).filter(|line| !line.is_empty()).collect::<Vec<_>>().join(" ")
+}
+
+/// RFC 4180 CSV with a header row; fields with commas, quotes or newlines are quoted
+pub struct CsvExporter;
+
+impl Exporter for CsvExporter {
+    fn write(&self, prompts: &[Prompt], columns: &[ExportColumn], metadata: &ExportMetadata, out: &mut dyn Write) -> io::Result<()> {
+        let mut writer = csv::Writer::from_writer(out);
+        writer.write_record(columns.iter().map(|c| c.name()))?;
+        for prompt in prompts {
+            writer.write_record(columns.iter().map(|&c| metadata.text(prompt, c)))?;
+        }
+        writer.flush()
+    }
+}
+
+/// A single JSON array of objects
+pub struct JsonExporter;
+
+impl Exporter for JsonExporter {
+    fn write(&self, prompts: &[Prompt], columns: &[ExportColumn], metadata: &ExportMetadata, out: &mut dyn Write) -> io::Result<()> {
+        let items: Vec<Value> = prompts.iter()
+            .map(|prompt| metadata.object(prompt, columns))
+            .collect();
+        serde_json::to_writer_pretty(&mut *out, &items)?;
+        writeln!(out)
+    }
+}
+
+/// One JSON object per line
+pub struct JsonlExporter;
+
+impl Exporter for JsonlExporter {
+    fn write(&self, prompts: &[Prompt], columns: &[ExportColumn], metadata: &ExportMetadata, out: &mut dyn Write) -> io::Result<()> {
+        for prompt in prompts {
+            serde_json::to_writer(&mut *out, &metadata.object(prompt, columns))?;
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn exporter(format: ExportFormat) -> Box<dyn Exporter> {
+    match format {
+        ExportFormat::Txt => Box::new(TxtExporter),
+        ExportFormat::Csv => Box::new(CsvExporter),
+        ExportFormat::Json => Box::new(JsonExporter),
+        ExportFormat::Jsonl => Box::new(JsonlExporter),
+    }
+}
+
+pub fn export_to_file(
+    prompts: &[Prompt],
+    path: &Path,
+    format: ExportFormat,
+    columns: &[ExportColumn],
+    metadata: &ExportMetadata,
+) -> io::Result<usize> {
+    let mut file = BufWriter::new(File::create(path)?);
+    exporter(format).write(prompts, columns, metadata, &mut file)?;
+    file.flush()?;
+    Ok(prompts.len())
+}
+
+/// Which prompts an export starts from, before filters are applied
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportSource {
+    /// Whatever Browse currently shows
+    Displayed,
+    /// Rows ticked in Browse
+    Selection,
+    /// Prompts in the clusters from the last duplicate scan
+    Clusters,
+}
+
+impl ExportSource {
+    pub const ALL: [ExportSource; 3] = [ExportSource::Displayed, ExportSource::Selection, ExportSource::Clusters];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ExportSource::Displayed => "Shown in Browse",
+            ExportSource::Selection => "Selected prompts",
+            ExportSource::Clusters => "Duplicate clusters",
+        }
+    }
+}
+
+/// Which member of a duplicate cluster stands in for the whole cluster
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Keeper {
+    Oldest,
+    Newest,
+    Longest,
+    Shortest,
+}
+
+impl Keeper {
+    pub const ALL: [Keeper; 4] = [Keeper::Oldest, Keeper::Newest, Keeper::Longest, Keeper::Shortest];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Keeper::Oldest => "Oldest",
+            Keeper::Newest => "Newest",
+            Keeper::Longest => "Longest",
+            Keeper::Shortest => "Shortest",
+        }
+    }
+
+    /// Ties fall back to the lower id so the choice is stable across exports
+    fn pick<'a>(&self, members: &[&'a Prompt]) -> Option<&'a Prompt> {
+        let by_id = |a: &&Prompt, b: &&Prompt| b.id.cmp(&a.id);
+        match self {
+            Keeper::Oldest => members.iter().copied().min_by_key(|p| p.id),
+            Keeper::Newest => members.iter().copied().max_by_key(|p| p.id),
+            Keeper::Longest => members.iter().copied()
+                .max_by(|a, b| a.text.chars().count().cmp(&b.text.chars().count()).then(by_id(a, b))),
+            Keeper::Shortest => members.iter().copied()
+                .min_by(|a, b| a.text.chars().count().cmp(&b.text.chars().count()).then(by_id(b, a))),
+        }
+    }
+}
+
+/// Narrows the starting set of an export. Empty fields don't filter.
+#[derive(Debug, Clone)]
+pub struct ExportFilter {
+    pub source: ExportSource,
+    /// Keep entries of any of these wildcards
+    pub wildcards: Vec<String>,
+    /// Inclusive `YYYY-MM-DD` bounds on `created_at`
+    pub date_from: String,
+    pub date_to: String,
+    pub source_file: Option<String>,
+    /// With `ExportSource::Clusters`, export one keeper per cluster instead of every member
+    pub keepers_only: bool,
+    pub keeper: Keeper,
+    /// Export this many random prompts from the filtered set
+    pub sample: Option<usize>,
+}
+
+impl Default for ExportFilter {
+    fn default() -> Self {
+        ExportFilter {
+            source: ExportSource::Displayed,
+            wildcards: Vec::new(),
+            date_from: String::new(),
+            date_to: String::new(),
+            source_file: None,
+            keepers_only: true,
+            keeper: Keeper::Oldest,
+            sample: None,
+        }
+    }
+}
+
+fn is_date(text: &str) -> bool {
+    let bytes = text.as_bytes();
+    bytes.len() == 10
+        && bytes.iter().enumerate().all(|(i, b)| if i == 4 || i == 7 { *b == b'-' } else { b.is_ascii_digit() })
+}
+
+impl ExportFilter {
+    /// Problem with the filter fields, if any
+    pub fn validate(&self) -> Result<(), String> {
+        for date in [&self.date_from, &self.date_to] {
+            if !date.trim().is_empty() && !is_date(date.trim()) {
+                return Err(format!("'{}' is not a date, use YYYY-MM-DD", date.trim()));
+            }
+        }
+        if self.sample == Some(0) {
+            return Err("Sample size must be at least 1".to_string());
+        }
+        Ok(())
+    }
+
+    /// Apply the filters and keeper choice to `prompts`, which must already be
+    /// the starting set for `self.source`. Order is preserved.
+    pub fn filter(
+        &self,
+        prompts: Vec<Prompt>,
+        memberships: &HashMap<i64, Vec<String>>,
+        clusters: &HashMap<i64, usize>,
+    ) -> Vec<Prompt> {
+        let (from, to) = (self.date_from.trim(), self.date_to.trim());
+        let mut filtered: Vec<Prompt> = prompts.into_iter()
+            .filter(|p| {
+                self.wildcards.is_empty()
+                    || memberships.get(&p.id).is_some_and(|names| names.iter().any(|n| self.wildcards.contains(n)))
+            })
+            .filter(|p| {
+                let day = p.created_at.get(..10).unwrap_or(&p.created_at);
+                (from.is_empty() || day >= from) && (to.is_empty() || day <= to)
+            })
+            .filter(|p| self.source_file.is_none() || p.source_file == self.source_file)
+            .collect();
+
+        if self.source == ExportSource::Clusters && self.keepers_only {
+            let mut members: HashMap<usize, Vec<&Prompt>> = HashMap::new();
+            for prompt in &filtered {
+                if let Some(&cluster) = clusters.get(&prompt.id) {
+                    members.entry(cluster).or_default().push(prompt);
+                }
+            }
+            let keepers: HashSet<i64> = members.values()
+                .filter_map(|m| self.keeper.pick(m))
+                .map(|p| p.id)
+                .collect();
+            filtered.retain(|p| keepers.contains(&p.id));
+        }
+        filtered
+    }
+
+    /// Random subset of `prompts` when sampling is on, keeping their order
+    pub fn sample(&self, mut prompts: Vec<Prompt>) -> Vec<Prompt> {
+        if let Some(size) = self.sample
+            && size < prompts.len()
+        {
+            let mut indices: Vec<usize> = (0..prompts.len()).collect();
+            fastrand::shuffle(&mut indices);
+            indices.truncate(size);
+            indices.sort_unstable();
+            let mut index = 0;
+            let mut picked = indices.into_iter().peekable();
+            prompts.retain(|_| {
+                let keep = picked.peek() == Some(&index);
+                if keep {
+                    picked.next();
+                }
+                index += 1;
+                keep
+            });
+        }
+        prompts
+    }
+
+    /// Human-readable summary of what was exported, e.g.
+    /// "selected prompts, created 2026-01-01 to 2026-02-01, random sample of 50 from 1200".
+    /// `shown` is the Browse filter and `total` the count before sampling.
+    pub fn describe(&self, shown: &PromptFilter, total: usize) -> String {
+        let mut parts = Vec::new();
+        match self.source {
+            ExportSource::Displayed => {
+                parts.extend(shown.describe());
+                if parts.is_empty() {
+                    parts.push("all prompts".to_string());
+                }
+            }
+            ExportSource::Selection => parts.push("selected prompts".to_string()),
+            ExportSource::Clusters if self.keepers_only => {
+                parts.push(format!("{} keeper of each duplicate cluster", self.keeper.label().to_lowercase()));
+            }
+            ExportSource::Clusters => parts.push("all members of duplicate clusters".to_string()),
+        }
+        if !self.wildcards.is_empty() {
+            parts.push(format!("in {}", self.wildcards.iter()
+                .map(|c| format!("__{}__", c))
+                .collect::<Vec<_>>()
+                .join(" or ")));
+        }
+        match (self.date_from.trim(), self.date_to.trim()) {
+            ("", "") => {}
+            (from, "") => parts.push(format!("created from {}", from)),
+            ("", to) => parts.push(format!("created until {}", to)),
+            (from, to) => parts.push(format!("created {} to {}", from, to)),
+        }
+        if let Some(file) = &self.source_file {
+            parts.push(format!("from {}", file));
+        }
+        if let Some(size) = self.sample
+            && size < total
+        {
+            parts.push(format!("random sample of {} from {}", size, total));
+        }
+        parts.join(", ")
+    }
+}
+
+/// File listing what the last wildcard export wrote, so a re-export can remove
+/// files it created without touching anything else in the folder
+const WILDCARD_MANIFEST: &str = ".prompt-dedup-wildcards";
+
+/// Turn a tag, collection or file name into a wildcard identifier usable in `__name__`.
+/// Path segments separated by `/` are kept so nested wildcards stay nested.
+pub fn sanitize_wildcard_name(name: &str) -> String {
+    let segments: Vec<String> = name
+        .split(['/', '\\'])
+        .map(|segment| {
+            let mut clean = String::new();
+            for c in segment.trim().to_lowercase().chars() {
+                if c.is_alphanumeric() || c == '-' {
+                    clean.push(c);
+                } else if !clean.ends_with('_') {
+                    clean.push('_');
+                }
+            }
+            clean.trim_matches('_').to_string()
+        })
+        .filter(|segment| !segment.is_empty())
+        .collect();
+
+    if segments.is_empty() {
+        "unnamed".to_string()
+    } else {
+        segments.join("/")
+    }
+}
+
+/// Sanitize group names, merging groups that end up with the same name and
+/// dropping repeated entries, so the output only depends on the input set
+fn sanitize_groups(groups: &[(String, Vec<String>)]) -> Vec<(String, Vec<String>)> {
+    let mut merged: Vec<(String, Vec<String>)> = Vec::new();
+    for (name, entries) in groups {
+        let name = sanitize_wildcard_name(name);
+        let index = match merged.iter().position(|(n, _)| *n == name) {
+            Some(index) => index,
+            None => {
+                merged.push((name, Vec::new()));
+                merged.len() - 1
+            }
+        };
+        for entry in entries {
+            let entry = one_line(entry);
+            if !entry.is_empty() && !merged[index].1.contains(&entry) {
+                merged[index].1.push(entry);
+            }
+        }
+    }
+    merged.sort_by(|a, b| a.0.cmp(&b.0));
+    merged
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct WildcardExportSummary {
+    pub written: usize,
+    pub unchanged: usize,
+    pub removed: usize,
+}
+
+/// Write one `<name>.txt` per group into `dir`. Files whose content is already
+/// up to date are left alone, and files from a previous export that no longer
+/// have a group are removed.
+pub fn export_wildcard_folder(groups: &[(String, Vec<String>)], dir: &Path) -> io::Result<WildcardExportSummary> {
+    let mut summary = WildcardExportSummary::default();
+    let mut written_files: Vec<String> = Vec::new();
+
+    for (name, entries) in sanitize_groups(groups) {
+        let relative = format!("{}.txt", name);
+        let path = dir.join(&relative);
+        let mut contents = entries.join("\n");
+        contents.push('\n');
+
+        if std::fs::read_to_string(&path).is_ok_and(|existing| existing == contents) {
+            summary.unchanged += 1;
+        } else {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(&path, contents)?;
+            summary.written += 1;
+        }
+        written_files.push(relative);
+    }
+
+    let manifest_path = dir.join(WILDCARD_MANIFEST);
+    if let Ok(previous) = std::fs::read_to_string(&manifest_path) {
+        // The manifest sits in a folder anyone can edit, so only ever remove
+        // plain relative `.txt` paths that stay inside `dir`
+        let removable = |line: &&str| {
+            line.ends_with(".txt") && safe_relative_path(line).is_some_and(|path| path == Path::new(line))
+        };
+        for stale in previous.lines().filter(removable) {
+            if !written_files.iter().any(|f| f == stale) && std::fs::remove_file(dir.join(stale)).is_ok() {
+                summary.removed += 1;
+            }
+        }
+    }
+    std::fs::write(&manifest_path, written_files.join("\n") + "\n")?;
+
+    Ok(summary)
+}
+
+/// Write all groups into one nested YAML wildcard file:
+/// `clothing/tops` becomes `clothing: { tops: [...] }`.
+/// A group whose name is also the parent of other groups is written as `<name>/all`.
+pub fn export_wildcard_yaml(groups: &[(String, Vec<String>)], path: &Path) -> io::Result<usize> {
+    use serde_yaml::{Mapping, Value as Yaml};
+
+    fn insert(map: &mut Mapping, segments: &[&str], entries: &[String]) {
+        let key = Yaml::from(segments[0]);
+        if segments.len() == 1 {
+            let list = Yaml::Sequence(entries.iter().map(|e| Yaml::from(e.as_str())).collect());
+            match map.get_mut(&key) {
+                Some(Yaml::Mapping(children)) => {
+                    children.insert(Yaml::from("all"), list);
+                }
+                _ => {
+                    map.insert(key, list);
+                }
+            }
+            return;
+        }
+
+        if let Some(Yaml::Sequence(items)) = map.get(&key) {
+            let mut children = Mapping::new();
+            children.insert(Yaml::from("all"), Yaml::Sequence(items.clone()));
+            map.insert(key.clone(), Yaml::Mapping(children));
+        }
+        let child = map.entry(key).or_insert_with(|| Yaml::Mapping(Mapping::new()));
+        if let Yaml::Mapping(children) = child {
+            insert(children, &segments[1..], entries);
+        }
+    }
+
+    let groups = sanitize_groups(groups);
+    let mut root = Mapping::new();
+    for (name, entries) in &groups {
+        let segments: Vec<&str> = name.split('/').collect();
+        insert(&mut root, &segments, entries);
+    }
+
+    let yaml = serde_yaml::to_string(&Yaml::Mapping(root))
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    std::fs::write(path, yaml)?;
+    Ok(groups.len())
+}
+
+/// One row of an Automatic1111 / Forge `styles.csv`
+#[derive(Debug, Clone)]
+pub struct StyleEntry {
+    pub name: String,
+    pub prompt: String,
+    pub negative_prompt: String,
+}
+
+/// Where the `{prompt}` placeholder goes in an exported style.
+/// A1111 substitutes the user's prompt there instead of appending the style to it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StylePlaceholder {
+    None,
+    Start,
+    End,
+}
+
+impl StylePlaceholder {
+    pub const ALL: [StylePlaceholder; 3] = [StylePlaceholder::None, StylePlaceholder::Start, StylePlaceholder::End];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            StylePlaceholder::None => "No placeholder",
+            StylePlaceholder::Start => "{prompt} at start",
+            StylePlaceholder::End => "{prompt} at end",
+        }
+    }
+
+    pub fn apply(&self, text: &str) -> String {
+        if text.contains("{prompt}") {
+            return text.to_string();
+        }
+        match self {
+            StylePlaceholder::None => text.to_string(),
+            StylePlaceholder::Start => format!("{{prompt}}, {}", text),
+            StylePlaceholder::End => format!("{}, {{prompt}}", text),
+        }
+    }
+}
+
+/// Default style name: the first `words` words of the prompt, without trailing commas
+pub fn style_name_from_text(text: &str, words: usize) -> String {
+    text.split_whitespace()
+        .take(words.max(1))
+        .collect::<Vec<_>>()
+        .join(" ")
+        .trim_end_matches([',', '.', ';'])
+        .to_string()
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct StylesExportSummary {
+    pub added: usize,
+    pub updated: usize,
+    /// Entries renamed because the name was already taken
+    pub renamed: usize,
+}
+
+/// Sidecar next to a styles.csv listing the style names this app wrote
+fn styles_manifest_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".prompt-dedup");
+    path.with_file_name(name)
+}
+
+/// Write `entries` as a styles.csv. With `merge`, rows already in the file are kept:
+/// styles this app wrote before are updated in place, and any other style with the
+/// same name is left alone while ours gets a numbered name instead.
+pub fn export_styles(entries: &[StyleEntry], path: &Path, merge: bool) -> io::Result<StylesExportSummary> {
+    let mut summary = StylesExportSummary::default();
+    let manifest_path = styles_manifest_path(path);
+
+    let mut owned: Vec<String> = Vec::new();
+    let mut rows: Vec<csv::StringRecord> = Vec::new();
+    let mut header = csv::StringRecord::from(vec!["name", "prompt", "negative_prompt"]);
+
+    if merge && path.exists() {
+        let mut reader = csv::ReaderBuilder::new().flexible(true).from_path(path)?;
+        header = reader.headers()?.clone();
+        for record in reader.records() {
+            rows.push(record?);
+        }
+        owned = std::fs::read_to_string(&manifest_path)
+            .map(|m| m.lines().map(str::to_string).collect())
+            .unwrap_or_default();
+    }
+
+    let column = |name: &str, fallback: usize| header.iter().position(|h| h == name).unwrap_or(fallback);
+    let (name_col, prompt_col, negative_col) = (column("name", 0), column("prompt", 1), column("negative_prompt", 2));
+    let width = header.len().max(3);
+
+    let mut used: Vec<String> = Vec::new();
+    for entry in entries {
+        let mut name = entry.name.clone();
+        let mut suffix = 2;
+        loop {
+            let existing = rows.iter().position(|row| row.get(name_col) == Some(name.as_str()));
+            match existing {
+                Some(index) if owned.contains(&name) && !used.contains(&name) => {
+                    let mut fields: Vec<String> = rows[index].iter().map(str::to_string).collect();
+                    fields.resize(width, String::new());
+                    fields[prompt_col] = entry.prompt.clone();
+                    fields[negative_col] = entry.negative_prompt.clone();
+                    rows[index] = csv::StringRecord::from(fields);
+                    summary.updated += 1;
+                    break;
+                }
+                Some(_) => {
+                    // Taken by a style we didn't create, or earlier in this export
+                    name = format!("{} ({})", entry.name, suffix);
+                    suffix += 1;
+                }
+                None => {
+                    let mut fields = vec![String::new(); width];
+                    fields[name_col] = name.clone();
+                    fields[prompt_col] = entry.prompt.clone();
+                    fields[negative_col] = entry.negative_prompt.clone();
+                    rows.push(csv::StringRecord::from(fields));
+                    owned.push(name.clone());
+                    summary.added += 1;
+                    break;
+                }
+            }
+        }
+        if name != entry.name {
+            summary.renamed += 1;
+        }
+        used.push(name);
+    }
+
+    let mut writer = csv::Writer::from_path(path)?;
+    writer.write_record(&header)?;
+    for row in &rows {
+        writer.write_record(row)?;
+    }
+    writer.flush()?;
+
+    owned.sort();
+    owned.dedup();
+    std::fs::write(&manifest_path, owned.join("\n") + "\n")?;
+
+    Ok(summary)
+}
+
+/// Relative path for a `source_file` value inside an export folder.
+/// Absolute paths and `..` segments are dropped so nothing is written outside the folder.
+pub fn safe_relative_path(name: &str) -> Option<PathBuf> {
+    let path: PathBuf = name
+        .split(['/', '\\'])
+        .filter(|segment| !segment.is_empty() && *segment != "." && *segment != "..")
+        .filter(|segment| !segment.contains(':'))
+        .collect();
+    (path.components().count() > 0).then_some(path)
+}
+
+/// Group prompts by source file, keeping their order. Prompts without a source
+/// (or with one that isn't a usable file name) go to `fallback`.
+pub fn split_by_source<'a>(prompts: &'a [Prompt], fallback: &str) -> Vec<(PathBuf, Vec<&'a Prompt>)> {
+    let fallback = safe_relative_path(fallback).unwrap_or_else(|| "unsorted.txt".into());
+    let mut groups: Vec<(PathBuf, Vec<&Prompt>)> = Vec::new();
+    let mut index: HashMap<PathBuf, usize> = HashMap::new();
 
     for prompt in prompts {
-        writeln!(file, "{}", prompt.text)?;
+        let path = prompt.source_file.as_deref()
+            .and_then(safe_relative_path)
+            .unwrap_or_else(|| fallback.clone());
+        let i = *index.entry(path.clone()).or_insert_with(|| {
+            groups.push((path, Vec::new()));
+            groups.len() - 1
+        });
+        groups[i].1.push(prompt);
+    }
+    groups
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SplitExportSummary {
+    pub files: usize,
+    pub prompts: usize,
+    pub backups: usize,
+}
+
+/// Write one text file per source file into `dir`.
+/// With `backup`, an existing file is first copied to `<name>.bak`.
+pub fn export_split(prompts: &[Prompt], dir: &Path, fallback: &str, backup: bool) -> io::Result<SplitExportSummary> {
+    let mut summary = SplitExportSummary::default();
+
+    for (relative, group) in split_by_source(prompts, fallback) {
+        let path = dir.join(&relative);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        if backup && path.exists() {
+            let mut backup_name = path.file_name().unwrap_or_default().to_os_string();
+            backup_name.push(".bak");
+            std::fs::copy(&path, path.with_file_name(backup_name))?;
+            summary.backups += 1;
+        }
+
+        let mut file = BufWriter::new(File::create(&path)?);
+        for prompt in &group {
+            writeln!(file, "{}", one_line(&prompt.text))?;
+        }
+        file.flush()?;
+
+        summary.files += 1;
+        summary.prompts += group.len();
+    }
+
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn group(name: &str, entries: &[&str]) -> (String, Vec<String>) {
+        (name.to_string(), entries.iter().map(|e| e.to_string()).collect())
+    }
+
+    #[test]
+    fn sanitizes_wildcard_names() {
+        assert_eq!(sanitize_wildcard_name("Hair Color"), "hair_color");
+        assert_eq!(sanitize_wildcard_name("  clothing / Tops!! "), "clothing/tops");
+        assert_eq!(sanitize_wildcard_name("..\\styles//sci-fi"), "styles/sci-fi");
+        assert_eq!(sanitize_wildcard_name("???"), "unnamed");
+    }
+
+    #[test]
+    fn merges_groups_that_sanitize_to_the_same_name() {
+        let groups = sanitize_groups(&[
+            group("Hair Color", &["red", " blue "]),
+            group("colors", &["green"]),
+            group("hair color", &["blue", "black", ""]),
+        ]);
+        assert_eq!(groups, [group("colors", &["green"]), group("hair_color", &["red", "blue", "black"])]);
     }
 
-    Ok(count)
+    #[test]
+    fn multi_line_entries_stay_one_line() {
+        assert_eq!(one_line("a cat,\r\n  on a mat\n\nsunset "), "a cat, on a mat sunset");
+        let dir = tempfile::tempdir().unwrap();
+        export_wildcard_folder(&[group("scenes", &["a cat,\non a mat", "a dog"])], dir.path()).unwrap();
+        assert_eq!(std::fs::read_to_string(dir.path().join("scenes.txt")).unwrap(), "a cat, on a mat\na dog\n");
+    }
+
+    #[test]
+    fn wildcard_folder_rewrites_only_changes_and_removes_stale_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let summary = export_wildcard_folder(&[group("hair", &["red"]), group("clothing/tops", &["shirt"])], dir.path()).unwrap();
+        assert_eq!((summary.written, summary.unchanged, summary.removed), (2, 0, 0));
+        assert_eq!(std::fs::read_to_string(dir.path().join("clothing/tops.txt")).unwrap(), "shirt\n");
+
+        let summary = export_wildcard_folder(&[group("hair", &["red"])], dir.path()).unwrap();
+        assert_eq!((summary.written, summary.unchanged, summary.removed), (0, 1, 1));
+        assert!(!dir.path().join("clothing/tops.txt").exists());
+    }
+
+    #[test]
+    fn wildcard_folder_only_removes_txt_files_inside_the_folder() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("wildcards");
+        std::fs::create_dir(&dir).unwrap();
+        let outside = root.path().join("outside.txt");
+        let absolute = root.path().join("absolute.txt");
+        for path in [&outside, &absolute, &dir.join("notes.md"), &dir.join("old.txt")] {
+            std::fs::write(path, "keep\n").unwrap();
+        }
+        let manifest = format!("../outside.txt\n{}\nnotes.md\nold.txt\n", absolute.display());
+        std::fs::write(dir.join(WILDCARD_MANIFEST), manifest).unwrap();
+
+        let summary = export_wildcard_folder(&[group("hair", &["red"])], &dir).unwrap();
+        assert_eq!(summary.removed, 1);
+        assert!(!dir.join("old.txt").exists());
+        assert!(outside.exists() && absolute.exists() && dir.join("notes.md").exists());
+    }
+
+    #[test]
+    fn wildcard_yaml_nests_groups_under_their_parents() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wildcards.yaml");
+        let count = export_wildcard_yaml(&[group("clothing", &["coat"]), group("clothing/tops", &["shirt"])], &path).unwrap();
+        assert_eq!(count, 2);
+        let yaml: serde_yaml::Value = serde_yaml::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(yaml["clothing"]["all"][0], "coat");
+        assert_eq!(yaml["clothing"]["tops"][0], "shirt");
+    }
+
+    fn style(name: &str, prompt: &str) -> StyleEntry {
+        StyleEntry { name: name.to_string(), prompt: prompt.to_string(), negative_prompt: String::new() }
+    }
+
+    fn read_styles(path: &Path) -> Vec<Vec<String>> {
+        csv::ReaderBuilder::new().flexible(true).from_path(path).unwrap()
+            .records()
+            .map(|record| record.unwrap().iter().map(str::to_string).collect())
+            .collect()
+    }
+
+    #[test]
+    fn styles_merge_updates_ours_and_renames_around_others() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("styles.csv");
+        std::fs::write(&path, "name,prompt,negative_prompt,extra\nCinematic,film grain,blurry,keep me\n").unwrap();
+
+        let summary = export_styles(&[style("Portrait", "soft light"), style("Cinematic", "anamorphic")], &path, true).unwrap();
+        assert_eq!((summary.added, summary.updated, summary.renamed), (2, 0, 1));
+        assert_eq!(read_styles(&path), [
+            ["Cinematic", "film grain", "blurry", "keep me"],
+            ["Portrait", "soft light", "", ""],
+            ["Cinematic (2)", "anamorphic", "", ""],
+        ]);
+
+        // Styles written before are ours to update, the user's own row stays untouched
+        let summary = export_styles(&[style("Portrait", "rim light"), style("Cinematic (2)", "lens flare")], &path, true).unwrap();
+        assert_eq!((summary.added, summary.updated, summary.renamed), (0, 2, 0));
+        assert_eq!(read_styles(&path), [
+            ["Cinematic", "film grain", "blurry", "keep me"],
+            ["Portrait", "rim light", "", ""],
+            ["Cinematic (2)", "lens flare", "", ""],
+        ]);
+    }
+
+    #[test]
+    fn styles_without_merge_replace_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("styles.csv");
+        std::fs::write(&path, "name,prompt,negative_prompt\nCinematic,film grain,\n").unwrap();
+
+        let summary = export_styles(&[style("Cinematic", "anamorphic"), style("Cinematic", "lens flare")], &path, false).unwrap();
+        assert_eq!((summary.added, summary.updated, summary.renamed), (2, 0, 1));
+        assert_eq!(read_styles(&path), [["Cinematic", "anamorphic", ""], ["Cinematic (2)", "lens flare", ""]]);
+    }
+
+    #[test]
+    fn style_placeholders_and_names() {
+        assert_eq!(StylePlaceholder::Start.apply("film grain"), "{prompt}, film grain");
+        assert_eq!(StylePlaceholder::End.apply("film grain"), "film grain, {prompt}");
+        assert_eq!(StylePlaceholder::End.apply("{prompt} in film grain"), "{prompt} in film grain");
+        assert_eq!(style_name_from_text("a castle, at dusk, oil painting", 3), "a castle, at");
+    }
 }
@@ -0,0 +1,854 @@
+use encoding_rs::{Decoder, Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
+use regex::Regex;
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use crate::db::{Database, PromptMetadata};
+use crate::error::{Error, Result};
+use crate::rules::RuleSet;
+
+/// Bytes read from a file for the import preview
+pub const PREVIEW_BYTES: usize = 256 * 1024;
+
+/// Records inserted between progress updates and cancel checks
+const BATCH_SIZE: usize = 1000;
+
+const READ_CHUNK: usize = 64 * 1024;
+
+/// Character encoding used to decode an import file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextEncoding {
+    Auto,
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Windows1252,
+}
+
+impl TextEncoding {
+    pub const ALL: [TextEncoding; 5] = [
+        TextEncoding::Auto,
+        TextEncoding::Utf8,
+        TextEncoding::Utf16Le,
+        TextEncoding::Utf16Be,
+        TextEncoding::Windows1252,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            TextEncoding::Auto => "Auto-detect",
+            TextEncoding::Utf8 => "UTF-8",
+            TextEncoding::Utf16Le => "UTF-16 LE",
+            TextEncoding::Utf16Be => "UTF-16 BE",
+            TextEncoding::Windows1252 => "Windows-1252 (Latin-1)",
+        }
+    }
+
+    fn encoding(&self) -> Option<&'static Encoding> {
+        match self {
+            TextEncoding::Auto => None,
+            TextEncoding::Utf8 => Some(UTF_8),
+            TextEncoding::Utf16Le => Some(UTF_16LE),
+            TextEncoding::Utf16Be => Some(UTF_16BE),
+            TextEncoding::Windows1252 => Some(WINDOWS_1252),
+        }
+    }
+}
+
+/// File contents decoded to text
+#[derive(Debug, Clone)]
+pub struct DecodedText {
+    pub text: String,
+    /// Name of the encoding that was actually used
+    pub encoding: &'static str,
+    /// 1-based line numbers that contained bytes invalid in that encoding
+    pub invalid_lines: Vec<usize>,
+}
+
+/// Decode raw file bytes. A byte order mark always wins; otherwise `Auto` guesses
+/// between UTF-16 (by its zero bytes), UTF-8 and Windows-1252.
+/// Invalid bytes are replaced with U+FFFD instead of failing the whole file.
+pub fn decode(bytes: &[u8], choice: TextEncoding) -> DecodedText {
+    let (encoding, body) = match Encoding::for_bom(bytes) {
+        Some((encoding, bom_len)) => (encoding, &bytes[bom_len..]),
+        None => (choice.encoding().unwrap_or_else(|| sniff_encoding(bytes)), bytes),
+    };
+
+    let (text, had_errors) = encoding.decode_without_bom_handling(body);
+    let invalid_lines = if had_errors {
+        text.lines()
+            .enumerate()
+            .filter(|(_, line)| line.contains(char::REPLACEMENT_CHARACTER))
+            .map(|(i, _)| i + 1)
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    DecodedText {
+        text: text.into_owned(),
+        encoding: encoding.name(),
+        invalid_lines,
+    }
+}
+
+/// Read the start of a file for the preview, along with the full file size
+pub fn read_preview(path: &Path) -> io::Result<(Vec<u8>, u64)> {
+    let file = File::open(path)?;
+    let total_bytes = file.metadata()?.len();
+    let mut bytes = Vec::new();
+    file.take(PREVIEW_BYTES as u64).read_to_end(&mut bytes)?;
+    Ok((bytes, total_bytes))
+}
+
+/// Like `decode`, for bytes cut off from a longer file.
+/// The trailing partial line is dropped so a split character isn't reported as invalid.
+pub fn decode_partial(bytes: &[u8], choice: TextEncoding) -> DecodedText {
+    let mut decoded = decode(bytes, choice);
+    if let Some(end) = decoded.text.rfind('\n') {
+        decoded.text.truncate(end);
+        let line_count = decoded.text.lines().count();
+        decoded.invalid_lines.retain(|&line| line <= line_count);
+    }
+    decoded
+}
+
+/// Decodes a file chunk by chunk and hands out one line at a time
+pub struct LineReader<R: Read> {
+    reader: R,
+    decoder: Decoder,
+    buffer: Vec<u8>,
+    pending: String,
+    eof: bool,
+    had_errors: bool,
+    line_number: usize,
+    invalid_lines: Vec<usize>,
+    bytes_read: u64,
+    /// Of the raw bytes read so far
+    hasher: Sha256,
+}
+
+impl<R: Read> LineReader<R> {
+    /// Sniff the encoding from the start of the stream, same rules as `decode`
+    pub fn new(mut reader: R, choice: TextEncoding) -> io::Result<Self> {
+        let mut buffer = vec![0; READ_CHUNK];
+        let mut filled = 0;
+        while filled < buffer.len() {
+            let n = reader.read(&mut buffer[filled..])?;
+            if n == 0 {
+                break;
+            }
+            filled += n;
+        }
+        buffer.truncate(filled);
+
+        let decoder = match Encoding::for_bom(&buffer) {
+            Some((encoding, _)) => encoding.new_decoder_with_bom_removal(),
+            None => choice.encoding()
+                .unwrap_or_else(|| sniff_encoding(&buffer))
+                .new_decoder_without_bom_handling(),
+        };
+
+        let mut line_reader = Self {
+            reader,
+            decoder,
+            buffer: vec![0; READ_CHUNK],
+            pending: String::new(),
+            eof: false,
+            had_errors: false,
+            line_number: 0,
+            invalid_lines: Vec::new(),
+            bytes_read: 0,
+            hasher: Sha256::new(),
+        };
+        line_reader.hasher.update(&buffer);
+        line_reader.decode_chunk(&buffer, false);
+        line_reader.bytes_read = filled as u64;
+        Ok(line_reader)
+    }
+
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+
+    /// 1-based line numbers that contained bytes invalid in the detected encoding
+    pub fn invalid_lines(&self) -> &[usize] {
+        &self.invalid_lines
+    }
+
+    /// SHA-256 in hex of the bytes read so far, the whole file once `next_line` returns `None`
+    pub fn hash(&self) -> String {
+        format!("{:x}", self.hasher.clone().finalize())
+    }
+
+    pub fn next_line(&mut self) -> io::Result<Option<String>> {
+        loop {
+            if let Some(pos) = self.pending.find('\n') {
+                let mut line: String = self.pending.drain(..=pos).collect();
+                line.pop();
+                if line.ends_with('\r') {
+                    line.pop();
+                }
+                return Ok(Some(self.finish_line(line)));
+            }
+
+            if self.eof {
+                if self.pending.is_empty() {
+                    return Ok(None);
+                }
+                let line = std::mem::take(&mut self.pending);
+                return Ok(Some(self.finish_line(line)));
+            }
+
+            let n = self.reader.read(&mut self.buffer)?;
+            self.bytes_read += n as u64;
+            self.hasher.update(&self.buffer[..n]);
+            self.eof = n == 0;
+            let chunk = std::mem::take(&mut self.buffer);
+            self.decode_chunk(&chunk[..n], self.eof);
+            self.buffer = chunk;
+        }
+    }
+
+    fn decode_chunk(&mut self, bytes: &[u8], last: bool) {
+        let needed = self.decoder
+            .max_utf8_buffer_length(bytes.len())
+            .unwrap_or(bytes.len() * 3);
+        self.pending.reserve(needed);
+        let (_, _, had_errors) = self.decoder.decode_to_string(bytes, &mut self.pending, last);
+        self.had_errors |= had_errors;
+    }
+
+    fn finish_line(&mut self, line: String) -> String {
+        self.line_number += 1;
+        if self.had_errors && line.contains(char::REPLACEMENT_CHARACTER) {
+            self.invalid_lines.push(self.line_number);
+        }
+        line
+    }
+}
+
+/// Everything a background import needs, detached from the UI state
+pub struct ImportJob {
+    pub db_path: String,
+    pub path: PathBuf,
+    pub file_name: String,
+    pub encoding: TextEncoding,
+    pub options: ImportOptions,
+}
+
+#[derive(Debug, Clone)]
+pub struct ImportSummary {
+    pub file_name: String,
+    pub total: usize,
+    pub added: usize,
+    /// 1-based line numbers that contained bytes invalid in the detected encoding
+    pub invalid_lines: Vec<usize>,
+    pub cancelled: bool,
+    /// Import batch the prompts were recorded under, unless cancelled
+    pub batch_id: Option<i64>,
+    /// When the same file was imported before, if it was
+    pub previous_import: Option<String>,
+}
+
+/// Progress counters shared with the import thread
+#[derive(Default)]
+pub struct ImportProgress {
+    pub bytes_read: AtomicU64,
+    pub records: AtomicUsize,
+    pub added: AtomicUsize,
+}
+
+/// A running background import
+pub struct ImportHandle {
+    pub file_name: String,
+    pub total_bytes: u64,
+    pub progress: Arc<ImportProgress>,
+    cancel: Arc<AtomicBool>,
+    result: Receiver<Result<ImportSummary>>,
+}
+
+impl ImportHandle {
+    /// Stop the import and roll back everything inserted so far
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelling(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed)
+    }
+
+    /// The final result once the thread is done
+    pub fn poll(&self) -> Option<Result<ImportSummary>> {
+        match self.result.try_recv() {
+            Ok(result) => Some(result),
+            Err(mpsc::TryRecvError::Empty) => None,
+            Err(mpsc::TryRecvError::Disconnected) => Some(Err(Error::ImportStopped)),
+        }
+    }
+}
+
+/// Stream `job.path` into the database on a background thread.
+/// The whole file is imported in one transaction, so a cancel or error leaves the database untouched.
+pub fn spawn_import(job: ImportJob) -> Result<ImportHandle> {
+    let file = File::open(&job.path).map_err(Error::Read)?;
+    let total_bytes = file.metadata().map_err(Error::Read)?.len();
+    let progress = Arc::new(ImportProgress::default());
+    let cancel = Arc::new(AtomicBool::new(false));
+    let (sender, result) = mpsc::channel();
+
+    let handle = ImportHandle {
+        file_name: job.file_name.clone(),
+        total_bytes,
+        progress: Arc::clone(&progress),
+        cancel: Arc::clone(&cancel),
+        result,
+    };
+
+    thread::spawn(move || {
+        let outcome = run_import(file, &job, &progress, &cancel);
+        let _ = sender.send(outcome);
+    });
+
+    Ok(handle)
+}
+
+/// Import on the calling thread, for the command line
+pub fn import_blocking(job: &ImportJob) -> Result<ImportSummary> {
+    let file = File::open(&job.path).map_err(Error::Read)?;
+    run_import(file, job, &ImportProgress::default(), &AtomicBool::new(false))
+}
+
+fn run_import(
+    file: File,
+    job: &ImportJob,
+    progress: &ImportProgress,
+    cancel: &AtomicBool,
+) -> Result<ImportSummary> {
+    let mut lines = LineReader::new(file, job.encoding).map_err(Error::Read)?;
+    let mut splitter = RecordSplitter::new(&job.options).map_err(Error::InvalidPattern)?;
+    let mut db = Database::open(&job.db_path)?;
+    let rules = if job.options.apply_rules {
+        RuleSet::new(db.tag_rules()?).map_err(Error::InvalidPattern)?
+    } else {
+        RuleSet::default()
+    };
+    let mut batch = db.begin_batch_insert(&job.file_name)?;
+    batch.set_tags(&job.options.tags)?;
+    batch.set_rules(rules);
+
+    let mut summary = ImportSummary {
+        file_name: job.file_name.clone(),
+        total: 0,
+        added: 0,
+        invalid_lines: Vec::new(),
+        cancelled: false,
+        batch_id: None,
+        previous_import: None,
+    };
+    let mut next_report = BATCH_SIZE;
+
+    loop {
+        let line = lines.next_line().map_err(Error::Read)?;
+        let records = match &line {
+            Some(line) => {
+                splitter.push_line(line);
+                splitter.take_records()
+            }
+            None => splitter.finish(),
+        };
+
+        for record in records {
+            summary.total += 1;
+            if batch.insert(&record.text, Some(record.line), &record.metadata)? {
+                summary.added += 1;
+            }
+        }
+
+        if summary.total >= next_report || line.is_none() {
+            next_report = summary.total + BATCH_SIZE;
+            progress.bytes_read.store(lines.bytes_read(), Ordering::Relaxed);
+            progress.records.store(summary.total, Ordering::Relaxed);
+            progress.added.store(summary.added, Ordering::Relaxed);
+        }
+
+        if cancel.load(Ordering::Relaxed) {
+            // Dropping the batch rolls the transaction back
+            summary.added = 0;
+            summary.cancelled = true;
+            break;
+        }
+
+        if line.is_none() {
+            summary.batch_id = Some(batch.batch_id());
+            summary.previous_import = batch.commit(Some(&lines.hash()))?;
+            break;
+        }
+    }
+
+    summary.invalid_lines = lines.invalid_lines().to_vec();
+    Ok(summary)
+}
+
+/// Valid UTF-8, allowing a multi-byte character to be cut off at the end of the sample
+fn is_utf8_prefix(bytes: &[u8]) -> bool {
+    match std::str::from_utf8(bytes) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none(),
+    }
+}
+
+fn sniff_encoding(bytes: &[u8]) -> &'static Encoding {
+    // ASCII-heavy UTF-16 text has a zero in most odd (LE) or even (BE) bytes,
+    // while the other half only has the odd zero from characters like U+0100
+    let sample = &bytes[..bytes.len().min(4096)];
+    let evens = sample.len().div_ceil(2);
+    let odds = sample.len() / 2;
+    let even_zeros = sample.iter().step_by(2).filter(|&&b| b == 0).count();
+    let odd_zeros = sample.iter().skip(1).step_by(2).filter(|&&b| b == 0).count();
+    let mostly_zeros = |zeros: usize, total: usize| total > 0 && zeros * 10 > total * 7;
+    let few_zeros = |zeros: usize, total: usize| zeros * 10 < total;
+
+    if mostly_zeros(odd_zeros, odds) && few_zeros(even_zeros, evens) {
+        UTF_16LE
+    } else if mostly_zeros(even_zeros, evens) && few_zeros(odd_zeros, odds) {
+        UTF_16BE
+    } else if is_utf8_prefix(bytes) {
+        UTF_8
+    } else {
+        WINDOWS_1252
+    }
+}
+
+/// How a text file is split into prompt records
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImportMode {
+    OnePerLine,
+    BlankLineSeparated,
+    Delimiter,
+    Regex,
+    PositiveNegative,
+}
+
+impl ImportMode {
+    pub const ALL: [ImportMode; 5] = [
+        ImportMode::OnePerLine,
+        ImportMode::BlankLineSeparated,
+        ImportMode::Delimiter,
+        ImportMode::Regex,
+        ImportMode::PositiveNegative,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ImportMode::OnePerLine => "One per line",
+            ImportMode::BlankLineSeparated => "Blank-line separated",
+            ImportMode::Delimiter => "Custom delimiter",
+            ImportMode::Regex => "Regex separator",
+            ImportMode::PositiveNegative => "Positive/Negative prompt blocks",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ImportOptions {
+    pub mode: ImportMode,
+    /// Literal separator for `ImportMode::Delimiter`, e.g. `---`
+    pub delimiter: String,
+    /// Separator pattern for `ImportMode::Regex`, matched against each line
+    pub pattern: String,
+    /// Join the lines of a multi-line record with spaces instead of newlines
+    pub join_lines: bool,
+    /// Tags added to every prompt in the file, including ones already in the library
+    pub tags: Vec<String>,
+    /// Also tag each prompt with the library's tag rules
+    pub apply_rules: bool,
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        Self {
+            mode: ImportMode::OnePerLine,
+            delimiter: "---".to_string(),
+            // Numbered headings like "1." "2)" or "#3"
+            pattern: r"^\s*(?:#\s*\d+|\d+[.)])\s*".to_string(),
+            join_lines: true,
+            tags: Vec::new(),
+            apply_rules: true,
+        }
+    }
+}
+
+/// One prompt parsed out of an import file
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsedRecord {
+    pub text: String,
+    /// 1-based line of the file the record starts on
+    pub line: usize,
+    /// Negative prompt and settings read from Positive/Negative blocks
+    pub metadata: PromptMetadata,
+}
+
+/// Split file contents into prompt records according to `options`.
+/// Only fails when the regex pattern is invalid.
+pub fn parse_records(contents: &str, options: &ImportOptions) -> Result<Vec<ParsedRecord>, regex::Error> {
+    let mut splitter = RecordSplitter::new(options)?;
+    for line in contents.lines() {
+        splitter.push_line(line);
+    }
+    Ok(splitter.finish())
+}
+
+#[derive(PartialEq)]
+enum Section {
+    Positive,
+    Negative,
+    Parameters,
+}
+
+/// Assembles records from a file fed one line at a time, so large files can be
+/// split without holding them in memory. Delimiters and regex separators are
+/// matched within a single line.
+///
+/// Positive/Negative mode reads A1111-style blocks:
+///
+/// ```text
+/// Positive prompt: a castle at dusk
+/// Negative prompt: blurry
+/// Steps: 20, Sampler: Euler a, CFG scale: 7, Seed: 42, Model: sd_xl_base
+/// ```
+///
+/// Blocks are separated by blank lines, by the next "Positive prompt:" line,
+/// or end with their parameters line when the next prompt follows directly.
+/// The "Positive prompt:" label is optional.
+pub struct RecordSplitter {
+    mode: ImportMode,
+    delimiter: String,
+    regex: Option<Regex>,
+    join_lines: bool,
+    positive: String,
+    negative: String,
+    /// Settings from the parameters line of the current block
+    settings: PromptMetadata,
+    section: Section,
+    /// Lines pushed so far
+    line_number: usize,
+    /// Line the current record started on
+    record_line: usize,
+    records: Vec<ParsedRecord>,
+}
+
+impl RecordSplitter {
+    pub fn new(options: &ImportOptions) -> Result<Self, regex::Error> {
+        let regex = match options.mode {
+            ImportMode::Regex => Some(Regex::new(&options.pattern)?),
+            _ => None,
+        };
+        Ok(Self {
+            mode: options.mode,
+            delimiter: options.delimiter.clone(),
+            regex,
+            join_lines: options.join_lines,
+            positive: String::new(),
+            negative: String::new(),
+            settings: PromptMetadata::default(),
+            section: Section::Positive,
+            line_number: 0,
+            record_line: 0,
+            records: Vec::new(),
+        })
+    }
+
+    pub fn push_line(&mut self, line: &str) {
+        self.line_number += 1;
+        match self.mode {
+            ImportMode::OnePerLine => {
+                self.append(line);
+                self.flush();
+            }
+            ImportMode::BlankLineSeparated => {
+                if line.trim().is_empty() {
+                    self.flush();
+                } else {
+                    self.append(line);
+                }
+            }
+            ImportMode::Delimiter if !self.delimiter.is_empty() => {
+                let parts: Vec<String> = line.split(self.delimiter.as_str()).map(str::to_string).collect();
+                self.push_parts(parts);
+            }
+            ImportMode::Delimiter => self.append(line),
+            ImportMode::Regex => {
+                let parts: Vec<String> = match &self.regex {
+                    Some(re) => re.split(line).map(str::to_string).collect(),
+                    None => vec![line.to_string()],
+                };
+                self.push_parts(parts);
+            }
+            ImportMode::PositiveNegative => self.push_block_line(line),
+        }
+    }
+
+    /// Records completed so far, leaving any partial record in place
+    pub fn take_records(&mut self) -> Vec<ParsedRecord> {
+        std::mem::take(&mut self.records)
+    }
+
+    /// Flush the last partial record and return everything not yet taken
+    pub fn finish(&mut self) -> Vec<ParsedRecord> {
+        self.flush();
+        self.take_records()
+    }
+
+    /// The first part continues the current record, every later part starts a new one
+    fn push_parts(&mut self, parts: Vec<String>) {
+        for (i, part) in parts.iter().enumerate() {
+            if i > 0 {
+                self.flush();
+            }
+            self.append(part);
+        }
+    }
+
+    fn append(&mut self, text: &str) {
+        if self.positive.trim().is_empty() && self.section == Section::Positive {
+            self.record_line = self.line_number;
+        }
+        let target = match self.section {
+            Section::Positive => &mut self.positive,
+            Section::Negative => &mut self.negative,
+            Section::Parameters => return,
+        };
+        target.push_str(text);
+        target.push('\n');
+    }
+
+    fn push_block_line(&mut self, line: &str) {
+        let trimmed = line.trim();
+
+        if trimmed.is_empty() {
+            self.flush();
+        } else if let Some(rest) = strip_prefix_ignore_case(trimmed, "positive prompt:") {
+            self.flush();
+            self.append(rest);
+        } else if let Some(rest) = strip_prefix_ignore_case(trimmed, "negative prompt:") {
+            self.section = Section::Negative;
+            self.append(rest);
+        } else if strip_prefix_ignore_case(trimmed, "steps:").is_some() {
+            self.section = Section::Parameters;
+            self.settings.read_settings(trimmed);
+        } else {
+            // The parameters line ends a block, so back-to-back infotext
+            // carries on with the next prompt
+            if self.section == Section::Parameters {
+                self.flush();
+            }
+            self.append(trimmed);
+        }
+    }
+
+    fn flush(&mut self) {
+        let text = clean_record(&self.positive, self.join_lines);
+        let negative = clean_record(&self.negative, self.join_lines);
+        let mut metadata = std::mem::take(&mut self.settings);
+        if !text.is_empty() {
+            metadata.negative_prompt = (!negative.is_empty()).then_some(negative);
+            self.records.push(ParsedRecord { text, line: self.record_line, metadata });
+        }
+        self.positive.clear();
+        self.negative.clear();
+        self.section = Section::Positive;
+    }
+}
+
+/// Trim every line of a record and drop empty ones
+fn clean_record(chunk: &str, join_lines: bool) -> String {
+    let lines: Vec<&str> = chunk
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect();
+    lines.join(if join_lines { " " } else { "\n" })
+}
+
+fn strip_prefix_ignore_case<'a>(line: &'a str, prefix: &str) -> Option<&'a str> {
+    let head = line.get(..prefix.len())?;
+    if head.eq_ignore_ascii_case(prefix) {
+        Some(&line[prefix.len()..])
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16(text: &str, big_endian: bool, bom: bool) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        if bom {
+            bytes.extend(if big_endian { [0xFE, 0xFF] } else { [0xFF, 0xFE] });
+        }
+        for unit in text.encode_utf16() {
+            bytes.extend(if big_endian { unit.to_be_bytes() } else { unit.to_le_bytes() });
+        }
+        bytes
+    }
+
+    #[test]
+    fn decodes_utf16_with_and_without_bom() {
+        let text = "a red cat, smile\nblue eyes, night sky\n";
+        for big_endian in [false, true] {
+            for bom in [false, true] {
+                let decoded = decode(&utf16(text, big_endian, bom), TextEncoding::Auto);
+                assert_eq!(decoded.text, text, "big endian {big_endian}, bom {bom}");
+                assert_eq!(decoded.encoding, if big_endian { "UTF-16BE" } else { "UTF-16LE" });
+                assert!(decoded.invalid_lines.is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn sniffs_utf16_with_stray_zero_bytes() {
+        // U+0100 and U+2000 put a zero in the byte that is otherwise never zero
+        let text = "Āmazing cat\u{2000}smile, blue eyes, night sky, red hair\n";
+        assert_eq!(decode(&utf16(text, false, false), TextEncoding::Auto).text, text);
+        assert_eq!(decode(&utf16(text, true, false), TextEncoding::Auto).text, text);
+    }
+
+    #[test]
+    fn decodes_windows_1252_and_utf8() {
+        let decoded = decode(b"caf\xe9 au lait\n\x93quoted\x94\n", TextEncoding::Auto);
+        assert_eq!(decoded.encoding, "windows-1252");
+        assert_eq!(decoded.text, "café au lait\n\u{201c}quoted\u{201d}\n");
+
+        let decoded = decode("café au lait\n".as_bytes(), TextEncoding::Auto);
+        assert_eq!(decoded.encoding, "UTF-8");
+        assert_eq!(decoded.text, "café au lait\n");
+    }
+
+    #[test]
+    fn reports_invalid_lines() {
+        let decoded = decode(b"fine\nbad \xff\nfine\nbad \xfe\n", TextEncoding::Utf8);
+        assert_eq!(decoded.invalid_lines, vec![2, 4]);
+
+        let mut reader = LineReader::new(&b"fine\nbad \xff\nfine\nbad \xfe"[..], TextEncoding::Utf8).unwrap();
+        let mut lines = Vec::new();
+        while let Some(line) = reader.next_line().unwrap() {
+            lines.push(line);
+        }
+        assert_eq!(lines.len(), 4);
+        assert_eq!(reader.invalid_lines(), [2, 4]);
+    }
+
+    fn split(contents: &str, options: ImportOptions) -> Vec<(String, usize)> {
+        parse_records(contents, &options).unwrap()
+            .into_iter()
+            .map(|record| (record.text, record.line))
+            .collect()
+    }
+
+    fn records(list: &[(&str, usize)]) -> Vec<(String, usize)> {
+        list.iter().map(|&(text, line)| (text.to_string(), line)).collect()
+    }
+
+    #[test]
+    fn splits_one_per_line_skipping_blanks() {
+        let parsed = split("a red cat\n\n  a blue dog  \n", ImportOptions::default());
+        assert_eq!(parsed, records(&[("a red cat", 1), ("a blue dog", 3)]));
+    }
+
+    #[test]
+    fn splits_on_blank_lines_joining_or_keeping_lines() {
+        let contents = "\na red cat,\nsitting\n\n\na blue dog\n";
+        let options = ImportOptions { mode: ImportMode::BlankLineSeparated, ..Default::default() };
+        assert_eq!(split(contents, options.clone()), records(&[("a red cat, sitting", 2), ("a blue dog", 6)]));
+
+        let options = ImportOptions { join_lines: false, ..options };
+        assert_eq!(split(contents, options), records(&[("a red cat,\nsitting", 2), ("a blue dog", 6)]));
+    }
+
+    #[test]
+    fn splits_on_delimiters_within_and_across_lines() {
+        let options = ImportOptions { mode: ImportMode::Delimiter, delimiter: "---".to_string(), ..Default::default() };
+        let parsed = split("a red cat --- a blue\ndog\n---\na green frog", options);
+        assert_eq!(parsed, records(&[("a red cat", 1), ("a blue dog", 1), ("a green frog", 4)]));
+    }
+
+    #[test]
+    fn splits_on_numbered_headings() {
+        let options = ImportOptions { mode: ImportMode::Regex, ..Default::default() };
+        let parsed = split("1. a red cat\n2) a blue\ndog\n#3 a green frog", options);
+        assert_eq!(parsed, records(&[("a red cat", 1), ("a blue dog", 2), ("a green frog", 4)]));
+
+        let options = ImportOptions { mode: ImportMode::Regex, pattern: "(".to_string(), ..Default::default() };
+        assert!(RecordSplitter::new(&options).is_err());
+    }
+
+    #[test]
+    fn reads_positive_negative_blocks() {
+        let contents = "Positive prompt: a castle at dusk\n\
+            Negative prompt: blurry,\nlow quality\n\
+            Steps: 20, Sampler: Euler a, CFG scale: 7.5, Seed: 42, Model: sd_xl_base\n\
+            positive prompt: a quiet lake\n\
+            \n\
+            a forest path\n";
+        let options = ImportOptions { mode: ImportMode::PositiveNegative, ..Default::default() };
+        let parsed = parse_records(contents, &options).unwrap();
+        assert_eq!(parsed.len(), 3);
+
+        assert_eq!((parsed[0].text.as_str(), parsed[0].line), ("a castle at dusk", 1));
+        let metadata = &parsed[0].metadata;
+        assert_eq!(metadata.negative_prompt.as_deref(), Some("blurry, low quality"));
+        assert_eq!(metadata.steps, Some(20));
+        assert_eq!(metadata.sampler.as_deref(), Some("Euler a"));
+        assert_eq!(metadata.cfg_scale, Some(7.5));
+        assert_eq!(metadata.seed, Some(42));
+        assert_eq!(metadata.model.as_deref(), Some("sd_xl_base"));
+
+        assert_eq!((parsed[1].text.as_str(), parsed[1].line), ("a quiet lake", 5));
+        assert_eq!(parsed[1].metadata, PromptMetadata::default());
+        assert_eq!((parsed[2].text.as_str(), parsed[2].line), ("a forest path", 7));
+    }
+
+    #[test]
+    fn reads_back_to_back_blocks() {
+        let contents = "a castle at dusk\n\
+            Negative prompt: blurry\n\
+            Steps: 20, Seed: 1\n\
+            a quiet lake\n\
+            at dawn\n\
+            Steps: 30, Seed: 2\n\
+            a forest path\n";
+        let options = ImportOptions { mode: ImportMode::PositiveNegative, ..Default::default() };
+        let parsed = parse_records(contents, &options).unwrap();
+        let summary: Vec<(&str, usize, Option<i64>, Option<&str>)> = parsed.iter()
+            .map(|r| (r.text.as_str(), r.line, r.metadata.seed, r.metadata.negative_prompt.as_deref()))
+            .collect();
+        assert_eq!(summary, [
+            ("a castle at dusk", 1, Some(1), Some("blurry")),
+            ("a quiet lake at dawn", 4, Some(2), None),
+            ("a forest path", 7, None, None),
+        ]);
+    }
+
+    #[test]
+    fn take_records_leaves_the_partial_record() {
+        let options = ImportOptions { mode: ImportMode::BlankLineSeparated, ..Default::default() };
+        let mut splitter = RecordSplitter::new(&options).unwrap();
+        for line in ["a red cat", "", "a blue"] {
+            splitter.push_line(line);
+        }
+        assert_eq!(splitter.take_records().len(), 1);
+        splitter.push_line("dog");
+        let rest = splitter.finish();
+        assert_eq!(rest.len(), 1);
+        assert_eq!((rest[0].text.as_str(), rest[0].line), ("a blue dog", 3));
+    }
+}
@@ -0,0 +1,28 @@
+//! Core of Prompt Deduplicator: a SQLite-backed library of image generation
+//! prompts with import, normalization, similarity scanning and export.
+//!
+//! [`Library`] is the entry point. The lower-level modules are public for
+//! tools that need more control than it offers.
+
+pub mod db;
+mod error;
+pub mod export;
+pub mod import;
+mod library;
+mod migrations;
+pub mod normalize;
+pub mod report;
+pub mod rules;
+pub mod similarity;
+pub mod syntax;
+pub mod wildcards;
+
+pub use db::{Database, Facet, Prompt, PromptFilter, PromptMetadata, PromptSort, TagMatch};
+pub use error::{Error, Result};
+pub use export::{ExportColumn, ExportFormat};
+pub use import::{ImportMode, ImportOptions, ImportSummary, TextEncoding};
+pub use library::{Library, ReplaceSummary, ResolveSummary, WildcardFolderImport, data_dir, default_library_path};
+pub use migrations::{SCHEMA_VERSION, UpgradeReport};
+pub use report::{Action, Decision};
+pub use rules::{CategorizeReport, RuleKind, TagRule};
+pub use similarity::SimilarPair;
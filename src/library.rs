@@ -0,0 +1,369 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::db::{
+    Database, EditReason, Prompt, PromptFilter, RecoveryReport, Replacement, UndoImportSummary, WildcardImportSummary, parse_tags,
+};
+use crate::error::{Error, Result};
+use crate::export::{self, ExportColumn, ExportFormat, ExportMetadata};
+use crate::import::{self, ImportHandle, ImportJob, ImportOptions, ImportSummary, TextEncoding};
+use crate::report::{Action, Decision};
+use crate::rules::{self, CategorizeReport, RuleKind, RuleSet};
+use crate::similarity::{self, SimilarPair};
+use crate::wildcards;
+
+/// A prompt library: one SQLite database and the operations the app and the
+/// command line share.
+///
+/// ```no_run
+/// use prompt_dedup::{Library, ImportOptions, TextEncoding};
+///
+/// let mut library = Library::open("prompts.db")?;
+/// library.import_file("prompts.txt".as_ref(), TextEncoding::Auto, &ImportOptions::default())?;
+/// let pairs = library.scan(0.8, false)?;
+/// let outcome = library.resolve(&pairs.iter().map(prompt_dedup::Decision::keep_first).collect::<Vec<_>>())?;
+/// println!("removed {} near-duplicates", outcome.deleted.len());
+/// # Ok::<(), prompt_dedup::Error>(())
+/// ```
+pub struct Library {
+    db: Database,
+}
+
+/// Result of importing a folder of wildcard files
+#[derive(Debug, Clone, Default)]
+pub struct WildcardFolderImport {
+    pub summary: WildcardImportSummary,
+    /// Files that couldn't be read or parsed, as `path: reason`
+    pub errors: Vec<String>,
+}
+
+/// Result of applying dedup decisions
+#[derive(Debug, Clone, Default)]
+pub struct ResolveSummary {
+    /// Prompts that were deleted
+    pub deleted: Vec<i64>,
+    /// Pairs where both prompts were kept
+    pub kept: usize,
+    /// Decisions skipped because the prompt was deleted or edited since
+    pub stale: usize,
+}
+
+/// Result of a Find & Replace run
+#[derive(Debug, Clone, Default)]
+pub struct ReplaceSummary {
+    /// Prompts that were (or, on a dry run, would be) changed
+    pub changed: Vec<Replacement>,
+    /// Prompts left alone because the new text already exists
+    pub collisions: Vec<i64>,
+}
+
+/// Per-user folder for app data: `~/Library/Application Support/prompt-dedup`
+/// on macOS, `%APPDATA%\prompt-dedup` on Windows, `~/.local/share/prompt-dedup` on Linux
+pub fn data_dir() -> PathBuf {
+    dirs::data_dir()
+        .map(|dir| dir.join("prompt-dedup"))
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// Library used when no other one has been opened
+pub fn default_library_path() -> PathBuf {
+    data_dir().join("prompts.db")
+}
+
+impl Library {
+    /// Open or create the library stored at `path`, migrating older databases.
+    /// Missing parent folders are created.
+    pub fn open(path: impl AsRef<Path>) -> Result<Library> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent()
+            && !parent.as_os_str().is_empty()
+        {
+            // A failure here shows up as a clearer error from opening the database
+            let _ = fs::create_dir_all(parent);
+        }
+        Ok(Library { db: Database::open(&path.to_string_lossy())? })
+    }
+
+    /// Open an existing library without changing it. Anything that writes
+    /// fails; check `is_read_only` before offering edits.
+    pub fn open_read_only(path: impl AsRef<Path>) -> Result<Library> {
+        let db = Database::open_read_only(&path.as_ref().to_string_lossy())?;
+        if db.needs_upgrade()? {
+            return Err(Error::NeedsUpgrade);
+        }
+        Ok(Library { db })
+    }
+
+    /// Schema version of the library at `path` when opening it writable would
+    /// upgrade it; `None` when it is current, newer or doesn't exist yet
+    pub fn pending_upgrade(path: impl AsRef<Path>) -> Result<Option<i64>> {
+        let path = path.as_ref();
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let db = match Database::open_read_only(&path.to_string_lossy()) {
+            Ok(db) => db,
+            // Left for `open` to refuse
+            Err(Error::NewerSchema { .. }) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        Ok(if !db.is_blank()? && db.needs_upgrade()? { Some(db.schema_version()?) } else { None })
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.db.is_read_only()
+    }
+
+    /// Problems `PRAGMA integrity_check` finds in the file at `path`; empty when healthy
+    pub fn check_integrity(path: impl AsRef<Path>) -> Result<Vec<String>> {
+        Ok(Database::integrity_check(&path.as_ref().to_string_lossy())?)
+    }
+
+    /// Copy whatever can still be read from a damaged library at `source`
+    /// into a new library at `target`, which must not exist yet
+    pub fn recover(source: impl AsRef<Path>, target: impl AsRef<Path>) -> Result<RecoveryReport> {
+        let target = target.as_ref();
+        if target.exists() {
+            return Err(Error::AlreadyExists(target.to_path_buf()));
+        }
+        Database::recover(&source.as_ref().to_string_lossy(), &target.to_string_lossy())
+    }
+
+    /// The underlying database, for operations not covered here
+    pub fn db(&self) -> &Database {
+        &self.db
+    }
+
+    pub fn db_mut(&mut self) -> &mut Database {
+        &mut self.db
+    }
+
+    /// File the library was opened from
+    pub fn path(&self) -> Option<&str> {
+        self.db.path()
+    }
+
+    pub fn count(&self) -> Result<i64> {
+        Ok(self.db.count()?)
+    }
+
+    fn import_job(&self, path: &Path, encoding: TextEncoding, options: &ImportOptions) -> Result<ImportJob> {
+        let db_path = self.path().ok_or(Error::NoDatabaseFile)?.to_string();
+        let file_name = path.file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| path.to_string_lossy().to_string());
+        Ok(ImportJob {
+            db_path,
+            path: path.to_path_buf(),
+            file_name,
+            encoding,
+            options: options.clone(),
+        })
+    }
+
+    /// Import a text file on the calling thread. Prompts that are already in
+    /// the library (after normalization) are skipped.
+    pub fn import_file(&mut self, path: &Path, encoding: TextEncoding, options: &ImportOptions) -> Result<ImportSummary> {
+        import::import_blocking(&self.import_job(path, encoding, options)?)
+    }
+
+    /// Import a text file on a background thread, see `ImportHandle::poll`
+    pub fn spawn_import(&self, path: &Path, encoding: TextEncoding, options: &ImportOptions) -> Result<ImportHandle> {
+        import::spawn_import(self.import_job(path, encoding, options)?)
+    }
+
+    /// Import every wildcard file under `folder`
+    pub fn import_wildcards(&mut self, folder: &Path) -> Result<WildcardFolderImport> {
+        let (sets, errors) = wildcards::read_folder(folder).map_err(Error::Read)?;
+        let summary = if sets.is_empty() {
+            WildcardImportSummary::default()
+        } else {
+            let name = folder.file_name().unwrap_or(folder.as_os_str()).to_string_lossy();
+            self.db.import_wildcards(&sets, &name)?
+        };
+        Ok(WildcardFolderImport { summary, errors })
+    }
+
+    /// Remove the prompts added by import batch `batch_id`, keeping any edited
+    /// since or found again by another import
+    pub fn undo_import(&mut self, batch_id: i64) -> Result<UndoImportSummary> {
+        self.db.undo_import(batch_id)?.ok_or(Error::NoSuchImport(batch_id))
+    }
+
+    /// Change a prompt's text by hand. Fails with `DuplicateText` when another
+    /// prompt already has that text, which `merge_prompt` can then fold it into.
+    pub fn edit_prompt(&mut self, id: i64, text: &str) -> Result<()> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Err(Error::EmptyText);
+        }
+        if let Some(existing) = self.db.find_duplicate(text, id)? {
+            return Err(Error::DuplicateText(existing.id));
+        }
+        self.db.update_prompt(id, text, &EditReason::Manual)?;
+        Ok(())
+    }
+
+    /// Delete prompt `from` in favour of prompt `into`, which takes over its
+    /// tags, collections, sources and any metadata it lacks, and records its text as merged
+    pub fn merge_prompt(&mut self, from: i64, into: i64) -> Result<()> {
+        Ok(self.db.merge_prompt(from, into)?)
+    }
+
+    /// Set a prompt's text back to what it was before version `version_id`,
+    /// unless another prompt has that text now. Returns the prompt's id.
+    pub fn restore_version(&mut self, version_id: i64) -> Result<i64> {
+        let (id, text) = self.db.version_text(version_id)?.ok_or(Error::NoSuchVersion(version_id))?;
+        if let Some(existing) = self.db.find_duplicate(&text, id)? {
+            return Err(Error::DuplicateText(existing.id));
+        }
+        self.db.update_prompt(id, &text, &EditReason::Restore)?;
+        Ok(id)
+    }
+
+    /// Prompts containing `query`, or every prompt when it is empty
+    pub fn search(&self, query: &str) -> Result<Vec<Prompt>> {
+        if query.is_empty() {
+            Ok(self.db.get_all()?)
+        } else {
+            Ok(self.db.search(query)?)
+        }
+    }
+
+    /// Prompts matching `filter`, see `Database::query`
+    pub fn query(&self, filter: &PromptFilter) -> Result<Vec<Prompt>> {
+        Ok(self.db.query(filter)?)
+    }
+
+    /// Pairs of prompts at least `threshold` similar, most similar first. With
+    /// `compare_negatives` their negative prompts must be that similar too.
+    pub fn scan(&self, threshold: f32, compare_negatives: bool) -> Result<Vec<SimilarPair>> {
+        let all = self.db.get_all()?;
+        let prompts: Vec<(i64, String)> = all.iter().map(|p| (p.id, p.text.clone())).collect();
+        let pairs = similarity::find_similar_pairs(&prompts, threshold);
+        if !compare_negatives {
+            return Ok(pairs);
+        }
+        let negatives: HashMap<i64, String> = all.into_iter()
+            .map(|p| (p.id, p.metadata.negative_prompt.unwrap_or_default()))
+            .collect();
+        Ok(similarity::with_similar_negatives(pairs, &negatives, threshold))
+    }
+
+    /// Apply dedup decisions. A decision whose prompt was deleted, or whose
+    /// text no longer matches the text recorded with the decision, is skipped.
+    pub fn resolve(&mut self, decisions: &[Decision]) -> Result<ResolveSummary> {
+        let mut summary = ResolveSummary::default();
+        let mut deleted = HashSet::new();
+        for decision in decisions {
+            let (delete_id, kept_id, expected_text) = match decision.action {
+                Action::KeepBoth => {
+                    summary.kept += 1;
+                    continue;
+                }
+                Action::DeleteA => (decision.id_a, decision.id_b, &decision.text_a),
+                Action::DeleteB => (decision.id_b, decision.id_a, &decision.text_b),
+            };
+            if deleted.contains(&delete_id) {
+                continue;
+            }
+
+            let Some(prompt) = self.db.get_prompt(delete_id)? else {
+                summary.stale += 1;
+                continue;
+            };
+            if expected_text.as_ref().is_some_and(|text| *text != prompt.text) {
+                summary.stale += 1;
+                continue;
+            }
+
+            // The prompt kept takes over the sources of its duplicate, unless
+            // it was itself deleted by an earlier decision
+            if deleted.contains(&kept_id) {
+                self.db.delete_prompt(delete_id)?;
+            } else {
+                self.db.delete_duplicate(delete_id, kept_id)?;
+            }
+            deleted.insert(delete_id);
+            summary.deleted.push(delete_id);
+        }
+        Ok(summary)
+    }
+
+    /// Write `prompts` to `path`. Tags, collections, wildcards and cluster ids
+    /// are filled in when those columns are picked; clusters come from `pairs`.
+    pub fn export(
+        &self,
+        prompts: &[Prompt],
+        path: &Path,
+        format: ExportFormat,
+        columns: &[ExportColumn],
+        pairs: &[SimilarPair],
+    ) -> Result<usize> {
+        let mut metadata = ExportMetadata::default();
+        if columns.contains(&ExportColumn::Tags) {
+            metadata.tags = self.db.tag_memberships()?;
+        }
+        if columns.contains(&ExportColumn::Collections) {
+            metadata.collections = self.db.collection_memberships()?;
+        }
+        if columns.contains(&ExportColumn::Wildcards) {
+            metadata.wildcards = self.db.wildcard_memberships()?;
+        }
+        if columns.contains(&ExportColumn::ClusterId) {
+            metadata.clusters = similarity::clusters_from_pairs(pairs);
+        }
+        export::export_to_file(prompts, path, format, columns, &metadata).map_err(Error::Write)
+    }
+
+    /// Store a rule that tags prompts matching `pattern` with `tag`. The
+    /// pattern is checked first, so invalid rules never reach the library.
+    pub fn add_tag_rule(&self, kind: RuleKind, pattern: &str, tag: &str) -> Result<i64> {
+        let [tag] = parse_tags(tag).try_into().map_err(|_| Error::InvalidTag(tag.to_string()))?;
+        rules::compile(kind, pattern).map_err(Error::InvalidPattern)?;
+        Ok(self.db.add_tag_rule(kind, pattern.trim(), &tag)?)
+    }
+
+    /// Run the enabled tag rules over the prompts matching `filter`, adding
+    /// the tags they give. With `dry_run` nothing is written.
+    pub fn categorize(&mut self, filter: &PromptFilter, dry_run: bool) -> Result<CategorizeReport> {
+        let rules = RuleSet::new(self.db.tag_rules()?).map_err(Error::InvalidPattern)?;
+        let prompts = self.db.query(filter)?;
+        let existing = self.db.tag_memberships()?;
+        let mut report = rules::categorize(&rules, &prompts, &existing);
+        report.dry_run = dry_run;
+        if !dry_run {
+            self.db.add_tags(&report.assignments)?;
+        }
+        Ok(report)
+    }
+
+    /// Replace `find` in every prompt. With `dry_run` nothing is written.
+    /// Each change is kept in the prompt's history with the rule as the reason.
+    pub fn replace(&mut self, find: &str, replace: &str, case_sensitive: bool, dry_run: bool) -> Result<ReplaceSummary> {
+        let mut summary = ReplaceSummary {
+            changed: self.db.preview_replace(find, replace, case_sensitive)?,
+            collisions: Vec::new(),
+        };
+        if dry_run {
+            return Ok(summary);
+        }
+
+        let reason = EditReason::replace(find, replace);
+        let mut changed = Vec::with_capacity(summary.changed.len());
+        for change in summary.changed {
+            match self.db.update_prompt(change.id, &change.replaced, &reason) {
+                Ok(_) => changed.push(change),
+                // Edits that would turn a prompt into an existing one are left alone
+                Err(rusqlite::Error::SqliteFailure(e, _)) if e.code == rusqlite::ErrorCode::ConstraintViolation => {
+                    summary.collisions.push(change.id);
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+        summary.changed = changed;
+        Ok(summary)
+    }
+}
+
@@ -1,16 +1,32 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 use eframe::egui::{self, Color32, Visuals, Stroke};
-use std::fs;
-use std::path::PathBuf;
-use std::time::Instant;
-
-mod db;
-mod export;
-mod similarity;
-
-use db::{Database, Prompt};
-use similarity::SimilarPair;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+mod browse;
+mod cli;
+mod config;
+
+use prompt_dedup::{Library, db, export, import, report, similarity};
+use db::{
+    CollectionInfo, ImportBatch, NormalizeReport, Prompt, PromptFilter, PromptMetadata, PromptSort, PromptSource, PromptVersion,
+    Replacement, TagInfo, TagMatch, WildcardInfo,
+};
+use export::{ExportColumn, ExportFilter, ExportFormat, ExportSource, Keeper, StyleEntry, StylePlaceholder};
+use import::{DecodedText, ImportHandle, ImportMode, ImportOptions, ImportSummary, ParsedRecord, TextEncoding};
+use prompt_dedup::normalize::NormalizeOptions;
+use report::ReportFormat;
+use browse::BrowseList;
+use config::AppConfig;
+use prompt_dedup::{Action, Decision, WildcardFolderImport};
+use prompt_dedup::rules::{CategorizeReport, RuleKind, TagRule};
+use similarity::{DiffOp, SimilarPair};
+
+/// Maximum number of parsed records listed in the import preview
+const IMPORT_PREVIEW_LIMIT: usize = 200;
 
 // Catppuccin Macchiato colors
 mod colors {
@@ -67,6 +83,12 @@ fn load_icon() -> egui::IconData {
 }
 
 fn main() -> eframe::Result<()> {
+    // Any argument means a headless command, see `cli::run`
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        std::process::exit(cli::run(&args));
+    }
+
     let icon = load_icon();
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
@@ -80,7 +102,7 @@ fn main() -> eframe::Result<()> {
         options,
         Box::new(|cc| {
             setup_catppuccin_theme(&cc.egui_ctx);
-            Ok(Box::new(PromptDedupApp::new()))
+            Ok(Box::new(AppShell::launch()))
         }),
     )
 }
@@ -91,12 +113,89 @@ enum Tab {
     Deduplicate,
 }
 
+/// What each exported wildcard file is made of
+#[derive(PartialEq, Clone, Copy)]
+enum WildcardGroupBy {
+    Wildcard,
+    Tag,
+    Collection,
+    SourceFile,
+}
+
+impl WildcardGroupBy {
+    const ALL: [WildcardGroupBy; 4] = [
+        WildcardGroupBy::Wildcard,
+        WildcardGroupBy::Tag,
+        WildcardGroupBy::Collection,
+        WildcardGroupBy::SourceFile,
+    ];
+
+    fn label(&self) -> &'static str {
+        match self {
+            WildcardGroupBy::Wildcard => "Wildcard",
+            WildcardGroupBy::Tag => "Tag",
+            WildcardGroupBy::Collection => "Collection",
+            WildcardGroupBy::SourceFile => "Source file",
+        }
+    }
+}
+
 struct PromptDedupApp {
-    db: Database,
+    library: Library,
+    /// Absolute path of the open library
+    library_path: PathBuf,
+    config: AppConfig,
+    /// Window title needs updating after opening a library
+    title_pending: bool,
     prompt_count: i64,
     last_import_result: Option<ImportResult>,
-    search_query: String,
-    displayed_prompts: Vec<Prompt>,
+    import_dialog: Option<ImportDialog>,
+    import_options: ImportOptions,
+    import_job: Option<ImportHandle>,
+    /// What Browse lists
+    browse_filter: PromptFilter,
+    /// Prompts matching `browse_filter`, loaded as Browse scrolls
+    browse: BrowseList,
+    export_format: ExportFormat,
+    export_columns: Vec<ExportColumn>,
+    show_export_options: bool,
+    export_filter: ExportFilter,
+    /// Distinct source files, gathered when the export options open
+    export_source_files: Vec<String>,
+    /// Prompts ticked in Browse
+    selected_ids: HashSet<i64>,
+    show_wildcard_export: bool,
+    wildcard_group_by: WildcardGroupBy,
+    wildcard_export_yaml: bool,
+    show_split_export: bool,
+    split_fallback: String,
+    split_overwrite: bool,
+    /// Files the shown prompts split into, counted when the popup opens
+    split_file_count: usize,
+    /// Folder of originals waiting for the user to confirm overwriting
+    split_pending_dir: Option<(PathBuf, usize)>,
+    show_styles_export: bool,
+    style_rows: Vec<StyleRow>,
+    style_name_words: usize,
+    style_placeholder: StylePlaceholder,
+    style_merge: bool,
+    wildcard_list: Vec<WildcardInfo>,
+    tag_list: Vec<TagInfo>,
+    collection_list: Vec<CollectionInfo>,
+    /// Tag typed into the bulk tagging box
+    tag_input: String,
+    /// Name typed into "Add to Collection" for a new collection
+    collection_input: String,
+    /// Models offered by the model filter, with prompt counts
+    model_list: Vec<(String, i64)>,
+    /// Files offered by the source file facet, with prompt counts
+    source_file_list: Vec<(String, i64)>,
+    /// Date and length facets as typed
+    facet_draft: FacetDraft,
+    /// Prompt open in the detail panel
+    detail: Option<DetailPanel>,
+    /// Prompt whose text is being edited in Browse or on a duplicate pair
+    editor: Option<PromptEditor>,
 
     // Tab state
     active_tab: Tab,
@@ -106,23 +205,50 @@ struct PromptDedupApp {
     similar_pairs: Vec<SimilarPair>,
     current_pair_index: usize,
     is_scanning: bool,
+    /// Pairs also need similar negative prompts
+    compare_negatives: bool,
 
     // Find & Replace state
     show_find_replace: bool,
     find_text: String,
     replace_text: String,
     case_sensitive: bool,
-    replace_preview: Vec<ReplacePreview>,
+    replace_preview: Vec<Replacement>,
+
+    // Tag rules state
+    show_tag_rules: bool,
+    tag_rules: Vec<TagRule>,
+    rule_kind: RuleKind,
+    rule_pattern: String,
+    rule_tag: String,
+    /// Dry run of the rules over the prompts shown in Browse
+    categorize_preview: Option<CategorizeReport>,
+
+    // Import history state
+    show_import_history: bool,
+    import_batches: Vec<ImportBatch>,
+    /// Batch waiting for the user to confirm undoing it
+    pending_undo: Option<i64>,
+
+    // Normalization settings state
+    show_normalize_settings: bool,
+    normalize_draft: NormalizeOptions,
+    normalize_report: Option<NormalizeReport>,
+    normalize_report_applied: bool,
 
     // Status message
     status_message: Option<(String, bool)>, // (message, is_error)
     status_time: Option<Instant>,
 }
 
-struct ReplacePreview {
+/// A prompt queued for styles.csv export with its editable name
+struct StyleRow {
     id: i64,
-    original: String,
-    replaced: String,
+    text: String,
+    negative_prompt: String,
+    name: String,
+    /// Name was typed by the user (or came from a saved title) rather than derived from the text
+    edited: bool,
 }
 
 struct ImportResult {
@@ -131,43 +257,437 @@ struct ImportResult {
     skipped: usize,
 }
 
-impl PromptDedupApp {
-    fn new() -> Self {
-        let db = Database::open("prompts.db").expect("Failed to open database");
-        let prompt_count = db.count().unwrap_or(0);
-        let displayed_prompts = db.get_all().unwrap_or_default();
+/// A file that has been read but not yet imported, so the split can be checked first
+struct ImportDialog {
+    path: PathBuf,
+    file_name: String,
+    /// Start of the file, see `import::PREVIEW_BYTES`
+    bytes: Vec<u8>,
+    total_bytes: u64,
+    encoding: TextEncoding,
+    decoded: DecodedText,
+    options: ImportOptions,
+    records: Result<Vec<ParsedRecord>, String>,
+    /// Comma-separated tags for every imported prompt
+    tags_text: String,
+}
+
+impl ImportDialog {
+    fn decode(&mut self) {
+        self.decoded = if self.is_partial() {
+            import::decode_partial(&self.bytes, self.encoding)
+        } else {
+            import::decode(&self.bytes, self.encoding)
+        };
+        self.reparse();
+    }
+
+    fn is_partial(&self) -> bool {
+        (self.bytes.len() as u64) < self.total_bytes
+    }
+
+    fn reparse(&mut self) {
+        self.records = import::parse_records(&self.decoded.text, &self.options)
+            .map_err(|e| e.to_string());
+    }
+}
+
+/// The Browse facets typed as text, applied to the filter once they parse
+#[derive(Default)]
+struct FacetDraft {
+    imported_from: String,
+    imported_to: String,
+    min_length: String,
+    max_length: String,
+}
+
+impl FacetDraft {
+    fn new(filter: &PromptFilter) -> Self {
+        let number = |value: Option<usize>| value.map(|n| n.to_string()).unwrap_or_default();
+        Self {
+            imported_from: filter.imported_from.clone().unwrap_or_default(),
+            imported_to: filter.imported_to.clone().unwrap_or_default(),
+            min_length: number(filter.min_length),
+            max_length: number(filter.max_length),
+        }
+    }
+
+    /// Copy the fields that parse into `filter`, empty ones clearing their facet
+    fn apply(&self, filter: &mut PromptFilter) {
+        fn date(text: &str) -> Option<Option<String>> {
+            match text.trim() {
+                "" => Some(None),
+                day => db::is_date(day).then(|| Some(day.to_string())),
+            }
+        }
+        fn number(text: &str) -> Option<Option<usize>> {
+            match text.trim() {
+                "" => Some(None),
+                n => n.parse().ok().map(Some),
+            }
+        }
+        if let Some(day) = date(&self.imported_from) {
+            filter.imported_from = day;
+        }
+        if let Some(day) = date(&self.imported_to) {
+            filter.imported_to = day;
+        }
+        if let Some(length) = number(&self.min_length) {
+            filter.min_length = length;
+        }
+        if let Some(length) = number(&self.max_length) {
+            filter.max_length = length;
+        }
+    }
+}
+
+/// A prompt's text being edited in place
+struct PromptEditor {
+    id: i64,
+    text: String,
+    /// The text box still needs focus after opening
+    focus: bool,
+    /// Prompt that already has the edited text, offered as a merge target
+    collision: Option<Prompt>,
+}
+
+impl PromptEditor {
+    fn new(id: i64, text: &str) -> Self {
+        Self { id, text: text.to_string(), focus: true, collision: None }
+    }
+}
+
+/// What the user did in the prompt editor this frame
+enum EditorAction {
+    Save,
+    Cancel,
+    /// Fold the edited prompt into the one that already has its text
+    Merge(i64),
+}
+
+/// The prompt open in the detail panel, with its fields as typed
+struct DetailPanel {
+    prompt: Prompt,
+    negative_prompt: String,
+    model: String,
+    sampler: String,
+    steps: String,
+    cfg_scale: String,
+    seed: String,
+    rating: Option<u8>,
+    favorite: bool,
+    notes: String,
+    /// Every import of the prompt, duplicates included
+    sources: Vec<PromptSource>,
+    /// Changes to the prompt's text, newest first
+    versions: Vec<PromptVersion>,
+}
+
+impl DetailPanel {
+    fn new(prompt: Prompt, sources: Vec<PromptSource>, versions: Vec<PromptVersion>) -> Self {
+        let metadata = &prompt.metadata;
+        let number = |value: Option<String>| value.unwrap_or_default();
         Self {
-            db,
-            prompt_count,
+            negative_prompt: metadata.negative_prompt.clone().unwrap_or_default(),
+            model: metadata.model.clone().unwrap_or_default(),
+            sampler: metadata.sampler.clone().unwrap_or_default(),
+            steps: number(metadata.steps.map(|v| v.to_string())),
+            cfg_scale: number(metadata.cfg_scale.map(|v| v.to_string())),
+            seed: number(metadata.seed.map(|v| v.to_string())),
+            rating: metadata.rating,
+            favorite: metadata.favorite,
+            notes: metadata.notes.clone().unwrap_or_default(),
+            sources,
+            versions,
+            prompt,
+        }
+    }
+
+    /// Throw away unsaved changes
+    fn revert(&mut self) {
+        let sources = std::mem::take(&mut self.sources);
+        let versions = std::mem::take(&mut self.versions);
+        *self = DetailPanel::new(self.prompt.clone(), sources, versions);
+    }
+
+    /// The fields as metadata, or which number field doesn't parse
+    fn metadata(&self) -> Result<PromptMetadata, String> {
+        fn text(value: &str) -> Option<String> {
+            let value = value.trim();
+            (!value.is_empty()).then(|| value.to_string())
+        }
+        fn number<T: std::str::FromStr>(value: &str, name: &str) -> Result<Option<T>, String> {
+            match value.trim() {
+                "" => Ok(None),
+                value => value.parse().map(Some).map_err(|_| format!("{} must be a number", name)),
+            }
+        }
+        Ok(PromptMetadata {
+            negative_prompt: text(&self.negative_prompt),
+            model: text(&self.model),
+            sampler: text(&self.sampler),
+            steps: number(&self.steps, "Steps")?,
+            cfg_scale: number(&self.cfg_scale, "CFG scale")?,
+            seed: number(&self.seed, "Seed")?,
+            rating: self.rating,
+            favorite: self.favorite,
+            notes: text(&self.notes),
+        })
+    }
+
+    fn is_modified(&self) -> bool {
+        self.metadata().map_or(true, |metadata| metadata != self.prompt.metadata)
+    }
+}
+
+impl PromptDedupApp {
+    fn new(library: Library, library_path: PathBuf, config: AppConfig) -> Self {
+        let library_path = library_path.canonicalize().unwrap_or(library_path);
+        let mut app = Self {
+            library,
+            library_path,
+            config,
+            title_pending: true,
+            prompt_count: 0,
             last_import_result: None,
-            search_query: String::new(),
-            displayed_prompts,
+            import_dialog: None,
+            import_options: ImportOptions::default(),
+            import_job: None,
+            browse_filter: PromptFilter::default(),
+            browse: BrowseList::default(),
+            export_format: ExportFormat::Txt,
+            export_columns: vec![ExportColumn::Id, ExportColumn::Text, ExportColumn::SourceFile],
+            show_export_options: false,
+            export_filter: ExportFilter::default(),
+            export_source_files: Vec::new(),
+            selected_ids: HashSet::new(),
+            show_wildcard_export: false,
+            wildcard_group_by: WildcardGroupBy::Wildcard,
+            wildcard_export_yaml: false,
+            show_split_export: false,
+            split_fallback: "unsorted.txt".to_string(),
+            split_overwrite: false,
+            split_file_count: 0,
+            split_pending_dir: None,
+            show_styles_export: false,
+            style_rows: Vec::new(),
+            style_name_words: 5,
+            style_placeholder: StylePlaceholder::None,
+            style_merge: true,
+            wildcard_list: Vec::new(),
+            tag_list: Vec::new(),
+            collection_list: Vec::new(),
+            tag_input: String::new(),
+            collection_input: String::new(),
+            model_list: Vec::new(),
+            source_file_list: Vec::new(),
+            facet_draft: FacetDraft::default(),
+            detail: None,
+            editor: None,
             active_tab: Tab::Browse,
             similarity_threshold: 0.80,
             similar_pairs: Vec::new(),
             current_pair_index: 0,
             is_scanning: false,
+            compare_negatives: false,
             show_find_replace: false,
             find_text: String::new(),
             replace_text: String::new(),
             case_sensitive: false,
             replace_preview: Vec::new(),
+            show_tag_rules: false,
+            show_import_history: false,
+            import_batches: Vec::new(),
+            pending_undo: None,
+            tag_rules: Vec::new(),
+            rule_kind: RuleKind::Keywords,
+            rule_pattern: String::new(),
+            rule_tag: String::new(),
+            categorize_preview: None,
+            show_normalize_settings: false,
+            normalize_draft: NormalizeOptions::default(),
+            normalize_report: None,
+            normalize_report_applied: false,
             status_message: None,
             status_time: None,
+        };
+        app.library_opened();
+        app
+    }
+
+    /// Load everything shown from the library that was just opened
+    fn library_opened(&mut self) {
+        self.config.opened(&self.library_path);
+        let _ = self.config.save();
+        self.title_pending = true;
+
+        self.browse_filter = PromptFilter::default();
+        self.facet_draft = FacetDraft::default();
+        self.detail = None;
+        self.editor = None;
+        self.selected_ids.clear();
+        self.last_import_result = None;
+        self.similar_pairs.clear();
+        self.current_pair_index = 0;
+        self.replace_preview.clear();
+        self.style_rows.clear();
+        self.show_tag_rules = false;
+        self.categorize_preview = None;
+        self.show_import_history = false;
+        self.pending_undo = None;
+        self.wildcard_list = self.library.db().wildcards().unwrap_or_default();
+        self.normalize_draft = self.library.db().normalize_options();
+        self.normalize_report = None;
+        self.normalize_report_applied = false;
+        self.status_message = None;
+        self.refresh_counts();
+
+        if let Some(upgrade) = self.library.db_mut().take_upgrade_report() {
+            let backup = upgrade.backup
+                .map(|path| format!(", the old version was saved as {}", path.display()))
+                .unwrap_or_default();
+            self.set_status(format!(
+                "Upgraded library from version {} to {}{}",
+                upgrade.from, upgrade.to, backup
+            ), false);
+        }
+
+        // Show what the one-time normalization migration merged
+        let migration_report = self.library.db_mut().take_migration_report()
+            .filter(|report| !report.collisions.is_empty());
+        if let Some(report) = migration_report {
+            self.set_status(format!(
+                "Merged {} prompts that were duplicates after normalization",
+                report.removed_count()
+            ), false);
+            self.normalize_report = Some(report);
+            self.normalize_report_applied = true;
+            self.show_normalize_settings = true;
+        }
+    }
+
+    /// Open (or create) the library at `path` in place of the current one
+    fn switch_library(&mut self, path: PathBuf) {
+        if self.import_job.is_some() {
+            self.set_status("Wait for the import to finish before switching libraries".to_string(), true);
+            return;
+        }
+
+        match Library::open(&path) {
+            Ok(library) => {
+                self.library = library;
+                self.library_path = path.canonicalize().unwrap_or(path);
+                self.library_opened();
+                // Keep the upgrade message if opening migrated anything
+                if self.status_message.is_none() {
+                    self.set_status(format!(
+                        "Opened library {} ({} prompts)",
+                        library_name(&self.library_path),
+                        self.prompt_count
+                    ), false);
+                }
+            }
+            Err(e) => {
+                self.config.recent.retain(|p| *p != path);
+                let _ = self.config.save();
+                self.set_status(format!("Failed to open {}: {}", path.display(), e), true);
+            }
+        }
+    }
+
+    fn new_library(&mut self) {
+        if let Some(path) = rfd::FileDialog::new()
+            .add_filter("Prompt library", &["db"])
+            .set_directory(prompt_dedup::data_dir())
+            .set_file_name("library.db")
+            .save_file()
+        {
+            // Never wipe an existing library, even if the dialog offered to replace it
+            let existed = path.is_file();
+            self.switch_library(path);
+            if existed && !self.status_message.as_ref().is_some_and(|(_, is_error)| *is_error) {
+                self.set_status(format!(
+                    "{} already exists, opened it instead of creating a new one",
+                    library_name(&self.library_path)
+                ), false);
+            }
+        }
+    }
+
+    fn open_library(&mut self) {
+        if let Some(path) = rfd::FileDialog::new()
+            .add_filter("Prompt library", &["db"])
+            .set_directory(self.library_path.parent().unwrap_or(Path::new(".")))
+            .pick_file()
+        {
+            self.switch_library(path);
         }
     }
 
+    fn render_menu_bar(&mut self, ctx: &egui::Context) {
+        egui::TopBottomPanel::top("menu_bar")
+            .frame(egui::Frame::new().fill(colors::MANTLE).inner_margin(egui::Margin::symmetric(12, 4)))
+            .show(ctx, |ui| {
+                egui::menu::bar(ui, |ui| {
+                    ui.menu_button("File", |ui| {
+                        if ui.button("New Library...").clicked() {
+                            ui.close_menu();
+                            self.new_library();
+                        }
+                        if ui.button("Open Library...").clicked() {
+                            ui.close_menu();
+                            self.open_library();
+                        }
+
+                        let recent: Vec<PathBuf> = self.config.recent.iter()
+                            .filter(|p| **p != self.library_path)
+                            .cloned()
+                            .collect();
+                        ui.add_enabled_ui(!recent.is_empty(), |ui| {
+                            ui.menu_button("Open Recent", |ui| {
+                                for path in recent {
+                                    let response = ui.button(library_name(&path))
+                                        .on_hover_text(path.display().to_string());
+                                    if response.clicked() {
+                                        ui.close_menu();
+                                        self.switch_library(path);
+                                    }
+                                }
+                                ui.separator();
+                                if ui.button("Clear Recent").clicked() {
+                                    ui.close_menu();
+                                    self.config.recent.retain(|p| *p == self.library_path);
+                                    let _ = self.config.save();
+                                }
+                            });
+                        });
+                    });
+
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        ui.label(egui::RichText::new(self.library_path.display().to_string())
+                            .color(colors::SUBTEXT)
+                            .small());
+                        if self.library.is_read_only() {
+                            ui.label(egui::RichText::new("read-only").color(colors::PEACH).small());
+                        } else if self.import_job.is_some() {
+                            ui.label(egui::RichText::new("importing, editing paused").color(colors::PEACH).small());
+                        }
+                    });
+                });
+            });
+    }
+
     fn set_status(&mut self, message: String, is_error: bool) {
         self.status_message = Some((message, is_error));
         self.status_time = Some(Instant::now());
     }
 
     fn clear_old_status(&mut self) {
-        if let Some(time) = self.status_time {
-            if time.elapsed().as_secs() >= 5 {
-                self.status_message = None;
-                self.status_time = None;
-            }
+        if let Some(time) = self.status_time
+            && time.elapsed().as_secs() >= 5
+        {
+            self.status_message = None;
+            self.status_time = None;
         }
     }
 
@@ -176,34 +696,21 @@ impl PromptDedupApp {
             .map(|n| n.to_string_lossy().to_string())
             .unwrap_or_else(|| "unknown".to_string());
 
-        match fs::read_to_string(&path) {
-            Ok(contents) => {
-                let prompts: Vec<(String, Option<String>)> = contents
-                    .lines()
-                    .map(|line| line.trim())
-                    .filter(|line| !line.is_empty())
-                    .map(|line| (line.to_string(), Some(file_name.clone())))
-                    .collect();
-
-                let total = prompts.len();
-                match self.db.insert_prompts(&prompts) {
-                    Ok(added) => {
-                        let skipped = total - added;
-
-                        self.last_import_result = Some(ImportResult {
-                            file_name: file_name.clone(),
-                            added,
-                            skipped,
-                        });
-
-                        self.prompt_count = self.db.count().unwrap_or(0);
-                        self.refresh_displayed_prompts();
-                        self.set_status(format!("Imported {} from {} ({} duplicates skipped)", added, file_name, skipped), false);
-                    }
-                    Err(e) => {
-                        self.set_status(format!("Database error: {}", e), true);
-                    }
-                }
+        match import::read_preview(&path) {
+            Ok((bytes, total_bytes)) => {
+                let mut dialog = ImportDialog {
+                    path,
+                    file_name,
+                    bytes,
+                    total_bytes,
+                    encoding: TextEncoding::Auto,
+                    decoded: import::decode(&[], TextEncoding::Auto),
+                    options: self.import_options.clone(),
+                    records: Ok(Vec::new()),
+                    tags_text: String::new(),
+                };
+                dialog.decode();
+                self.import_dialog = Some(dialog);
             }
             Err(e) => {
                 self.set_status(format!("Failed to read file: {}", e), true);
@@ -211,599 +718,3037 @@ impl PromptDedupApp {
         }
     }
 
+    fn start_import(&mut self, mut dialog: ImportDialog) {
+        dialog.options.tags = db::parse_tags(&dialog.tags_text);
+        match self.library.spawn_import(&dialog.path, dialog.encoding, &dialog.options) {
+            Ok(handle) => self.import_job = Some(handle),
+            Err(e) => self.set_status(e.to_string(), true),
+        }
+    }
+
+    fn poll_import_job(&mut self) {
+        let Some(result) = self.import_job.as_ref().and_then(|job| job.poll()) else {
+            return;
+        };
+        self.import_job = None;
+
+        match result {
+            Ok(summary) => self.finish_import(summary),
+            Err(e) => self.set_status(e.to_string(), true),
+        }
+    }
+
+    fn finish_import(&mut self, summary: ImportSummary) {
+        if summary.cancelled {
+            self.set_status(format!("Import of {} cancelled, nothing was added", summary.file_name), false);
+            return;
+        }
+
+        let skipped = summary.total - summary.added;
+        self.last_import_result = Some(ImportResult {
+            file_name: summary.file_name.clone(),
+            added: summary.added,
+            skipped,
+        });
+
+        self.refresh_counts();
+
+        let invalid_info = if !summary.invalid_lines.is_empty() {
+            format!(
+                ", {} lines had invalid bytes: {}",
+                summary.invalid_lines.len(),
+                list_line_numbers(&summary.invalid_lines)
+            )
+        } else {
+            String::new()
+        };
+        let previous_info = summary.previous_import
+            .map(|time| format!(". The same file was already imported on {}", time))
+            .unwrap_or_default();
+        self.set_status(format!(
+            "Imported {} from {} ({} duplicates skipped{}){}",
+            summary.added, summary.file_name, skipped, invalid_info, previous_info
+        ), false);
+    }
+
+    fn open_export_options(&mut self) {
+        let mut files: Vec<String> = self.library.db().get_all().unwrap_or_default()
+            .into_iter()
+            .filter_map(|p| p.source_file)
+            .collect();
+        files.sort();
+        files.dedup();
+        if self.export_filter.source_file.as_ref().is_some_and(|f| !files.contains(f)) {
+            self.export_filter.source_file = None;
+        }
+        self.export_filter.wildcards.retain(|c| self.wildcard_list.iter().any(|w| w.name == *c));
+        self.export_source_files = files;
+        self.show_export_options = true;
+    }
+
+    /// Prompts the export starts from, before `ExportFilter::filter`
+    fn export_starting_set(&self, clusters: &HashMap<i64, usize>) -> Vec<Prompt> {
+        match self.export_filter.source {
+            ExportSource::Displayed => self.shown_prompts(),
+            ExportSource::Selection => self.library.db().get_all().unwrap_or_default()
+                .into_iter()
+                .filter(|p| self.selected_ids.contains(&p.id))
+                .collect(),
+            ExportSource::Clusters => self.library.db().get_all().unwrap_or_default()
+                .into_iter()
+                .filter(|p| clusters.contains_key(&p.id))
+                .collect(),
+        }
+    }
+
     fn export_prompts(&mut self) {
-        if self.displayed_prompts.is_empty() {
+        let format = self.export_format;
+        if format.has_columns() && self.export_columns.is_empty() {
+            self.set_status("Pick at least one column to export".to_string(), true);
+            return;
+        }
+        if let Err(e) = self.export_filter.validate() {
+            self.set_status(e, true);
+            return;
+        }
+
+        let clusters = similarity::clusters_from_pairs(&self.similar_pairs);
+        let memberships = self.library.db().wildcard_memberships().unwrap_or_default();
+        let filtered = self.export_filter.filter(self.export_starting_set(&clusters), &memberships, &clusters);
+        let total = filtered.len();
+        if total == 0 {
             self.set_status("Nothing to export".to_string(), true);
             return;
         }
 
         if let Some(path) = rfd::FileDialog::new()
-            .add_filter("Text files", &["txt"])
-            .set_file_name("prompts.txt")
+            .add_filter(format.label(), &[format.extension()])
+            .set_file_name(format!("prompts.{}", format.extension()))
             .save_file()
         {
-            match export::export_to_txt(&self.displayed_prompts, &path) {
+            let prompts = self.export_filter.sample(filtered);
+
+            // Keep the column picker order no matter which order they were ticked in
+            let columns: Vec<ExportColumn> = ExportColumn::ALL.into_iter()
+                .filter(|c| self.export_columns.contains(c))
+                .collect();
+
+            match self.library.export(&prompts, &path, format, &columns, &self.similar_pairs) {
                 Ok(count) => {
-                    let filter_info = if !self.search_query.is_empty() {
-                        " (filtered)"
-                    } else {
-                        ""
-                    };
-                    self.set_status(format!("Exported {} prompts{}", count, filter_info), false);
+                    let description = self.export_filter.describe(&self.browse_filter, total);
+                    self.set_status(format!("Exported {} prompts: {}", count, description), false);
+                    self.show_export_options = false;
                 }
                 Err(e) => {
-                    self.set_status(format!("Failed to export: {}", e), true);
+                    self.set_status(e.to_string(), true);
                 }
             }
         }
     }
 
-    fn refresh_displayed_prompts(&mut self) {
-        self.displayed_prompts = if self.search_query.is_empty() {
-            self.db.get_all().unwrap_or_default()
-        } else {
-            self.db.search(&self.search_query).unwrap_or_default()
-        };
-    }
+    fn render_export_options_popup(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_export_options;
+        let cluster_count = similarity::clusters_from_pairs(&self.similar_pairs)
+            .values()
+            .collect::<HashSet<_>>()
+            .len();
 
-    fn refresh_counts(&mut self) {
-        self.prompt_count = self.db.count().unwrap_or(0);
-        self.refresh_displayed_prompts();
-    }
+        egui::Window::new("Export Prompts")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                let filter = &mut self.export_filter;
+                egui::Grid::new("export_options_grid")
+                    .num_columns(2)
+                    .spacing([10.0, 8.0])
+                    .show(ui, |ui| {
+                        ui.label("Start from:");
+                        ui.vertical(|ui| {
+                            for source in ExportSource::ALL {
+                                let count = match source {
+                                    ExportSource::Displayed => format!("{} prompts", self.browse.len()),
+                                    ExportSource::Selection => format!("{} prompts", self.selected_ids.len()),
+                                    ExportSource::Clusters => format!("{} clusters", cluster_count),
+                                };
+                                ui.radio_value(&mut filter.source, source, format!("{} ({})", source.label(), count));
+                            }
+                        });
+                        ui.end_row();
 
-    fn scan_for_duplicates(&mut self) {
-        let all_prompts = self.db.get_all().unwrap_or_default();
-        let prompts_for_scan: Vec<(i64, String)> = all_prompts
-            .iter()
-            .map(|p| (p.id, p.text.clone()))
-            .collect();
+                        if filter.source == ExportSource::Clusters {
+                            ui.label("Per cluster:");
+                            ui.horizontal(|ui| {
+                                ui.checkbox(&mut filter.keepers_only, "Only the keeper:");
+                                ui.add_enabled_ui(filter.keepers_only, |ui| {
+                                    egui::ComboBox::from_id_salt("export_keeper")
+                                        .selected_text(filter.keeper.label())
+                                        .show_ui(ui, |ui| {
+                                            for keeper in Keeper::ALL {
+                                                ui.selectable_value(&mut filter.keeper, keeper, keeper.label());
+                                            }
+                                        });
+                                });
+                            });
+                            ui.end_row();
+                        }
 
-        self.similar_pairs = similarity::find_similar_pairs(&prompts_for_scan, self.similarity_threshold);
-        self.current_pair_index = 0;
-        self.is_scanning = false;
-    }
+                        if !self.wildcard_list.is_empty() {
+                            ui.label("Wildcards:");
+                            let summary = if filter.wildcards.is_empty() {
+                                "Any".to_string()
+                            } else {
+                                filter.wildcards.join(", ")
+                            };
+                            ui.menu_button(summary, |ui| {
+                                for wildcard in &self.wildcard_list {
+                                    let mut checked = filter.wildcards.contains(&wildcard.name);
+                                    if ui.checkbox(&mut checked, format!("__{}__", wildcard.name)).changed() {
+                                        if checked {
+                                            filter.wildcards.push(wildcard.name.clone());
+                                        } else {
+                                            filter.wildcards.retain(|c| *c != wildcard.name);
+                                        }
+                                    }
+                                }
+                            });
+                            ui.end_row();
+                        }
 
-    fn delete_prompt(&mut self, id: i64) {
-        let _ = self.db.delete_prompt(id);
-        self.refresh_counts();
+                        ui.label("Created:");
+                        ui.horizontal(|ui| {
+                            ui.add(egui::TextEdit::singleline(&mut filter.date_from)
+                                .hint_text("YYYY-MM-DD")
+                                .desired_width(90.0));
+                            ui.label("to");
+                            ui.add(egui::TextEdit::singleline(&mut filter.date_to)
+                                .hint_text("YYYY-MM-DD")
+                                .desired_width(90.0));
+                        });
+                        ui.end_row();
 
-        // Remove pairs containing this ID
-        self.similar_pairs.retain(|p| p.id_a != id && p.id_b != id);
+                        ui.label("Source file:");
+                        egui::ComboBox::from_id_salt("export_source_file")
+                            .selected_text(filter.source_file.as_deref().unwrap_or("Any"))
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut filter.source_file, None, "Any");
+                                for file in &self.export_source_files {
+                                    ui.selectable_value(&mut filter.source_file, Some(file.clone()), file);
+                                }
+                            });
+                        ui.end_row();
 
-        // Adjust index if needed
-        if self.current_pair_index >= self.similar_pairs.len() && !self.similar_pairs.is_empty() {
-            self.current_pair_index = self.similar_pairs.len() - 1;
-        }
+                        ui.label("Random sample:");
+                        ui.horizontal(|ui| {
+                            let mut sampling = filter.sample.is_some();
+                            if ui.checkbox(&mut sampling, "Export only").changed() {
+                                filter.sample = sampling.then_some(500);
+                            }
+                            if let Some(size) = &mut filter.sample {
+                                ui.add(egui::DragValue::new(size).range(1..=1_000_000));
+                                ui.label("random prompts");
+                            }
+                        });
+                        ui.end_row();
+                    });
+
+                ui.add_space(8.0);
+                ui.label(egui::RichText::new(format!(
+                    "Written as {}. Dates compare against when a prompt was imported (UTC).",
+                    self.export_format.label()
+                )).color(colors::SUBTEXT));
+                ui.add_space(16.0);
+
+                ui.vertical_centered(|ui| {
+                    ui.horizontal(|ui| {
+                        let export_btn = egui::Button::new(
+                            egui::RichText::new("Export...").color(colors::CRUST)
+                        ).fill(colors::BLUE);
+
+                        if ui.add(export_btn).clicked() {
+                            self.export_prompts();
+                        }
+
+                        if ui.button("Cancel").clicked() {
+                            self.show_export_options = false;
+                        }
+                    });
+                });
+            });
+
+        self.show_export_options &= open;
     }
 
-    fn remove_all_duplicates(&mut self) {
-        // Collect IDs to delete (keep first, delete second from each pair)
-        let ids_to_delete: Vec<i64> = self.similar_pairs.iter()
-            .map(|p| p.id_b)
-            .collect();
+    fn wildcard_export_groups(&self) -> Vec<(String, Vec<String>)> {
+        let shown = self.shown_prompts();
+        let mut groups: Vec<(String, Vec<String>)> = Vec::new();
+        let mut add = |name: &str, text: &str| {
+            match groups.iter_mut().find(|(n, _)| n == name) {
+                Some((_, entries)) => entries.push(text.to_string()),
+                None => groups.push((name.to_string(), vec![text.to_string()])),
+            }
+        };
 
-        let count = ids_to_delete.len();
-        for id in ids_to_delete {
-            let _ = self.db.delete_prompt(id);
+        match self.wildcard_group_by {
+            WildcardGroupBy::Wildcard | WildcardGroupBy::Tag | WildcardGroupBy::Collection => {
+                let db = self.library.db();
+                let memberships = match self.wildcard_group_by {
+                    WildcardGroupBy::Wildcard => db.wildcard_memberships(),
+                    WildcardGroupBy::Tag => db.tag_memberships(),
+                    _ => db.collection_memberships(),
+                }.unwrap_or_default();
+                for prompt in &shown {
+                    for name in memberships.get(&prompt.id).into_iter().flatten() {
+                        add(name, &prompt.text);
+                    }
+                }
+            }
+            WildcardGroupBy::SourceFile => {
+                for prompt in &shown {
+                    let name = prompt.source_file.as_deref()
+                        .map(|source| source.rsplit_once('.').map_or(source, |(stem, _)| stem))
+                        .unwrap_or("unsorted");
+                    add(name, &prompt.text);
+                }
+            }
         }
-
-        self.similar_pairs.clear();
-        self.current_pair_index = 0;
-        self.refresh_counts();
-        self.set_status(format!("Removed {} duplicate prompts", count), false);
+        groups
     }
 
-    fn render_highlighted_text(&self, ui: &mut egui::Ui, text: &str) {
-        if self.search_query.is_empty() {
-            ui.label(text);
+    fn export_wildcards(&mut self) {
+        let groups = self.wildcard_export_groups();
+        if groups.is_empty() {
+            self.set_status("Nothing to export as wildcards".to_string(), true);
             return;
         }
 
-        let query_lower = self.search_query.to_lowercase();
-        let text_lower = text.to_lowercase();
-
-        let mut job = egui::text::LayoutJob::default();
-        let mut last_end = 0;
-
-        for (start, _) in text_lower.match_indices(&query_lower) {
-            if start > last_end {
-                job.append(
-                    &text[last_end..start],
-                    0.0,
-                    egui::TextFormat::simple(egui::FontId::default(), colors::TEXT),
-                );
+        if self.wildcard_export_yaml {
+            let Some(path) = rfd::FileDialog::new()
+                .add_filter("YAML files", &["yaml"])
+                .set_file_name("wildcards.yaml")
+                .save_file()
+            else {
+                return;
+            };
+            match export::export_wildcard_yaml(&groups, &path) {
+                Ok(count) => self.set_status(format!("Exported {} wildcards to {}", count, path.display()), false),
+                Err(e) => self.set_status(format!("Failed to export: {}", e), true),
+            }
+        } else {
+            let Some(dir) = rfd::FileDialog::new().pick_folder() else {
+                return;
+            };
+            match export::export_wildcard_folder(&groups, &dir) {
+                Ok(summary) => self.set_status(format!(
+                    "Exported wildcards: {} written, {} unchanged, {} removed",
+                    summary.written, summary.unchanged, summary.removed
+                ), false),
+                Err(e) => self.set_status(format!("Failed to export: {}", e), true),
             }
+        }
+        self.show_wildcard_export = false;
+    }
 
-            let end = start + self.search_query.len();
-            job.append(
-                &text[start..end],
-                0.0,
-                egui::TextFormat {
-                    color: colors::CRUST,
-                    background: colors::YELLOW,
-                    ..egui::TextFormat::simple(egui::FontId::default(), colors::CRUST)
-                },
-            );
+    fn render_wildcard_export_popup(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_wildcard_export;
 
-            last_end = end;
+        egui::Window::new("Export as Wildcards")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("wildcard_export_grid")
+                    .num_columns(2)
+                    .spacing([10.0, 8.0])
+                    .show(ui, |ui| {
+                        ui.label("One wildcard per:");
+                        egui::ComboBox::from_id_salt("wildcard_group_by")
+                            .selected_text(self.wildcard_group_by.label())
+                            .show_ui(ui, |ui| {
+                                for group_by in WildcardGroupBy::ALL {
+                                    ui.selectable_value(&mut self.wildcard_group_by, group_by, group_by.label());
+                                }
+                            });
+                        ui.end_row();
+
+                        ui.label("Write to:");
+                        ui.horizontal(|ui| {
+                            ui.radio_value(&mut self.wildcard_export_yaml, false, "Folder of .txt files");
+                            ui.radio_value(&mut self.wildcard_export_yaml, true, "Single .yaml file");
+                        });
+                        ui.end_row();
+                    });
+
+                ui.add_space(8.0);
+                ui.label(egui::RichText::new(format!(
+                    "Exports the {} prompts currently shown. Re-exporting into the same folder only rewrites changed files.",
+                    self.browse.len()
+                )).color(colors::SUBTEXT));
+                ui.add_space(16.0);
+
+                ui.vertical_centered(|ui| {
+                    ui.horizontal(|ui| {
+                        let export_btn = egui::Button::new(
+                            egui::RichText::new("Export").color(colors::CRUST)
+                        ).fill(colors::BLUE);
+
+                        if ui.add(export_btn).clicked() {
+                            self.export_wildcards();
+                        }
+
+                        if ui.button("Cancel").clicked() {
+                            self.show_wildcard_export = false;
+                        }
+                    });
+                });
+            });
+
+        self.show_wildcard_export &= open;
+    }
+
+    fn open_split_export(&mut self) {
+        self.count_split_files();
+        self.show_split_export = true;
+    }
+
+    fn count_split_files(&mut self) {
+        self.split_file_count = export::split_by_source(&self.shown_prompts(), &self.split_fallback).len();
+    }
+
+    fn choose_split_folder(&mut self) {
+        if self.browse.is_empty() {
+            self.set_status("Nothing to export".to_string(), true);
+            return;
         }
+        let Some(dir) = rfd::FileDialog::new().pick_folder() else {
+            return;
+        };
 
-        if last_end < text.len() {
-            job.append(
-                &text[last_end..],
-                0.0,
-                egui::TextFormat::simple(egui::FontId::default(), colors::TEXT),
-            );
+        if self.split_overwrite {
+            let existing = export::split_by_source(&self.shown_prompts(), &self.split_fallback)
+                .iter()
+                .filter(|(relative, _)| dir.join(relative).exists())
+                .count();
+            self.split_pending_dir = Some((dir, existing));
+        } else {
+            self.export_split(dir);
         }
+    }
 
-        ui.label(job);
+    fn export_split(&mut self, dir: PathBuf) {
+        match export::export_split(&self.shown_prompts(), &dir, &self.split_fallback, self.split_overwrite) {
+            Ok(summary) => {
+                let backup_info = if summary.backups > 0 {
+                    format!(", {} originals backed up as .bak", summary.backups)
+                } else {
+                    String::new()
+                };
+                self.set_status(format!(
+                    "Exported {} prompts into {} files{}",
+                    summary.prompts, summary.files, backup_info
+                ), false);
+                self.show_split_export = false;
+            }
+            Err(e) => self.set_status(format!("Failed to export: {}", e), true),
+        }
+        self.split_pending_dir = None;
     }
 
-    fn render_browse_tab(&mut self, ui: &mut egui::Ui) {
-        // Toolbar card
-        egui::Frame::new()
-            .fill(colors::SURFACE0)
-            .inner_margin(16.0)
-            .corner_radius(8.0)
-            .show(ui, |ui| {
-                ui.set_width(ui.available_width());
+    fn render_split_export_popup(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_split_export;
 
-                // First row: Import, Export, and actions
-                ui.horizontal(|ui| {
-                    let import_btn = egui::Button::new(
-                        egui::RichText::new("Import File").color(colors::CRUST)
-                    ).fill(colors::GREEN);
+        egui::Window::new("Export per Source File")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("split_export_grid")
+                    .num_columns(2)
+                    .spacing([10.0, 8.0])
+                    .show(ui, |ui| {
+                        ui.label("Write to:");
+                        ui.vertical(|ui| {
+                            ui.radio_value(&mut self.split_overwrite, false, "A new folder");
+                            ui.radio_value(&mut self.split_overwrite, true, "The folder with the original files (overwrite, keeps .bak)");
+                        });
+                        ui.end_row();
 
-                    if ui.add(import_btn).clicked() {
-                        if let Some(path) = rfd::FileDialog::new()
-                            .add_filter("Text files", &["txt"])
-                            .pick_file()
-                        {
-                            self.import_file(path);
+                        ui.label("No source:");
+                        if ui.add(egui::TextEdit::singleline(&mut self.split_fallback).desired_width(200.0)).changed() {
+                            self.count_split_files();
                         }
-                    }
+                        ui.end_row();
+                    });
 
+                ui.add_space(8.0);
+                ui.label(egui::RichText::new(format!(
+                    "{} prompts currently shown, {} files",
+                    self.browse.len(),
+                    self.split_file_count
+                )).color(colors::SUBTEXT));
+                ui.add_space(16.0);
+
+                if let Some((dir, existing)) = self.split_pending_dir.clone() {
+                    ui.label(egui::RichText::new(format!(
+                        "Overwrite {} existing files in {}? Each is copied to .bak first.",
+                        existing,
+                        dir.display()
+                    )).color(colors::PEACH));
                     ui.add_space(8.0);
 
-                    let export_btn = egui::Button::new(
-                        egui::RichText::new("Export").color(colors::CRUST)
-                    ).fill(colors::BLUE);
+                    ui.vertical_centered(|ui| {
+                        ui.horizontal(|ui| {
+                            let confirm_btn = egui::Button::new(
+                                egui::RichText::new("Overwrite").color(colors::CRUST)
+                            ).fill(colors::RED);
 
-                    if ui.add(export_btn).clicked() {
-                        self.export_prompts();
-                    }
+                            if ui.add(confirm_btn).clicked() {
+                                self.export_split(dir);
+                            }
 
-                    ui.add_space(16.0);
+                            if ui.button("Cancel").clicked() {
+                                self.split_pending_dir = None;
+                            }
+                        });
+                    });
+                } else {
+                    ui.vertical_centered(|ui| {
+                        ui.horizontal(|ui| {
+                            let export_btn = egui::Button::new(
+                                egui::RichText::new("Choose Folder...").color(colors::CRUST)
+                            ).fill(colors::BLUE);
 
-                    if let Some(result) = &self.last_import_result {
-                        ui.label(
-                            egui::RichText::new(format!(
-                                "Last: {} (+{}, -{} dupes)",
-                                result.file_name, result.added, result.skipped
-                            ))
-                            .color(colors::SUBTEXT)
-                        );
-                    }
-                });
+                            if ui.add(export_btn).clicked() {
+                                self.choose_split_folder();
+                            }
 
-                ui.add_space(12.0);
+                            if ui.button("Cancel").clicked() {
+                                self.show_split_export = false;
+                            }
+                        });
+                    });
+                }
+            });
 
-                // Second row: Search
-                ui.horizontal(|ui| {
-                    ui.label("Search:");
-                    let response = ui.add(
-                        egui::TextEdit::singleline(&mut self.search_query)
-                            .hint_text("Type to filter prompts...")
-                            .desired_width(400.0)
-                    );
-                    if response.changed() {
-                        self.refresh_displayed_prompts();
-                    }
+        self.show_split_export &= open;
+        if !self.show_split_export {
+            self.split_pending_dir = None;
+        }
+    }
 
-                    if ui.button("Clear").clicked() {
-                        self.search_query.clear();
-                        self.refresh_displayed_prompts();
-                    }
+    fn open_styles_export(&mut self) {
+        self.style_rows = self.shown_prompts().iter()
+            .map(|prompt| StyleRow {
+                id: prompt.id,
+                text: prompt.text.clone(),
+                negative_prompt: prompt.metadata.negative_prompt.clone().unwrap_or_default(),
+                name: prompt.title.clone()
+                    .unwrap_or_else(|| export::style_name_from_text(&prompt.text, self.style_name_words)),
+                edited: prompt.title.is_some(),
+            })
+            .collect();
+        self.show_styles_export = true;
+    }
 
-                    ui.add_space(16.0);
-                    ui.label(format!("Showing: {}", self.displayed_prompts.len()));
-                });
-            });
+    fn export_styles(&mut self) {
+        if self.style_rows.is_empty() {
+            self.set_status("Nothing to export as styles".to_string(), true);
+            return;
+        }
 
-        ui.add_space(16.0);
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("CSV files", &["csv"])
+            .set_file_name("styles.csv")
+            .save_file()
+        else {
+            return;
+        };
 
-        // Prompts table card
-        egui::Frame::new()
-            .fill(colors::BASE)
-            .inner_margin(0.0)
-            .corner_radius(8.0)
-            .stroke(Stroke::new(1.0, colors::SURFACE0))
-            .show(ui, |ui| {
-                ui.set_width(ui.available_width());
+        // Names typed in the dialog become the prompt's title for next time
+        if self.can_edit() {
+            for row in self.style_rows.iter().filter(|row| row.edited) {
+                let _ = self.library.db().set_title(row.id, &row.name);
+            }
+        }
 
-                // Table header
-                egui::Frame::new()
-                    .fill(colors::SURFACE0)
-                    .inner_margin(egui::Margin::symmetric(12, 8))
+        let entries: Vec<StyleEntry> = self.style_rows.iter()
+            .map(|row| StyleEntry {
+                name: if row.name.trim().is_empty() {
+                    export::style_name_from_text(&row.text, self.style_name_words)
+                } else {
+                    row.name.trim().to_string()
+                },
+                prompt: self.style_placeholder.apply(&row.text),
+                negative_prompt: row.negative_prompt.clone(),
+            })
+            .collect();
+
+        match export::export_styles(&entries, &path, self.style_merge) {
+            Ok(summary) => {
+                let renamed_info = if summary.renamed > 0 {
+                    format!(", {} renamed to avoid existing styles", summary.renamed)
+                } else {
+                    String::new()
+                };
+                self.set_status(format!(
+                    "Exported styles: {} added, {} updated{}",
+                    summary.added, summary.updated, renamed_info
+                ), false);
+                self.show_styles_export = false;
+                self.refresh_displayed_prompts();
+            }
+            Err(e) => self.set_status(format!("Failed to export: {}", e), true),
+        }
+    }
+
+    fn render_styles_export_popup(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_styles_export;
+
+        egui::Window::new("Export as A1111 Styles")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(true)
+            .default_width(600.0)
+            .default_height(450.0)
+            .show(ctx, |ui| {
+                egui::Grid::new("styles_export_grid")
+                    .num_columns(2)
+                    .spacing([10.0, 8.0])
                     .show(ui, |ui| {
-                        ui.set_width(ui.available_width());
-                        ui.label(egui::RichText::new("Prompts").strong());
+                        ui.label("Default name:");
+                        ui.horizontal(|ui| {
+                            ui.label("first");
+                            let words = ui.add(egui::DragValue::new(&mut self.style_name_words).range(1..=20));
+                            ui.label("words");
+                            if words.changed() {
+                                for row in self.style_rows.iter_mut().filter(|row| !row.edited) {
+                                    row.name = export::style_name_from_text(&row.text, self.style_name_words);
+                                }
+                            }
+                        });
+                        ui.end_row();
+
+                        ui.label("Placeholder:");
+                        egui::ComboBox::from_id_salt("style_placeholder")
+                            .selected_text(self.style_placeholder.label())
+                            .show_ui(ui, |ui| {
+                                for placeholder in StylePlaceholder::ALL {
+                                    ui.selectable_value(&mut self.style_placeholder, placeholder, placeholder.label());
+                                }
+                            });
+                        ui.end_row();
+
+                        ui.label("Existing file:");
+                        ui.checkbox(&mut self.style_merge, "Merge, keeping styles created elsewhere");
+                        ui.end_row();
                     });
 
-                // Table rows
-                let prompts_clone: Vec<String> = self.displayed_prompts.iter()
-                    .map(|p| p.text.clone())
-                    .collect();
+                ui.add_space(12.0);
 
-                egui::ScrollArea::vertical()
-                    .max_height(ui.available_height() - 10.0)
+                egui::Frame::new()
+                    .fill(colors::BASE)
+                    .inner_margin(8.0)
+                    .corner_radius(6.0)
+                    .stroke(egui::Stroke::new(1.0, colors::SURFACE0))
                     .show(ui, |ui| {
                         ui.set_width(ui.available_width());
+                        egui::ScrollArea::vertical()
+                            .max_height(260.0)
+                            .show(ui, |ui| {
+                                egui::Grid::new("style_rows_grid")
+                                    .num_columns(2)
+                                    .striped(true)
+                                    .spacing([10.0, 6.0])
+                                    .show(ui, |ui| {
+                                        for row in &mut self.style_rows {
+                                            if ui.add(egui::TextEdit::singleline(&mut row.name).desired_width(180.0)).changed() {
+                                                row.edited = true;
+                                            }
+                                            let preview: String = row.text.chars().take(80).collect();
+                                            let suffix = if row.text.chars().count() > 80 { "..." } else { "" };
+                                            ui.label(egui::RichText::new(format!("{}{}", preview, suffix)).color(colors::SUBTEXT));
+                                            ui.end_row();
+                                        }
+                                    });
+                            });
+                    });
 
-                        for (i, text) in prompts_clone.iter().enumerate() {
-                            let bg_color = if i % 2 == 0 {
-                                colors::BASE
-                            } else {
-                                colors::MANTLE
-                            };
+                ui.add_space(16.0);
 
-                            egui::Frame::new()
-                                .fill(bg_color)
-                                .inner_margin(egui::Margin::symmetric(12, 8))
-                                .show(ui, |ui| {
-                                    ui.horizontal(|ui| {
-                                        ui.with_layout(
-                                            egui::Layout::left_to_right(egui::Align::Center)
-                                                .with_main_wrap(true),
-                                            |ui| {
-                                                ui.set_width(ui.available_width() - 60.0);
-                                                self.render_highlighted_text(ui, text);
-                                            }
-                                        );
+                ui.vertical_centered(|ui| {
+                    ui.horizontal(|ui| {
+                        let export_btn = egui::Button::new(
+                            egui::RichText::new(format!("Export {} Styles", self.style_rows.len())).color(colors::CRUST)
+                        ).fill(colors::BLUE);
 
-                                        ui.with_layout(
-                                            egui::Layout::right_to_left(egui::Align::Center),
-                                            |ui| {
-                                                if ui.small_button("Copy").clicked() {
-                                                    ui.ctx().copy_text(text.clone());
-                                                }
-                                            }
-                                        );
-                                    });
-                                });
+                        if ui.add(export_btn).clicked() {
+                            self.export_styles();
+                        }
+
+                        if ui.button("Cancel").clicked() {
+                            self.show_styles_export = false;
                         }
                     });
+                });
             });
-    }
 
-    fn render_deduplicate_tab(&mut self, ui: &mut egui::Ui) {
-        // Toolbar card
-        egui::Frame::new()
-            .fill(colors::SURFACE0)
-            .inner_margin(16.0)
-            .corner_radius(8.0)
-            .show(ui, |ui| {
-                ui.set_width(ui.available_width());
+        self.show_styles_export &= open;
+    }
 
-                ui.horizontal(|ui| {
-                    ui.label("Similarity threshold:");
+    fn refresh_displayed_prompts(&mut self) {
+        if self.browse_filter.uses_clusters() {
+            let clusters = similarity::clusters_from_pairs(&self.similar_pairs);
+            if let Err(e) = self.library.db_mut().set_clusters(&clusters) {
+                self.set_status(format!("Couldn't filter by duplicate clusters: {}", e), true);
+            }
+        }
+        if let Err(e) = self.browse.reload(self.library.db(), &self.browse_filter) {
+            self.set_status(format!("Couldn't list prompts: {}", e), true);
+        }
+    }
 
-                    let slider = egui::Slider::new(&mut self.similarity_threshold, 0.5..=0.99)
-                        .show_value(false);
-                    ui.add(slider);
+    /// Every prompt Browse lists, read in full for exports
+    fn shown_prompts(&self) -> Vec<Prompt> {
+        self.library.query(&self.browse_filter).unwrap_or_default()
+    }
 
-                    // Read-only percentage display
-                    ui.label(egui::RichText::new(format!("{:.0}%", self.similarity_threshold * 100.0))
-                        .color(colors::TEXT));
+    /// Reload the sidebar lists, dropping filters on tags or collections that are gone
+    fn refresh_tag_lists(&mut self) {
+        self.tag_list = self.library.db().tags().unwrap_or_default();
+        self.collection_list = self.library.db().collections().unwrap_or_default();
+        self.model_list = self.library.db().models().unwrap_or_default();
+        self.source_file_list = self.library.db().source_files().unwrap_or_default();
+        if self.browse_filter.source_file.as_ref()
+            .is_some_and(|f| !self.source_file_list.iter().any(|(file, _)| file == f))
+        {
+            self.browse_filter.source_file = None;
+        }
+        if self.browse_filter.model.as_ref()
+            .is_some_and(|m| !self.model_list.iter().any(|(model, _)| model.eq_ignore_ascii_case(m)))
+        {
+            self.browse_filter.model = None;
+        }
+
+        let tags = &self.tag_list;
+        self.browse_filter.tags.retain(|t| tags.iter().any(|tag| tag.name.eq_ignore_ascii_case(t)));
+        let collections = &self.collection_list;
+        if self.browse_filter.collection.as_ref()
+            .is_some_and(|c| !collections.iter().any(|info| info.name.eq_ignore_ascii_case(c)))
+        {
+            self.browse_filter.collection = None;
+        }
+    }
+
+    /// Prompts a bulk action applies to: the ticked ones, or everything shown
+    /// when nothing is ticked
+    fn bulk_target(&self) -> Vec<i64> {
+        if self.selected_ids.is_empty() {
+            self.library.db().query_ids(&self.browse_filter).unwrap_or_default()
+        } else {
+            self.selected_ids.iter().copied().collect()
+        }
+    }
+
+    /// How many prompts `bulk_target` holds and what they are, without reading them
+    fn bulk_target_count(&self) -> (usize, String) {
+        if self.selected_ids.is_empty() {
+            (self.browse.len(), format!("{} shown", self.browse.len()))
+        } else {
+            (self.selected_ids.len(), format!("{} selected", self.selected_ids.len()))
+        }
+    }
+
+    fn tag_prompts(&mut self, remove: bool) {
+        let tags = db::parse_tags(&self.tag_input);
+        let [tag] = tags.as_slice() else {
+            self.set_status("Type a single tag name".to_string(), true);
+            return;
+        };
+        let tag = tag.clone();
+        let ids = self.bulk_target();
+        let result = if remove {
+            self.library.db_mut().remove_tag(&ids, &tag)
+        } else {
+            self.library.db_mut().add_tag(&ids, &tag)
+        };
+        self.refresh_counts();
+        match result {
+            Ok(changed) if remove => self.set_status(format!("Removed \"{}\" from {} prompts", tag, changed), false),
+            Ok(changed) => self.set_status(format!("Tagged {} prompts \"{}\"", changed, tag), false),
+            Err(e) => self.set_status(format!("Failed to update tags: {}", e), true),
+        }
+    }
+
+    fn add_to_collection(&mut self, name: String) {
+        let ids = self.bulk_target();
+        let result = self.library.db_mut().add_to_collection(&ids, &name);
+        self.refresh_counts();
+        match result {
+            Ok(added) => {
+                self.collection_input.clear();
+                self.set_status(format!("Added {} prompts to \"{}\"", added, name), false);
+            }
+            Err(e) => self.set_status(format!("Failed to update collection: {}", e), true),
+        }
+    }
+
+    fn remove_from_collection(&mut self, name: String) {
+        let ids = self.bulk_target();
+        let result = self.library.db_mut().remove_from_collection(&ids, &name);
+        self.selected_ids.clear();
+        self.refresh_counts();
+        match result {
+            Ok(removed) => self.set_status(format!("Removed {} prompts from \"{}\"", removed, name), false),
+            Err(e) => self.set_status(format!("Failed to update collection: {}", e), true),
+        }
+    }
+
+    fn import_wildcard_folder(&mut self, path: PathBuf) {
+        match self.library.import_wildcards(&path) {
+            Ok(WildcardFolderImport { summary, errors }) if summary.wildcards == 0 => {
+                let message = match errors.first() {
+                    Some(error) => format!("No wildcards imported: {}", error),
+                    None => "No wildcard files found in folder".to_string(),
+                };
+                self.set_status(message, true);
+            }
+            Ok(WildcardFolderImport { summary, errors }) => {
+                self.wildcard_list = self.library.db().wildcards().unwrap_or_default();
+                self.refresh_counts();
+
+                let error_info = if errors.is_empty() {
+                    String::new()
+                } else {
+                    format!(", {} files failed: {}", errors.len(), errors[0])
+                };
+                self.set_status(format!(
+                    "Imported {} wildcards ({} entries, {} new, {} templates{})",
+                    summary.wildcards, summary.entries, summary.added, summary.templates, error_info
+                ), !errors.is_empty());
+            }
+            Err(e) => {
+                self.set_status(e.to_string(), true);
+            }
+        }
+    }
+
+    fn open_detail(&mut self, id: i64) {
+        let db = self.library.db();
+        let loaded = db.get_prompt(id).and_then(|prompt| match prompt {
+            Some(prompt) => Ok(Some(DetailPanel::new(prompt, db.prompt_sources(id)?, db.prompt_versions(id)?))),
+            None => Ok(None),
+        });
+        match loaded {
+            Ok(detail) => self.detail = detail,
+            Err(e) => self.set_status(format!("Failed to load prompt: {}", e), true),
+        }
+    }
+
+    fn save_detail(&mut self) {
+        let Some(detail) = &self.detail else {
+            return;
+        };
+        let id = detail.prompt.id;
+        let result = detail.metadata()
+            .and_then(|metadata| self.library.db().set_metadata(id, &metadata).map_err(|e| e.to_string()));
+        match result {
+            Ok(()) => {
+                self.open_detail(id);
+                self.refresh_counts();
+                self.set_status(format!("Saved details of prompt #{}", id), false);
+            }
+            Err(e) => self.set_status(format!("Couldn't save details: {}", e), true),
+        }
+    }
+
+    fn render_detail_panel(&mut self, ctx: &egui::Context) {
+        let can_edit = self.can_edit();
+        let mut close = false;
+        let mut save = false;
+        let mut revert = false;
+        let mut restore = None;
+
+        egui::SidePanel::right("prompt_details")
+            .frame(egui::Frame::new().fill(colors::MANTLE).inner_margin(16.0))
+            .default_width(340.0)
+            .show(ctx, |ui| {
+                let Some(detail) = &mut self.detail else {
+                    return;
+                };
+                ui.horizontal(|ui| {
+                    ui.label(egui::RichText::new(format!("Prompt #{}", detail.prompt.id)).strong());
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        close = ui.small_button("Close").clicked();
+                    });
+                });
+                ui.add_space(8.0);
+
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    ui.label(&detail.prompt.text);
+                    if detail.sources.is_empty() {
+                        if let Some(source) = &detail.prompt.source_file {
+                            ui.label(egui::RichText::new(format!("From {}", source)).color(colors::SUBTEXT).small());
+                        }
+                    } else {
+                        let files: HashSet<&str> = detail.sources.iter().map(|s| s.source_file.as_str()).collect();
+                        let heading = match files.len() {
+                            1 => "Appears in 1 file".to_string(),
+                            n => format!("Appears in {} files", n),
+                        };
+                        ui.collapsing(egui::RichText::new(heading).color(colors::SUBTEXT).small(), |ui| {
+                            for source in &detail.sources {
+                                let place = match source.line {
+                                    Some(line) => format!("{}:{}", source.source_file, line),
+                                    None => source.source_file.clone(),
+                                };
+                                ui.label(egui::RichText::new(format!("{}  ·  {}", place, source.imported_at))
+                                    .color(colors::SUBTEXT)
+                                    .small());
+                            }
+                        });
+                    }
+
+                    if !detail.versions.is_empty() {
+                        let heading = match detail.versions.len() {
+                            1 => "Edited once".to_string(),
+                            n => format!("Edited {} times", n),
+                        };
+                        ui.collapsing(egui::RichText::new(heading).color(colors::SUBTEXT).small(), |ui| {
+                            for version in &detail.versions {
+                                ui.horizontal(|ui| {
+                                    ui.label(egui::RichText::new(version.reason.label()).small().strong());
+                                    ui.label(egui::RichText::new(&version.created_at).color(colors::SUBTEXT).small());
+                                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                                        let restore_btn = egui::Button::new("Restore").small();
+                                        if ui.add_enabled(can_edit, restore_btn)
+                                            .on_hover_text("Set the text back to how it was before this change")
+                                            .clicked()
+                                        {
+                                            restore = Some(version.id);
+                                        }
+                                    });
+                                });
+                                word_diff_label(ui, &version.old_text, &version.new_text);
+                                ui.add_space(6.0);
+                            }
+                        });
+                    }
+
+                    ui.add_space(12.0);
+
+                    ui.add_enabled_ui(can_edit, |ui| {
+                        ui.horizontal(|ui| {
+                            ui.label("Rating:");
+                            for stars in 1..=5u8 {
+                                let lit = detail.rating.is_some_and(|rating| rating >= stars);
+                                let star = egui::RichText::new(if lit { "★" } else { "☆" })
+                                    .color(if lit { colors::YELLOW } else { colors::SUBTEXT });
+                                if ui.add(egui::Button::new(star).frame(false)).clicked() {
+                                    // Clicking the current rating clears it
+                                    detail.rating = (detail.rating != Some(stars)).then_some(stars);
+                                }
+                            }
+                            ui.add_space(12.0);
+                            ui.checkbox(&mut detail.favorite, "Favorite");
+                        });
+
+                        ui.add_space(8.0);
+                        ui.label("Negative prompt:");
+                        ui.add(egui::TextEdit::multiline(&mut detail.negative_prompt)
+                            .desired_rows(3)
+                            .desired_width(f32::INFINITY));
+
+                        ui.add_space(8.0);
+                        egui::Grid::new("detail_settings_grid")
+                            .num_columns(2)
+                            .spacing([10.0, 6.0])
+                            .show(ui, |ui| {
+                                for (label, value, hint) in [
+                                    ("Model:", &mut detail.model, "checkpoint name"),
+                                    ("Sampler:", &mut detail.sampler, "Euler a"),
+                                    ("Steps:", &mut detail.steps, "20"),
+                                    ("CFG scale:", &mut detail.cfg_scale, "7"),
+                                    ("Seed:", &mut detail.seed, "-1"),
+                                ] {
+                                    ui.label(label);
+                                    ui.add(egui::TextEdit::singleline(value).hint_text(hint).desired_width(200.0));
+                                    ui.end_row();
+                                }
+                            });
+
+                        ui.add_space(8.0);
+                        ui.label("Notes:");
+                        ui.add(egui::TextEdit::multiline(&mut detail.notes)
+                            .desired_rows(4)
+                            .desired_width(f32::INFINITY));
+                    });
+
+                    ui.add_space(16.0);
+
+                    ui.horizontal(|ui| {
+                        let modified = detail.is_modified();
+                        let save_btn = egui::Button::new(
+                            egui::RichText::new("Save").color(colors::CRUST)
+                        ).fill(colors::GREEN);
+                        save = ui.add_enabled(can_edit && modified, save_btn).clicked();
+                        revert = ui.add_enabled(modified, egui::Button::new("Revert")).clicked();
+                    });
+                });
+            });
+
+        if save {
+            self.save_detail();
+        } else if revert && let Some(detail) = &mut self.detail {
+            detail.revert();
+        } else if let Some(version_id) = restore {
+            self.restore_version(version_id);
+        }
+        if close {
+            self.detail = None;
+        }
+    }
+
+    fn restore_version(&mut self, version_id: i64) {
+        match self.library.restore_version(version_id) {
+            Ok(id) => {
+                self.open_detail(id);
+                self.refresh_counts();
+                self.set_status(format!("Restored an earlier version of prompt #{}", id), false);
+            }
+            Err(e) => self.set_status(format!("Couldn't restore: {}", e), true),
+        }
+    }
+
+    /// Whether the library can be changed: it isn't read-only and no import
+    /// is holding its write lock
+    fn can_edit(&self) -> bool {
+        !self.library.is_read_only() && self.import_job.is_none()
+    }
+
+    /// Edit a prompt's text in place, unless the library can't be changed
+    fn start_editing(&mut self, id: i64, text: &str) {
+        if self.can_edit() {
+            self.editor = Some(PromptEditor::new(id, text));
+        }
+    }
+
+    /// Text box with Save and Cancel for `self.editor`, shared by Browse rows and
+    /// duplicate pairs. Ctrl+Enter saves and Escape cancels.
+    fn render_prompt_editor(&mut self, ui: &mut egui::Ui) -> Option<EditorAction> {
+        let can_edit = self.can_edit();
+        let editor = self.editor.as_mut()?;
+        let mut action = None;
+
+        let response = ui.add(
+            egui::TextEdit::multiline(&mut editor.text)
+                .desired_rows(3)
+                .desired_width(f32::INFINITY)
+        );
+        if editor.focus {
+            response.request_focus();
+            editor.focus = false;
+        }
+        if response.changed() {
+            editor.collision = None;
+        }
+        if can_edit && response.has_focus() && ui.input_mut(|i| i.consume_key(egui::Modifiers::COMMAND, egui::Key::Enter)) {
+            action = Some(EditorAction::Save);
+        }
+        if response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+            action = Some(EditorAction::Cancel);
+        }
+
+        if let Some(existing) = &editor.collision {
+            ui.label(egui::RichText::new(format!("Prompt #{} already has this text:", existing.id)).color(colors::YELLOW));
+            ui.label(egui::RichText::new(&existing.text).color(colors::SUBTEXT).small());
+        }
+
+        ui.horizontal(|ui| {
+            if let Some(existing) = &editor.collision {
+                let merge_btn = egui::Button::new(
+                    egui::RichText::new(format!("Merge into #{}", existing.id)).color(colors::CRUST)
+                ).fill(colors::BLUE);
+                if ui.add_enabled(can_edit, merge_btn)
+                    .on_hover_text(format!(
+                        "Delete this prompt and give #{} its tags, collections and sources",
+                        existing.id
+                    ))
+                    .clicked()
+                {
+                    action = Some(EditorAction::Merge(existing.id));
+                }
+            } else {
+                let save_btn = egui::Button::new(
+                    egui::RichText::new("Save").color(colors::CRUST)
+                ).fill(colors::GREEN);
+                if ui.add_enabled(can_edit && !editor.text.trim().is_empty(), save_btn).clicked() {
+                    action = Some(EditorAction::Save);
+                }
+            }
+            if ui.button("Cancel").clicked() {
+                action = Some(EditorAction::Cancel);
+            }
+        });
+        action
+    }
+
+    fn apply_editor_action(&mut self, action: EditorAction) {
+        let Some(editor) = &mut self.editor else {
+            return;
+        };
+        let id = editor.id;
+        match action {
+            EditorAction::Cancel => self.editor = None,
+            EditorAction::Save => match self.library.edit_prompt(id, &editor.text) {
+                Ok(()) => {
+                    self.editor = None;
+                    self.prompt_edited(id);
+                    self.set_status(format!("Saved prompt #{}", id), false);
+                }
+                Err(prompt_dedup::Error::DuplicateText(existing)) => {
+                    editor.collision = self.library.db().get_prompt(existing).ok().flatten();
+                }
+                Err(e) => self.set_status(format!("Couldn't save prompt #{}: {}", id, e), true),
+            },
+            EditorAction::Merge(into) => match self.library.merge_prompt(id, into) {
+                Ok(()) => {
+                    self.editor = None;
+                    self.forget_pairs(id);
+                    self.refresh_counts();
+                    self.set_status(format!("Merged prompt #{} into #{}", id, into), false);
+                }
+                Err(e) => self.set_status(format!("Couldn't merge prompt #{}: {}", id, e), true),
+            },
+        }
+    }
+
+    /// Show a prompt's new text everywhere it is on screen
+    fn prompt_edited(&mut self, id: i64) {
+        self.refresh_counts();
+        let Ok(Some(prompt)) = self.library.db().get_prompt(id) else {
+            return;
+        };
+        if let Some(detail) = &mut self.detail
+            && detail.prompt.id == id
+        {
+            detail.prompt.text = prompt.text.clone();
+            detail.versions = self.library.db().prompt_versions(id).unwrap_or_default();
+        }
+        for pair in &mut self.similar_pairs {
+            if pair.id_a == id {
+                pair.text_a = prompt.text.clone();
+            } else if pair.id_b == id {
+                pair.text_b = prompt.text.clone();
+            } else {
+                continue;
+            }
+            pair.similarity = similarity::jaccard_similarity(&pair.text_a, &pair.text_b);
+        }
+    }
+
+    fn refresh_counts(&mut self) {
+        self.prompt_count = self.library.db().count().unwrap_or(0);
+        // Close the detail panel when its prompt was deleted or merged away
+        if let Some(detail) = &self.detail
+            && !matches!(self.library.db().get_prompt(detail.prompt.id), Ok(Some(_)))
+        {
+            self.detail = None;
+        }
+        if self.show_import_history {
+            self.import_batches = self.library.db().import_batches().unwrap_or_default();
+        }
+        self.refresh_tag_lists();
+        self.refresh_displayed_prompts();
+    }
+
+    fn scan_for_duplicates(&mut self) {
+        self.similar_pairs = self.library.scan(self.similarity_threshold, self.compare_negatives).unwrap_or_default();
+        self.current_pair_index = 0;
+        self.is_scanning = false;
+        if self.browse_filter.uses_clusters() {
+            self.refresh_displayed_prompts();
+        }
+    }
+
+    /// Delete one prompt of a pair, handing its sources to the prompt `kept`
+    fn delete_duplicate(&mut self, id: i64, kept: i64) {
+        if let Err(e) = self.library.db_mut().delete_duplicate(id, kept) {
+            self.set_status(format!("Failed to delete prompt: {}", e), true);
+        }
+        self.forget_pairs(id);
+        self.refresh_counts();
+    }
+
+    /// Drop the pairs a deleted prompt was part of
+    fn forget_pairs(&mut self, id: i64) {
+        self.similar_pairs.retain(|p| p.id_a != id && p.id_b != id);
+
+        // Adjust index if needed
+        if self.current_pair_index >= self.similar_pairs.len() && !self.similar_pairs.is_empty() {
+            self.current_pair_index = self.similar_pairs.len() - 1;
+        }
+    }
+
+    fn remove_all_duplicates(&mut self) {
+        // Keep first, delete second from each pair
+        let decisions: Vec<Decision> = self.similar_pairs.iter().map(Decision::keep_first).collect();
+        let result = self.library.resolve(&decisions);
+
+        self.similar_pairs.clear();
+        self.current_pair_index = 0;
+        self.refresh_counts();
+        match result {
+            Ok(summary) => self.set_status(format!("Removed {} duplicate prompts", summary.deleted.len()), false),
+            Err(e) => self.set_status(e.to_string(), true),
+        }
+    }
+
+    fn export_report(&mut self, format: ReportFormat) {
+        if let Some(path) = rfd::FileDialog::new()
+            .add_filter(format.extension(), &[format.extension()])
+            .set_file_name(format!("dedup-report.{}", format.extension()))
+            .save_file()
+        {
+            match report::export_report(&self.similar_pairs, self.similarity_threshold, format, &path) {
+                Ok(decisions_path) => {
+                    let decisions_name = decisions_path.file_name().unwrap_or_default().to_string_lossy();
+                    self.set_status(format!(
+                        "Exported report of {} pairs, edit {} and apply it to act on them",
+                        self.similar_pairs.len(),
+                        decisions_name
+                    ), false);
+                }
+                Err(e) => {
+                    self.set_status(format!("Failed to export: {}", e), true);
+                }
+            }
+        }
+    }
+
+    fn apply_decisions_file(&mut self) {
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("Decisions", &["csv", "json"])
+            .pick_file()
+        else {
+            return;
+        };
+
+        let decisions = match report::read_decisions(&path) {
+            Ok(decisions) => decisions,
+            Err(e) => {
+                self.set_status(e.to_string(), true);
+                return;
+            }
+        };
+
+        // Prompts removed or edited since the report was written are skipped
+        let summary = match self.library.resolve(&decisions) {
+            Ok(summary) => summary,
+            Err(e) => {
+                self.set_status(e.to_string(), true);
+                self.refresh_counts();
+                return;
+            }
+        };
+        self.similar_pairs.retain(|p| !summary.deleted.contains(&p.id_a) && !summary.deleted.contains(&p.id_b));
+
+        // Pairs the reviewer chose to keep are settled, drop them from the queue too
+        self.similar_pairs.retain(|p| !decisions.iter().any(|d| {
+            d.action == Action::KeepBoth && d.id_a == p.id_a && d.id_b == p.id_b
+        }));
+        if self.current_pair_index >= self.similar_pairs.len() {
+            self.current_pair_index = self.similar_pairs.len().saturating_sub(1);
+        }
+        self.refresh_counts();
+
+        let mut message = format!(
+            "Applied {} decisions: deleted {}, kept {} pairs",
+            decisions.len(),
+            summary.deleted.len(),
+            summary.kept
+        );
+        if summary.stale > 0 {
+            message.push_str(&format!(", skipped {} changed or missing", summary.stale));
+        }
+        self.set_status(message, false);
+    }
+
+    fn render_highlighted_text(&self, ui: &mut egui::Ui, text: &str) {
+        if self.browse_filter.search.is_empty() {
+            ui.label(text);
+            return;
+        }
+
+        let query_lower = self.browse_filter.search.to_lowercase();
+        let text_lower = text.to_lowercase();
+
+        let mut job = egui::text::LayoutJob::default();
+        let mut last_end = 0;
+
+        for (start, _) in text_lower.match_indices(&query_lower) {
+            if start > last_end {
+                job.append(
+                    &text[last_end..start],
+                    0.0,
+                    egui::TextFormat::simple(egui::FontId::default(), colors::TEXT),
+                );
+            }
+
+            let end = start + self.browse_filter.search.len();
+            job.append(
+                &text[start..end],
+                0.0,
+                egui::TextFormat {
+                    color: colors::CRUST,
+                    background: colors::YELLOW,
+                    ..egui::TextFormat::simple(egui::FontId::default(), colors::CRUST)
+                },
+            );
+
+            last_end = end;
+        }
+
+        if last_end < text.len() {
+            job.append(
+                &text[last_end..],
+                0.0,
+                egui::TextFormat::simple(egui::FontId::default(), colors::TEXT),
+            );
+        }
+
+        ui.label(job);
+    }
+
+    fn render_browse_tab(&mut self, ui: &mut egui::Ui) {
+        // Toolbar card
+        egui::Frame::new()
+            .fill(colors::SURFACE0)
+            .inner_margin(16.0)
+            .corner_radius(8.0)
+            .show(ui, |ui| {
+                ui.set_width(ui.available_width());
+
+                // First row: Import, Export, and actions
+                ui.horizontal(|ui| {
+                    let import_btn = egui::Button::new(
+                        egui::RichText::new("Import File").color(colors::CRUST)
+                    ).fill(colors::GREEN);
+
+                    let can_import = self.can_edit();
+                    if ui.add_enabled(can_import, import_btn).clicked()
+                        && let Some(path) = rfd::FileDialog::new()
+                            .add_filter("Text files", &["txt"])
+                            .pick_file()
+                    {
+                        self.import_file(path);
+                    }
+
+                    ui.add_space(8.0);
+
+                    if ui.add_enabled(can_import, egui::Button::new("Import Wildcards")).clicked()
+                        && let Some(path) = rfd::FileDialog::new().pick_folder()
+                    {
+                        self.import_wildcard_folder(path);
+                    }
+
+                    ui.add_space(8.0);
+
+                    if ui.button("Import History").clicked() {
+                        self.open_import_history();
+                    }
+
+                    ui.add_space(8.0);
+
+                    let export_btn = egui::Button::new(
+                        egui::RichText::new("Export").color(colors::CRUST)
+                    ).fill(colors::BLUE);
+
+                    if ui.add(export_btn).clicked() {
+                        self.open_export_options();
+                    }
+
+                    egui::ComboBox::from_id_salt("export_format")
+                        .selected_text(self.export_format.label())
+                        .show_ui(ui, |ui| {
+                            for format in ExportFormat::ALL {
+                                ui.selectable_value(&mut self.export_format, format, format.label());
+                            }
+                        });
+
+                    ui.menu_button("Export As", |ui| {
+                        if ui.button("Wildcard Files...").clicked() {
+                            self.show_wildcard_export = true;
+                            ui.close_menu();
+                        }
+                        if ui.button("A1111 Styles...").clicked() {
+                            self.open_styles_export();
+                            ui.close_menu();
+                        }
+                        if ui.button("One File per Source...").clicked() {
+                            self.open_split_export();
+                            ui.close_menu();
+                        }
+                    });
+
+                    if self.export_format.has_columns() {
+                        ui.menu_button("Columns", |ui| {
+                            for column in ExportColumn::ALL {
+                                let mut checked = self.export_columns.contains(&column);
+                                if ui.checkbox(&mut checked, column.name()).changed() {
+                                    if checked {
+                                        self.export_columns.push(column);
+                                    } else {
+                                        self.export_columns.retain(|c| *c != column);
+                                    }
+                                }
+                            }
+                        });
+                    }
+
+                    ui.add_space(16.0);
+
+                    if let Some(result) = &self.last_import_result {
+                        ui.label(
+                            egui::RichText::new(format!(
+                                "Last: {} (+{}, -{} dupes)",
+                                result.file_name, result.added, result.skipped
+                            ))
+                            .color(colors::SUBTEXT)
+                        );
+                    }
+                });
+
+                ui.add_space(12.0);
+
+                // Second row: Search
+                ui.horizontal(|ui| {
+                    ui.label("Search:");
+                    let response = ui.add(
+                        egui::TextEdit::singleline(&mut self.browse_filter.search)
+                            .hint_text("Type to filter prompts...")
+                            .desired_width(400.0)
+                    );
+                    if response.changed() {
+                        self.refresh_displayed_prompts();
+                    }
+
+                    if ui.button("Clear").clicked() {
+                        self.browse_filter.search.clear();
+                        self.refresh_displayed_prompts();
+                    }
+
+                    if ui.checkbox(&mut self.browse_filter.search_history, "Earlier versions")
+                        .on_hover_text("Also find prompts whose text matched before an edit")
+                        .changed()
+                        && !self.browse_filter.search.is_empty()
+                    {
+                        self.refresh_displayed_prompts();
+                    }
+
+                    ui.add_space(16.0);
+                    ui.label("Sort:");
+                    let mut sort = self.browse_filter.sort;
+                    egui::ComboBox::from_id_salt("browse_sort")
+                        .selected_text(sort.label())
+                        .show_ui(ui, |ui| {
+                            for option in PromptSort::ALL {
+                                ui.selectable_value(&mut sort, option, option.label());
+                            }
+                        });
+                    if sort != self.browse_filter.sort {
+                        self.browse_filter.sort = sort;
+                        self.refresh_displayed_prompts();
+                    }
+
+                    ui.add_space(8.0);
+                    ui.menu_button("More Filters", |ui| self.render_facet_menu(ui));
+
+                    if !self.wildcard_list.is_empty() {
+                        ui.add_space(16.0);
+                        ui.label("Wildcard:");
+
+                        let selected_text = match &self.browse_filter.wildcard {
+                            Some(name) => format!("__{}__", name),
+                            None => "All prompts".to_string(),
+                        };
+                        let mut selected = self.browse_filter.wildcard.clone();
+                        egui::ComboBox::from_id_salt("wildcard_filter")
+                            .selected_text(selected_text)
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut selected, None, "All prompts");
+                                for wildcard in &self.wildcard_list {
+                                    ui.selectable_value(
+                                        &mut selected,
+                                        Some(wildcard.name.clone()),
+                                        format!("__{}__ ({})", wildcard.name, wildcard.entry_count),
+                                    );
+                                }
+                            });
+                        if selected != self.browse_filter.wildcard {
+                            self.browse_filter.wildcard = selected;
+                            self.refresh_displayed_prompts();
+                        }
+                    }
+
+                    ui.add_space(16.0);
+                    ui.label(format!("Showing: {}", self.browse.len()));
+
+                    ui.add_space(16.0);
+                    if ui.button("Select Shown").clicked() {
+                        self.selected_ids.extend(self.library.db().query_ids(&self.browse_filter).unwrap_or_default());
+                    }
+                    if !self.selected_ids.is_empty() {
+                        ui.label(format!("{} selected", self.selected_ids.len()));
+                        if ui.button("Clear Selection").clicked() {
+                            self.selected_ids.clear();
+                        }
+                    }
+                });
+
+                let facets = self.browse_filter.facets();
+                if !facets.is_empty() {
+                    ui.add_space(8.0);
+                    let mut remove = None;
+                    ui.horizontal_wrapped(|ui| {
+                        for (facet, description) in facets {
+                            let chip = egui::Button::new(
+                                egui::RichText::new(format!("{}  ✕", description)).color(colors::TEXT)
+                            )
+                                .fill(colors::SURFACE1)
+                                .corner_radius(10.0);
+                            if ui.add(chip).on_hover_text("Remove this filter").clicked() {
+                                remove = Some(facet);
+                            }
+                        }
+                    });
+                    if let Some(facet) = remove {
+                        self.browse_filter.clear_facet(facet);
+                        self.facet_draft = FacetDraft::new(&self.browse_filter);
+                        self.refresh_displayed_prompts();
+                    }
+                }
+
+                ui.add_space(12.0);
+
+                // Third row: bulk tagging and collections
+                ui.horizontal(|ui| {
+                    let can_edit = self.can_edit();
+                    let (count, target) = self.bulk_target_count();
+                    let has_target = can_edit && count > 0;
+
+                    ui.label("Tag:");
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.tag_input)
+                            .hint_text("tag name")
+                            .desired_width(160.0)
+                    );
+                    let has_tag = !self.tag_input.trim().is_empty();
+                    if ui.add_enabled(has_target && has_tag, egui::Button::new("Add Tag")).clicked() {
+                        self.tag_prompts(false);
+                    }
+                    if ui.add_enabled(has_target && has_tag, egui::Button::new("Remove Tag")).clicked() {
+                        self.tag_prompts(true);
+                    }
+
+                    ui.add_space(16.0);
+
+                    let mut add_to = None;
+                    ui.add_enabled_ui(has_target, |ui| {
+                        ui.menu_button("Add to Collection", |ui| {
+                            for collection in &self.collection_list {
+                                if ui.button(&collection.name).clicked() {
+                                    add_to = Some(collection.name.clone());
+                                    ui.close_menu();
+                                }
+                            }
+                            if !self.collection_list.is_empty() {
+                                ui.separator();
+                            }
+                            ui.horizontal(|ui| {
+                                ui.add(
+                                    egui::TextEdit::singleline(&mut self.collection_input)
+                                        .hint_text("New collection")
+                                        .desired_width(140.0)
+                                );
+                                let name = self.collection_input.trim();
+                                if ui.add_enabled(!name.is_empty(), egui::Button::new("Create")).clicked() {
+                                    add_to = Some(name.to_string());
+                                    ui.close_menu();
+                                }
+                            });
+                        });
+                    });
+                    if let Some(name) = add_to {
+                        self.add_to_collection(name);
+                    }
+
+                    if let Some(collection) = self.browse_filter.collection.clone()
+                        && ui.add_enabled(has_target, egui::Button::new("Remove from Collection")).clicked()
+                    {
+                        self.remove_from_collection(collection);
+                    }
+
+                    ui.add_space(16.0);
+                    ui.label(egui::RichText::new(format!("Applies to the {} prompts", target)).color(colors::SUBTEXT));
+
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        if ui.button("Tag Rules...").clicked() {
+                            self.open_tag_rules();
+                        }
+                    });
+                });
+            });
+
+        ui.add_space(16.0);
+
+        ui.horizontal_top(|ui| {
+            let height = ui.available_height();
+
+            // Tags and collections sidebar
+            egui::Frame::new()
+                .fill(colors::BASE)
+                .inner_margin(12.0)
+                .corner_radius(8.0)
+                .stroke(Stroke::new(1.0, colors::SURFACE0))
+                .show(ui, |ui| {
+                    ui.set_width(180.0);
+                    ui.set_min_height(height - 24.0);
+                    ui.vertical(|ui| self.render_browse_sidebar(ui));
+                });
+
+            ui.vertical(|ui| self.render_prompt_table(ui));
+        });
+    }
+
+    /// Source file, import date, length and syntax facets, under "More Filters"
+    fn render_facet_menu(&mut self, ui: &mut egui::Ui) {
+        let mut changed = false;
+        let invalid = |valid: bool| if valid { colors::TEXT } else { colors::RED };
+
+        egui::Grid::new("facet_grid")
+            .num_columns(2)
+            .spacing([8.0, 6.0])
+            .show(ui, |ui| {
+                ui.label("Source file:");
+                let file_text = self.browse_filter.source_file.clone().unwrap_or_else(|| "Any file".to_string());
+                ui.menu_button(file_text, |ui| {
+                    egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                        if ui.selectable_label(self.browse_filter.source_file.is_none(), "Any file").clicked() {
+                            self.browse_filter.source_file = None;
+                            changed = true;
+                            ui.close_menu();
+                        }
+                        for (file, count) in &self.source_file_list {
+                            let active = self.browse_filter.source_file.as_ref() == Some(file);
+                            if ui.selectable_label(active, format!("{} ({})", file, count)).clicked() {
+                                self.browse_filter.source_file = Some(file.clone());
+                                changed = true;
+                                ui.close_menu();
+                            }
+                        }
+                    });
+                });
+                ui.end_row();
+
+                let draft = &mut self.facet_draft;
+                ui.label("Imported:");
+                ui.horizontal(|ui| {
+                    for (i, day) in [&mut draft.imported_from, &mut draft.imported_to].into_iter().enumerate() {
+                        if i == 1 {
+                            ui.label("to");
+                        }
+                        let valid = day.trim().is_empty() || db::is_date(day.trim());
+                        changed |= ui.add(
+                            egui::TextEdit::singleline(day)
+                                .hint_text("YYYY-MM-DD")
+                                .text_color(invalid(valid))
+                                .desired_width(90.0)
+                        ).changed();
+                    }
+                });
+                ui.end_row();
+
+                ui.label("Length:");
+                ui.horizontal(|ui| {
+                    for (i, length) in [&mut draft.min_length, &mut draft.max_length].into_iter().enumerate() {
+                        if i == 1 {
+                            ui.label("to");
+                        }
+                        let valid = length.trim().is_empty() || length.trim().parse::<usize>().is_ok();
+                        changed |= ui.add(
+                            egui::TextEdit::singleline(length)
+                                .hint_text(if i == 0 { "min" } else { "max" })
+                                .text_color(invalid(valid))
+                                .desired_width(60.0)
+                        ).changed();
+                    }
+                    ui.label("characters");
+                });
+                ui.end_row();
+            });
+
+        ui.add_space(4.0);
+        changed |= ui.checkbox(&mut self.browse_filter.has_lora, "Has a LoRA")
+            .on_hover_text("Loads a network with <lora:...> or <lyco:...>")
+            .changed();
+        changed |= ui.checkbox(&mut self.browse_filter.has_weights, "Has weights")
+            .on_hover_text("Weights part of the prompt, like (red hair:1.2)")
+            .changed();
+        changed |= ui.checkbox(&mut self.browse_filter.in_cluster, "In a duplicate cluster")
+            .on_hover_text("Part of a similar pair from the last Find Duplicates scan")
+            .changed();
+        if self.browse_filter.in_cluster && self.similar_pairs.is_empty() {
+            ui.label(egui::RichText::new("Run Find Duplicates first").color(colors::SUBTEXT).small());
+        }
+
+        if changed {
+            self.facet_draft.apply(&mut self.browse_filter);
+            self.refresh_displayed_prompts();
+        }
+    }
+
+    fn render_browse_sidebar(&mut self, ui: &mut egui::Ui) {
+        let can_edit = self.can_edit();
+        let mut changed = false;
+        let mut delete_tag = None;
+        let mut delete_collection = None;
+
+        ui.label(egui::RichText::new("Filters").strong());
+        ui.add_space(4.0);
+        changed |= ui.checkbox(&mut self.browse_filter.favorites_only, "Favorites only").changed();
+        let rating_text = match self.browse_filter.min_rating {
+            Some(rating) => format!("{}+ stars", rating),
+            None => "Any rating".to_string(),
+        };
+        egui::ComboBox::from_id_salt("rating_filter")
+            .selected_text(rating_text)
+            .show_ui(ui, |ui| {
+                changed |= ui.selectable_value(&mut self.browse_filter.min_rating, None, "Any rating").changed();
+                for rating in 1..=5u8 {
+                    changed |= ui.selectable_value(
+                        &mut self.browse_filter.min_rating,
+                        Some(rating),
+                        format!("{}+ stars", rating),
+                    ).changed();
+                }
+            });
+        if !self.model_list.is_empty() {
+            let model_text = self.browse_filter.model.clone().unwrap_or_else(|| "Any model".to_string());
+            egui::ComboBox::from_id_salt("model_filter")
+                .selected_text(model_text)
+                .show_ui(ui, |ui| {
+                    changed |= ui.selectable_value(&mut self.browse_filter.model, None, "Any model").changed();
+                    for (model, count) in &self.model_list {
+                        changed |= ui.selectable_value(
+                            &mut self.browse_filter.model,
+                            Some(model.clone()),
+                            format!("{} ({})", model, count),
+                        ).changed();
+                    }
+                });
+        }
+
+        ui.add_space(16.0);
+        ui.label(egui::RichText::new("Tags").strong());
+        ui.add_space(4.0);
+        if self.tag_list.is_empty() {
+            ui.label(egui::RichText::new("No tags yet").color(colors::SUBTEXT));
+        } else {
+            ui.horizontal(|ui| {
+                ui.label("Match:");
+                for tag_match in [TagMatch::Any, TagMatch::All] {
+                    if ui.selectable_label(self.browse_filter.tag_match == tag_match, tag_match.label()).clicked() {
+                        self.browse_filter.tag_match = tag_match;
+                        changed = !self.browse_filter.tags.is_empty();
+                    }
+                }
+            });
+            egui::ScrollArea::vertical()
+                .id_salt("tag_sidebar")
+                .max_height(ui.available_height() / 2.0)
+                .show(ui, |ui| {
+                    for tag in &self.tag_list {
+                        let active = self.browse_filter.tags.iter().any(|t| t.eq_ignore_ascii_case(&tag.name));
+                        let response = ui.selectable_label(active, format!("{} ({})", tag.name, tag.prompt_count));
+                        if response.clicked() {
+                            if active {
+                                self.browse_filter.tags.retain(|t| !t.eq_ignore_ascii_case(&tag.name));
+                            } else {
+                                self.browse_filter.tags.push(tag.name.clone());
+                            }
+                            changed = true;
+                        }
+                        if can_edit {
+                            response.context_menu(|ui| {
+                                if ui.button("Delete Tag").clicked() {
+                                    delete_tag = Some(tag.name.clone());
+                                    ui.close_menu();
+                                }
+                            });
+                        }
+                    }
+                });
+            if !self.browse_filter.tags.is_empty() && ui.small_button("Clear Tags").clicked() {
+                self.browse_filter.tags.clear();
+                changed = true;
+            }
+        }
+
+        ui.add_space(16.0);
+        ui.label(egui::RichText::new("Collections").strong());
+        ui.add_space(4.0);
+        if self.collection_list.is_empty() {
+            ui.label(egui::RichText::new("No collections yet").color(colors::SUBTEXT));
+        } else {
+            if ui.selectable_label(self.browse_filter.collection.is_none(), "All prompts").clicked() {
+                self.browse_filter.collection = None;
+                changed = true;
+            }
+            egui::ScrollArea::vertical()
+                .id_salt("collection_sidebar")
+                .show(ui, |ui| {
+                    for collection in &self.collection_list {
+                        let active = self.browse_filter.collection.as_ref()
+                            .is_some_and(|c| c.eq_ignore_ascii_case(&collection.name));
+                        let response = ui.selectable_label(
+                            active,
+                            format!("{} ({})", collection.name, collection.prompt_count),
+                        );
+                        if response.clicked() && !active {
+                            self.browse_filter.collection = Some(collection.name.clone());
+                            changed = true;
+                        }
+                        if can_edit {
+                            response.context_menu(|ui| {
+                                if ui.button("Delete Collection").clicked() {
+                                    delete_collection = Some(collection.name.clone());
+                                    ui.close_menu();
+                                }
+                            });
+                        }
+                    }
+                });
+        }
+
+        if let Some(name) = delete_tag {
+            match self.library.db().delete_tag(&name) {
+                Ok(()) => self.set_status(format!("Deleted tag \"{}\"", name), false),
+                Err(e) => self.set_status(format!("Failed to delete tag: {}", e), true),
+            }
+            self.refresh_counts();
+        } else if let Some(name) = delete_collection {
+            match self.library.db().delete_collection(&name) {
+                Ok(()) => self.set_status(format!("Deleted collection \"{}\", its prompts were kept", name), false),
+                Err(e) => self.set_status(format!("Failed to delete collection: {}", e), true),
+            }
+            self.refresh_counts();
+        } else if changed {
+            self.refresh_displayed_prompts();
+        }
+    }
+
+    fn render_prompt_table(&mut self, ui: &mut egui::Ui) {
+        // Prompts table card
+        egui::Frame::new()
+            .fill(colors::BASE)
+            .inner_margin(0.0)
+            .corner_radius(8.0)
+            .stroke(Stroke::new(1.0, colors::SURFACE0))
+            .show(ui, |ui| {
+                ui.set_width(ui.available_width());
+
+                // Table header
+                egui::Frame::new()
+                    .fill(colors::SURFACE0)
+                    .inner_margin(egui::Margin::symmetric(12, 8))
+                    .show(ui, |ui| {
+                        ui.set_width(ui.available_width());
+                        let title = std::iter::once("Prompts".to_string())
+                            .chain(self.browse_filter.describe())
+                            .collect::<Vec<_>>()
+                            .join(" · ");
+                        ui.label(egui::RichText::new(title).strong())
+                            .on_hover_text("Double-click a prompt to edit it");
+                    });
+
+                // Table rows, laying out only the ones in view
+                let mut open_detail = None;
+                let mut start_editing = None;
+                let mut editor_action = None;
+
+                egui::ScrollArea::vertical()
+                    .max_height(ui.available_height() - 10.0)
+                    .show_viewport(ui, |ui, viewport| {
+                        ui.set_width(ui.available_width());
+
+                        let visible = self.browse.visible_rows(ui.available_width(), viewport.min.y..viewport.max.y);
+                        ui.add_space(self.browse.height_of(0..visible.start));
+
+                        let rows = self.browse.rows(self.library.db(), visible.clone()).unwrap_or_else(|e| {
+                            self.set_status(format!("Couldn't read prompts: {}", e), true);
+                            Vec::new()
+                        });
+                        for (i, prompt) in rows {
+                            let Prompt { id, text, is_template, .. } = &prompt;
+                            let PromptMetadata { rating, favorite, .. } = &prompt.metadata;
+                            let bg_color = if i % 2 == 0 {
+                                colors::BASE
+                            } else {
+                                colors::MANTLE
+                            };
+
+                            let editing = self.editor.as_ref().is_some_and(|editor| editor.id == *id);
+                            let row = egui::Frame::new()
+                                .fill(bg_color)
+                                .inner_margin(egui::Margin::symmetric(12, 8))
+                                .show(ui, |ui| {
+                                    ui.horizontal(|ui| {
+                                        let mut selected = self.selected_ids.contains(id);
+                                        if ui.checkbox(&mut selected, "").changed() {
+                                            if selected {
+                                                self.selected_ids.insert(*id);
+                                            } else {
+                                                self.selected_ids.remove(id);
+                                            }
+                                        }
+
+                                        ui.with_layout(
+                                            egui::Layout::left_to_right(egui::Align::Center)
+                                                .with_main_wrap(true),
+                                            |ui| {
+                                                ui.set_width(ui.available_width() - if *is_template { 190.0 } else { 120.0 });
+                                                if editing {
+                                                    ui.vertical(|ui| editor_action = self.render_prompt_editor(ui));
+                                                    return;
+                                                }
+                                                if *favorite {
+                                                    ui.label(egui::RichText::new("★").color(colors::YELLOW))
+                                                        .on_hover_text("Favorite");
+                                                }
+                                                self.render_highlighted_text(ui, text);
+                                                for tag in self.browse.tags(*id) {
+                                                    ui.label(egui::RichText::new(format!("#{}", tag))
+                                                        .color(colors::BLUE)
+                                                        .small());
+                                                }
+                                            }
+                                        );
+
+                                        ui.with_layout(
+                                            egui::Layout::right_to_left(egui::Align::Center),
+                                            |ui| {
+                                                if ui.small_button("Details").clicked() {
+                                                    open_detail = Some(*id);
+                                                }
+                                                if ui.small_button("Copy").clicked() {
+                                                    ui.ctx().copy_text(text.clone());
+                                                }
+                                                if let Some(rating) = rating {
+                                                    ui.label(egui::RichText::new(format!("{}★", rating))
+                                                        .color(colors::YELLOW)
+                                                        .small());
+                                                }
+                                                if *is_template {
+                                                    ui.label(egui::RichText::new("template")
+                                                        .color(colors::PEACH)
+                                                        .small())
+                                                        .on_hover_text("Uses Dynamic Prompts syntax ({a|b} or __wildcard__)");
+                                                }
+                                            }
+                                        );
+                                    });
+                                });
+
+                            if !editing
+                                && row.response.contains_pointer()
+                                && ui.input(|i| i.pointer.button_double_clicked(egui::PointerButton::Primary))
+                            {
+                                start_editing = Some((*id, text.clone()));
+                            }
+                            self.browse.set_height(i, row.response.rect.height() + ui.spacing().item_spacing.y);
+                        }
+
+                        ui.add_space(self.browse.height_of(visible.end..self.browse.len()));
+                    });
+
+                if let Some(id) = open_detail {
+                    self.open_detail(id);
+                }
+                if let Some((id, text)) = start_editing {
+                    self.start_editing(id, &text);
+                }
+                if let Some(action) = editor_action {
+                    self.apply_editor_action(action);
+                }
+            });
+    }
+
+    fn render_deduplicate_tab(&mut self, ui: &mut egui::Ui) {
+        // Toolbar card
+        egui::Frame::new()
+            .fill(colors::SURFACE0)
+            .inner_margin(16.0)
+            .corner_radius(8.0)
+            .show(ui, |ui| {
+                ui.set_width(ui.available_width());
+
+                ui.horizontal(|ui| {
+                    ui.label("Similarity threshold:");
+
+                    let slider = egui::Slider::new(&mut self.similarity_threshold, 0.5..=0.99)
+                        .show_value(false);
+                    ui.add(slider);
+
+                    // Read-only percentage display
+                    ui.label(egui::RichText::new(format!("{:.0}%", self.similarity_threshold * 100.0))
+                        .color(colors::TEXT));
+
+                    ui.checkbox(&mut self.compare_negatives, "Compare negative prompts")
+                        .on_hover_text("Only pair prompts whose negative prompts are also this similar");
+
+                    ui.add_space(20.0);
+
+                    let scan_button = egui::Button::new(
+                        egui::RichText::new("Find Duplicates").color(colors::CRUST)
+                    ).fill(colors::PEACH);
+
+                    if ui.add(scan_button).clicked() {
+                        self.is_scanning = true;
+                        self.scan_for_duplicates();
+                    }
+
+                    ui.add_space(10.0);
+
+                    // Remove All button - only show when pairs exist
+                    if !self.similar_pairs.is_empty() {
+                        let remove_all_btn = egui::Button::new(
+                            egui::RichText::new("Remove All").color(colors::CRUST)
+                        ).fill(colors::RED);
+
+                        if ui.add_enabled(self.can_edit(), remove_all_btn).clicked() {
+                            self.remove_all_duplicates();
+                        }
+
+                        ui.add_space(10.0);
+                        ui.label(format!("{} pairs found", self.similar_pairs.len()));
+
+                        ui.add_space(10.0);
+                        ui.menu_button("Export Report", |ui| {
+                            if ui.button("HTML...").clicked() {
+                                ui.close_menu();
+                                self.export_report(ReportFormat::Html);
+                            }
+                            if ui.button("Markdown...").clicked() {
+                                ui.close_menu();
+                                self.export_report(ReportFormat::Markdown);
+                            }
+                        });
+                    }
+
+                    if ui.add_enabled(self.can_edit(), egui::Button::new("Apply Decisions...")).clicked() {
+                        self.apply_decisions_file();
+                    }
+
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        if ui.button("Normalization...").clicked() {
+                            self.normalize_draft = self.library.db().normalize_options();
+                            self.normalize_report = None;
+                            self.show_normalize_settings = true;
+                        }
+                    });
+                });
+            });
+
+        ui.add_space(16.0);
+
+        // Comparison view
+        if self.similar_pairs.is_empty() {
+            egui::Frame::new()
+                .fill(colors::BASE)
+                .inner_margin(40.0)
+                .corner_radius(8.0)
+                .stroke(Stroke::new(1.0, colors::SURFACE0))
+                .show(ui, |ui| {
+                    ui.set_width(ui.available_width());
+                    ui.vertical_centered(|ui| {
+                        ui.add_space(40.0);
+                        ui.label(egui::RichText::new("No duplicate pairs found").size(18.0).color(colors::SUBTEXT));
+                        ui.add_space(10.0);
+                        ui.label(egui::RichText::new("Click 'Find Duplicates' to scan your prompts").color(colors::SUBTEXT));
+                        ui.add_space(10.0);
+                        ui.label(egui::RichText::new("Try lowering the threshold to find more matches").color(colors::SUBTEXT));
+                        ui.add_space(40.0);
+                    });
+                });
+        } else {
+            let pair = self.similar_pairs[self.current_pair_index].clone();
+            let total_pairs = self.similar_pairs.len();
+            let mut editor_action = None;
+
+            // Main comparison card
+            egui::Frame::new()
+                .fill(colors::BASE)
+                .inner_margin(20.0)
+                .corner_radius(8.0)
+                .stroke(Stroke::new(1.0, colors::SURFACE0))
+                .show(ui, |ui| {
+                    ui.set_width(ui.available_width());
+
+                    // Header
+                    ui.horizontal(|ui| {
+                        ui.label(egui::RichText::new(format!(
+                            "Pair {} of {}",
+                            self.current_pair_index + 1,
+                            total_pairs
+                        )).strong());
+
+                        ui.add_space(20.0);
+
+                        ui.label(egui::RichText::new(format!(
+                            "{:.0}% similar",
+                            pair.similarity * 100.0
+                        )).color(colors::YELLOW));
+                    });
+
+                    ui.add_space(16.0);
+
+                    // Side by side comparison - centered
+                    let gap = 20.0;
+                    let available_width = ui.available_width();
+                    // Account for gap and frame inner margins (16px each side = 32px per card)
+                    let card_width = (available_width - gap) / 2.0 - 32.0;
+
+                    ui.columns(2, |columns| {
+                        // Left prompt
+                        columns[0].vertical_centered(|ui| {
+                            egui::Frame::new()
+                                .fill(colors::MANTLE)
+                                .inner_margin(16.0)
+                                .corner_radius(6.0)
+                                .show(ui, |ui| {
+                                    ui.set_width(card_width);
+                                    ui.set_min_height(150.0);
+
+                                    ui.vertical(|ui| {
+                                        if self.editor.as_ref().is_some_and(|editor| editor.id == pair.id_a) {
+                                            editor_action = self.render_prompt_editor(ui);
+                                        } else {
+                                            ui.label(&pair.text_a);
+                                        }
+
+                                        ui.add_space(16.0);
+
+                                        ui.with_layout(egui::Layout::bottom_up(egui::Align::LEFT), |ui| {
+                                            ui.horizontal(|ui| {
+                                                let delete_btn = egui::Button::new(
+                                                    egui::RichText::new("Delete").color(colors::CRUST)
+                                                ).fill(colors::RED);
+
+                                                if ui.add_enabled(self.can_edit(), delete_btn).clicked() {
+                                                    self.delete_duplicate(pair.id_a, pair.id_b);
+                                                }
+
+                                                if ui.small_button("Copy").clicked() {
+                                                    ui.ctx().copy_text(pair.text_a.clone());
+                                                }
+
+                                                if ui.add_enabled(self.can_edit(), egui::Button::new("Edit").small()).clicked() {
+                                                    self.start_editing(pair.id_a, &pair.text_a);
+                                                }
+                                            });
+                                        });
+                                    });
+                                });
+                        });
+
+                        // Right prompt
+                        columns[1].vertical_centered(|ui| {
+                            egui::Frame::new()
+                                .fill(colors::MANTLE)
+                                .inner_margin(16.0)
+                                .corner_radius(6.0)
+                                .show(ui, |ui| {
+                                    ui.set_width(card_width);
+                                    ui.set_min_height(150.0);
+
+                                    ui.vertical(|ui| {
+                                        if self.editor.as_ref().is_some_and(|editor| editor.id == pair.id_b) {
+                                            editor_action = self.render_prompt_editor(ui);
+                                        } else {
+                                            ui.label(&pair.text_b);
+                                        }
+
+                                        ui.add_space(16.0);
+
+                                        ui.with_layout(egui::Layout::bottom_up(egui::Align::LEFT), |ui| {
+                                            ui.horizontal(|ui| {
+                                                let delete_btn = egui::Button::new(
+                                                    egui::RichText::new("Delete").color(colors::CRUST)
+                                                ).fill(colors::RED);
+
+                                                if ui.add_enabled(self.can_edit(), delete_btn).clicked() {
+                                                    self.delete_duplicate(pair.id_b, pair.id_a);
+                                                }
+
+                                                if ui.small_button("Copy").clicked() {
+                                                    ui.ctx().copy_text(pair.text_b.clone());
+                                                }
+
+                                                if ui.add_enabled(self.can_edit(), egui::Button::new("Edit").small()).clicked() {
+                                                    self.start_editing(pair.id_b, &pair.text_b);
+                                                }
+                                            });
+                                        });
+                                    });
+                                });
+                        });
+                    });
 
                     ui.add_space(20.0);
 
-                    let scan_button = egui::Button::new(
-                        egui::RichText::new("Find Duplicates").color(colors::CRUST)
-                    ).fill(colors::PEACH);
+                    // Navigation
+                    ui.vertical_centered(|ui| {
+                        // Skip button
+                        let skip_btn = egui::Button::new(
+                            egui::RichText::new("Skip (keep both)")
+                        );
+                        if ui.add(skip_btn).clicked() && self.current_pair_index < self.similar_pairs.len() - 1 {
+                            self.current_pair_index += 1;
+                        }
 
-                    if ui.add(scan_button).clicked() {
-                        self.is_scanning = true;
-                        self.scan_for_duplicates();
-                    }
+                        ui.add_space(12.0);
 
-                    ui.add_space(10.0);
+                        // Previous / Next
+                        ui.horizontal(|ui| {
+                            let can_prev = self.current_pair_index > 0;
+                            let can_next = self.current_pair_index < self.similar_pairs.len() - 1;
 
-                    // Remove All button - only show when pairs exist
-                    if !self.similar_pairs.is_empty() {
-                        let remove_all_btn = egui::Button::new(
-                            egui::RichText::new("Remove All").color(colors::CRUST)
-                        ).fill(colors::RED);
+                            if ui.add_enabled(can_prev, egui::Button::new("◀ Previous")).clicked() {
+                                self.current_pair_index -= 1;
+                            }
 
-                        if ui.add(remove_all_btn).clicked() {
-                            self.remove_all_duplicates();
+                            ui.add_space(20.0);
+
+                            if ui.add_enabled(can_next, egui::Button::new("Next ▶")).clicked() {
+                                self.current_pair_index += 1;
+                            }
+                        });
+                    });
+                });
+
+            if let Some(action) = editor_action {
+                self.apply_editor_action(action);
+            }
+        }
+    }
+
+    fn update_replace_preview(&mut self) {
+        self.replace_preview = self.library.db()
+            .preview_replace(&self.find_text, &self.replace_text, self.case_sensitive)
+            .unwrap_or_default();
+    }
+
+    fn apply_replacements(&mut self) {
+        let result = self.library.replace(&self.find_text, &self.replace_text, self.case_sensitive, false);
+
+        self.replace_preview.clear();
+        self.find_text.clear();
+        self.replace_text.clear();
+        self.show_find_replace = false;
+        self.refresh_counts();
+        match result {
+            Ok(summary) if summary.collisions.is_empty() => {
+                self.set_status(format!("Replaced text in {} prompts", summary.changed.len()), false);
+            }
+            Ok(summary) => self.set_status(format!(
+                "Replaced text in {} prompts, skipped {} that would duplicate an existing prompt",
+                summary.changed.len(),
+                summary.collisions.len()
+            ), false),
+            Err(e) => self.set_status(e.to_string(), true),
+        }
+    }
+
+    fn render_find_replace_popup(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_find_replace;
+
+        egui::Window::new("Find & Replace")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(true)
+            .default_width(500.0)
+            .default_height(400.0)
+            .show(ctx, |ui| {
+                // Grid for aligned Find/Replace fields
+                egui::Grid::new("find_replace_grid")
+                    .num_columns(2)
+                    .spacing([10.0, 8.0])
+                    .show(ui, |ui| {
+                        ui.label("Find:");
+                        let find_response = ui.add(
+                            egui::TextEdit::singleline(&mut self.find_text)
+                                .desired_width(350.0)
+                        );
+                        if find_response.changed() {
+                            self.update_replace_preview();
                         }
+                        ui.end_row();
 
-                        ui.add_space(10.0);
-                        ui.label(format!("{} pairs found", self.similar_pairs.len()));
-                    }
+                        ui.label("Replace:");
+                        let replace_response = ui.add(
+                            egui::TextEdit::singleline(&mut self.replace_text)
+                                .desired_width(350.0)
+                        );
+                        if replace_response.changed() {
+                            self.update_replace_preview();
+                        }
+                        ui.end_row();
+                    });
+
+                ui.add_space(8.0);
+
+                let checkbox_response = ui.checkbox(&mut self.case_sensitive, "Case sensitive");
+                if checkbox_response.changed() {
+                    self.update_replace_preview();
+                }
+
+                ui.add_space(16.0);
+
+                // Match count - centered
+                ui.vertical_centered(|ui| {
+                    ui.label(egui::RichText::new(format!(
+                        "Matches: {} prompts",
+                        self.replace_preview.len()
+                    )).color(colors::YELLOW));
+                });
+
+                ui.add_space(8.0);
+
+                // Preview list - centered container
+                ui.vertical_centered(|ui| {
+                    egui::Frame::new()
+                        .fill(colors::BASE)
+                        .inner_margin(8.0)
+                        .corner_radius(6.0)
+                        .stroke(egui::Stroke::new(1.0, colors::SURFACE0))
+                        .show(ui, |ui| {
+                            ui.set_width(ui.available_width());
+                            egui::ScrollArea::vertical()
+                                .max_height(200.0)
+                                .show(ui, |ui| {
+                                    if self.replace_preview.is_empty() {
+                                        ui.vertical_centered(|ui| {
+                                            ui.label(egui::RichText::new("No matches found").color(colors::SUBTEXT));
+                                        });
+                                    } else {
+                                        for preview in &self.replace_preview {
+                                            egui::Frame::new()
+                                                .fill(colors::MANTLE)
+                                                .inner_margin(8.0)
+                                                .corner_radius(4.0)
+                                                .show(ui, |ui| {
+                                                    // Show truncated versions for long prompts
+                                                    let orig_display: String = preview.original.chars().take(80).collect();
+                                                    let repl_display: String = preview.replaced.chars().take(80).collect();
+
+                                                    let orig_suffix = if preview.original.len() > 80 { "..." } else { "" };
+                                                    let repl_suffix = if preview.replaced.len() > 80 { "..." } else { "" };
+
+                                                    ui.label(egui::RichText::new(format!("{}{}", orig_display, orig_suffix))
+                                                        .color(colors::RED));
+                                                    ui.label(egui::RichText::new(format!("→ {}{}", repl_display, repl_suffix))
+                                                        .color(colors::GREEN));
+                                                });
+                                            ui.add_space(4.0);
+                                        }
+                                    }
+                                });
+                        });
+                });
+
+                ui.add_space(16.0);
+
+                // Buttons - centered
+                ui.vertical_centered(|ui| {
+                    ui.horizontal(|ui| {
+                        let can_apply = !self.replace_preview.is_empty() && self.can_edit();
+
+                        let apply_btn = egui::Button::new(
+                            egui::RichText::new("Apply All").color(colors::CRUST)
+                        ).fill(colors::GREEN);
+
+                        if ui.add_enabled(can_apply, apply_btn).clicked() {
+                            self.apply_replacements();
+                        }
+
+                        if ui.button("Cancel").clicked() {
+                            self.show_find_replace = false;
+                        }
+                    });
                 });
             });
 
-        ui.add_space(16.0);
+        self.show_find_replace = open;
+    }
 
-        // Comparison view
-        if self.similar_pairs.is_empty() {
-            egui::Frame::new()
-                .fill(colors::BASE)
-                .inner_margin(40.0)
-                .corner_radius(8.0)
-                .stroke(Stroke::new(1.0, colors::SURFACE0))
-                .show(ui, |ui| {
-                    ui.set_width(ui.available_width());
-                    ui.vertical_centered(|ui| {
-                        ui.add_space(40.0);
-                        ui.label(egui::RichText::new("No duplicate pairs found").size(18.0).color(colors::SUBTEXT));
-                        ui.add_space(10.0);
-                        ui.label(egui::RichText::new("Click 'Find Duplicates' to scan your prompts").color(colors::SUBTEXT));
-                        ui.add_space(10.0);
-                        ui.label(egui::RichText::new("Try lowering the threshold to find more matches").color(colors::SUBTEXT));
-                        ui.add_space(40.0);
+    fn open_tag_rules(&mut self) {
+        self.tag_rules = self.library.db().tag_rules().unwrap_or_default();
+        self.categorize_preview = None;
+        self.show_tag_rules = true;
+    }
+
+    fn add_tag_rule(&mut self) {
+        match self.library.add_tag_rule(self.rule_kind, &self.rule_pattern, &self.rule_tag) {
+            Ok(_) => {
+                self.rule_pattern.clear();
+                self.rule_tag.clear();
+                self.tag_rules = self.library.db().tag_rules().unwrap_or_default();
+                self.categorize_preview = None;
+            }
+            Err(e) => self.set_status(e.to_string(), true),
+        }
+    }
+
+    fn preview_categorize(&mut self) {
+        match self.library.categorize(&self.browse_filter, true) {
+            Ok(report) => self.categorize_preview = Some(report),
+            Err(e) => self.set_status(e.to_string(), true),
+        }
+    }
+
+    fn apply_categorize(&mut self) {
+        let result = self.library.categorize(&self.browse_filter, false);
+        self.categorize_preview = None;
+        self.refresh_counts();
+        match result {
+            Ok(report) => self.set_status(format!(
+                "Tag rules added {} tags to {} prompts",
+                report.assignments.len(),
+                report.tagged_prompts()
+            ), false),
+            Err(e) => self.set_status(e.to_string(), true),
+        }
+    }
+
+    fn render_tag_rules_popup(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_tag_rules;
+        let can_edit = self.can_edit();
+
+        egui::Window::new("Tag Rules")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(true)
+            .default_width(600.0)
+            .show(ctx, |ui| {
+                ui.label(egui::RichText::new(
+                    "Keywords are |-separated whole words, e.g. portrait|headshot. \
+                     Tags like style:cyberpunk share the style namespace, so a prompt getting two style tags is reported as a conflict."
+                ).color(colors::SUBTEXT));
+
+                ui.add_space(8.0);
+
+                let mut delete = None;
+                let mut toggle = None;
+                egui::Frame::new()
+                    .fill(colors::BASE)
+                    .inner_margin(8.0)
+                    .corner_radius(6.0)
+                    .stroke(egui::Stroke::new(1.0, colors::SURFACE0))
+                    .show(ui, |ui| {
+                        ui.set_width(ui.available_width());
+                        egui::ScrollArea::vertical()
+                            .max_height(200.0)
+                            .show(ui, |ui| {
+                                if self.tag_rules.is_empty() {
+                                    ui.vertical_centered(|ui| {
+                                        ui.label(egui::RichText::new("No rules yet").color(colors::SUBTEXT));
+                                    });
+                                    return;
+                                }
+                                egui::Grid::new("tag_rules_grid")
+                                    .num_columns(5)
+                                    .spacing([10.0, 6.0])
+                                    .show(ui, |ui| {
+                                        for rule in &self.tag_rules {
+                                            let mut enabled = rule.enabled;
+                                            if ui.add_enabled(can_edit, egui::Checkbox::without_text(&mut enabled)).changed() {
+                                                toggle = Some((rule.id, enabled));
+                                            }
+                                            ui.label(egui::RichText::new(rule.kind.label()).color(colors::SUBTEXT).small());
+                                            ui.label(&rule.pattern);
+                                            ui.label(egui::RichText::new(format!("→ {}", rule.tag)).color(colors::BLUE));
+                                            if ui.add_enabled(can_edit, egui::Button::new("Delete").small()).clicked() {
+                                                delete = Some(rule.id);
+                                            }
+                                            ui.end_row();
+                                        }
+                                    });
+                            });
                     });
-                });
-        } else {
-            let pair = self.similar_pairs[self.current_pair_index].clone();
-            let total_pairs = self.similar_pairs.len();
 
-            // Main comparison card
-            egui::Frame::new()
-                .fill(colors::BASE)
-                .inner_margin(20.0)
-                .corner_radius(8.0)
-                .stroke(Stroke::new(1.0, colors::SURFACE0))
-                .show(ui, |ui| {
-                    ui.set_width(ui.available_width());
+                if let Some((id, enabled)) = toggle {
+                    let _ = self.library.db().set_tag_rule_enabled(id, enabled);
+                }
+                if let Some(id) = delete {
+                    let _ = self.library.db().delete_tag_rule(id);
+                }
+                if toggle.is_some() || delete.is_some() {
+                    self.tag_rules = self.library.db().tag_rules().unwrap_or_default();
+                    self.categorize_preview = None;
+                }
 
-                    // Header
+                ui.add_space(8.0);
+
+                ui.add_enabled_ui(can_edit, |ui| {
                     ui.horizontal(|ui| {
-                        ui.label(egui::RichText::new(format!(
-                            "Pair {} of {}",
-                            self.current_pair_index + 1,
-                            total_pairs
-                        )).strong());
+                        egui::ComboBox::from_id_salt("rule_kind")
+                            .selected_text(self.rule_kind.label())
+                            .show_ui(ui, |ui| {
+                                for kind in RuleKind::ALL {
+                                    ui.selectable_value(&mut self.rule_kind, kind, kind.label());
+                                }
+                            });
+                        ui.add(
+                            egui::TextEdit::singleline(&mut self.rule_pattern)
+                                .hint_text("neon|cyberpunk")
+                                .desired_width(220.0)
+                        );
+                        ui.label("→");
+                        ui.add(
+                            egui::TextEdit::singleline(&mut self.rule_tag)
+                                .hint_text("style:cyberpunk")
+                                .desired_width(140.0)
+                        );
+                        let can_add = !self.rule_pattern.trim().is_empty() && !self.rule_tag.trim().is_empty();
+                        if ui.add_enabled(can_add, egui::Button::new("Add Rule")).clicked() {
+                            self.add_tag_rule();
+                        }
+                    });
+                });
 
-                        ui.add_space(20.0);
+                ui.add_space(16.0);
 
+                if let Some(report) = &self.categorize_preview {
+                    ui.vertical_centered(|ui| {
                         ui.label(egui::RichText::new(format!(
-                            "{:.0}% similar",
-                            pair.similarity * 100.0
+                            "{} new tags on {} of {} prompts",
+                            report.assignments.len(),
+                            report.tagged_prompts(),
+                            report.checked
                         )).color(colors::YELLOW));
                     });
 
-                    ui.add_space(16.0);
-
-                    // Side by side comparison - centered
-                    let gap = 20.0;
-                    let available_width = ui.available_width();
-                    // Account for gap and frame inner margins (16px each side = 32px per card)
-                    let card_width = (available_width - gap) / 2.0 - 32.0;
+                    ui.add_space(8.0);
 
-                    ui.columns(2, |columns| {
-                        // Left prompt
-                        columns[0].vertical_centered(|ui| {
-                            egui::Frame::new()
-                                .fill(colors::MANTLE)
-                                .inner_margin(16.0)
-                                .corner_radius(6.0)
+                    let mut select = None;
+                    egui::Frame::new()
+                        .fill(colors::BASE)
+                        .inner_margin(8.0)
+                        .corner_radius(6.0)
+                        .stroke(egui::Stroke::new(1.0, colors::SURFACE0))
+                        .show(ui, |ui| {
+                            ui.set_width(ui.available_width());
+                            egui::ScrollArea::vertical()
+                                .id_salt("categorize_preview")
+                                .max_height(200.0)
                                 .show(ui, |ui| {
-                                    ui.set_width(card_width);
-                                    ui.set_min_height(150.0);
+                                    for outcome in &report.rules {
+                                        ui.label(format!(
+                                            "{} → {}: matches {}, {} new",
+                                            outcome.rule.pattern, outcome.rule.tag, outcome.matched, outcome.added
+                                        ));
+                                    }
+                                    for (i, conflict) in report.conflicts.iter().enumerate() {
+                                        ui.horizontal(|ui| {
+                                            ui.label(egui::RichText::new(format!(
+                                                "Conflict: {} on {} prompts",
+                                                conflict.tags.join(" + "),
+                                                conflict.prompt_ids.len()
+                                            )).color(colors::PEACH));
+                                            if ui.small_button("Select").clicked() {
+                                                select = Some(i);
+                                            }
+                                        });
+                                    }
+                                });
+                        });
+                    if let Some(i) = select {
+                        let ids = report.conflicts[i].prompt_ids.clone();
+                        self.selected_ids.extend(ids);
+                    }
 
-                                    ui.vertical(|ui| {
-                                        ui.label(&pair.text_a);
+                    ui.add_space(16.0);
+                }
 
-                                        ui.add_space(16.0);
+                ui.vertical_centered(|ui| {
+                    ui.horizontal(|ui| {
+                        let has_rules = self.tag_rules.iter().any(|rule| rule.enabled);
+                        let shown = self.browse.len();
+                        if ui.add_enabled(has_rules, egui::Button::new(format!("Preview on {} Shown", shown))).clicked() {
+                            self.preview_categorize();
+                        }
 
-                                        ui.with_layout(egui::Layout::bottom_up(egui::Align::LEFT), |ui| {
-                                            ui.horizontal(|ui| {
-                                                let delete_btn = egui::Button::new(
-                                                    egui::RichText::new("Delete").color(colors::CRUST)
-                                                ).fill(colors::RED);
+                        let apply_btn = egui::Button::new(
+                            egui::RichText::new(format!("Apply to {} Shown", shown)).color(colors::CRUST)
+                        ).fill(colors::GREEN);
+                        if ui.add_enabled(has_rules && can_edit, apply_btn).clicked() {
+                            self.apply_categorize();
+                        }
 
-                                                if ui.add(delete_btn).clicked() {
-                                                    self.delete_prompt(pair.id_a);
-                                                }
+                        if ui.button("Close").clicked() {
+                            self.show_tag_rules = false;
+                        }
+                    });
+                });
+            });
 
-                                                if ui.small_button("Copy").clicked() {
-                                                    ui.ctx().copy_text(pair.text_a.clone());
-                                                }
-                                            });
-                                        });
-                                    });
-                                });
-                        });
+        self.show_tag_rules &= open;
+    }
 
-                        // Right prompt
-                        columns[1].vertical_centered(|ui| {
-                            egui::Frame::new()
-                                .fill(colors::MANTLE)
-                                .inner_margin(16.0)
-                                .corner_radius(6.0)
-                                .show(ui, |ui| {
-                                    ui.set_width(card_width);
-                                    ui.set_min_height(150.0);
+    fn open_import_history(&mut self) {
+        self.import_batches = self.library.db().import_batches().unwrap_or_default();
+        self.pending_undo = None;
+        self.show_import_history = true;
+    }
 
-                                    ui.vertical(|ui| {
-                                        ui.label(&pair.text_b);
+    fn undo_import(&mut self, batch_id: i64) {
+        self.pending_undo = None;
+        match self.library.undo_import(batch_id) {
+            Ok(summary) => {
+                let mut kept = String::new();
+                if summary.kept_edited > 0 {
+                    kept.push_str(&format!(", kept {} edited since", summary.kept_edited));
+                }
+                if summary.kept_seen_later > 0 {
+                    kept.push_str(&format!(", kept {} another import found too", summary.kept_seen_later));
+                }
+                self.set_status(format!("Undid import #{}: removed {} prompts{}", batch_id, summary.removed, kept), false);
+                self.similar_pairs.clear();
+                self.current_pair_index = 0;
+                self.refresh_counts();
+            }
+            Err(e) => self.set_status(e.to_string(), true),
+        }
+    }
 
-                                        ui.add_space(16.0);
+    fn render_import_history_popup(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_import_history;
+        let can_edit = self.can_edit();
+        let mut undo = None;
+        let mut confirm = None;
 
-                                        ui.with_layout(egui::Layout::bottom_up(egui::Align::LEFT), |ui| {
-                                            ui.horizontal(|ui| {
-                                                let delete_btn = egui::Button::new(
-                                                    egui::RichText::new("Delete").color(colors::CRUST)
-                                                ).fill(colors::RED);
+        egui::Window::new("Import History")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(true)
+            .default_width(640.0)
+            .show(ctx, |ui| {
+                ui.label(egui::RichText::new(
+                    "Undoing an import removes the prompts it added. Prompts whose text was edited since \
+                     are kept (titles, ratings, stars and notes don't count as edits), and so are prompts another import found too, which then belong to that import. \
+                     Prompts it skipped as duplicates were never its own."
+                ).color(colors::SUBTEXT));
 
-                                                if ui.add(delete_btn).clicked() {
-                                                    self.delete_prompt(pair.id_b);
-                                                }
+                ui.add_space(8.0);
 
-                                                if ui.small_button("Copy").clicked() {
-                                                    ui.ctx().copy_text(pair.text_b.clone());
-                                                }
-                                            });
-                                        });
+                egui::Frame::new()
+                    .fill(colors::BASE)
+                    .inner_margin(8.0)
+                    .corner_radius(6.0)
+                    .stroke(egui::Stroke::new(1.0, colors::SURFACE0))
+                    .show(ui, |ui| {
+                        ui.set_width(ui.available_width());
+                        egui::ScrollArea::vertical()
+                            .max_height(360.0)
+                            .show(ui, |ui| {
+                                if self.import_batches.is_empty() {
+                                    ui.vertical_centered(|ui| {
+                                        ui.label(egui::RichText::new("No imports yet").color(colors::SUBTEXT));
+                                    });
+                                    return;
+                                }
+                                egui::Grid::new("import_history_grid")
+                                    .num_columns(5)
+                                    .spacing([12.0, 6.0])
+                                    .striped(true)
+                                    .show(ui, |ui| {
+                                        for batch in &self.import_batches {
+                                            ui.label(egui::RichText::new(format!("#{}", batch.id)).color(colors::SUBTEXT));
+                                            ui.label(&batch.source_file).on_hover_text(
+                                                batch.file_hash.as_deref().map(|hash| format!("SHA-256 {}", hash)).unwrap_or_default()
+                                            );
+                                            ui.label(egui::RichText::new(&batch.imported_at).color(colors::SUBTEXT).small());
+                                            let mut kept = Vec::new();
+                                            if batch.edited > 0 {
+                                                kept.push(format!("{} edited", batch.edited));
+                                            }
+                                            if batch.seen_later > 0 {
+                                                kept.push(format!("{} imported again", batch.seen_later));
+                                            }
+                                            ui.label(format!(
+                                                "{} read, {} added, {} left{}",
+                                                batch.records,
+                                                batch.added,
+                                                batch.remaining,
+                                                if kept.is_empty() { String::new() } else { format!(" ({})", kept.join(", ")) }
+                                            ));
+
+                                            if let Some(undone) = &batch.undone_at {
+                                                ui.label(egui::RichText::new(format!("Undone {}", undone)).color(colors::SUBTEXT).small());
+                                            } else if self.pending_undo == Some(batch.id) {
+                                                ui.horizontal(|ui| {
+                                                    let confirm_btn = egui::Button::new(
+                                                        egui::RichText::new(format!(
+                                                            "Remove {}",
+                                                            batch.remaining - batch.edited - batch.seen_later
+                                                        ))
+                                                            .color(colors::CRUST)
+                                                    ).fill(colors::RED);
+                                                    if ui.add(confirm_btn).clicked() {
+                                                        confirm = Some(batch.id);
+                                                    }
+                                                    if ui.small_button("Cancel").clicked() {
+                                                        undo = Some(None);
+                                                    }
+                                                });
+                                            } else if ui.add_enabled(can_edit, egui::Button::new("Undo").small()).clicked() {
+                                                undo = Some(Some(batch.id));
+                                            }
+                                            ui.end_row();
+                                        }
                                     });
+                            });
+                    });
+
+                ui.add_space(8.0);
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    if ui.button("Close").clicked() {
+                        self.show_import_history = false;
+                    }
+                });
+            });
+
+        if let Some(batch_id) = undo {
+            self.pending_undo = batch_id;
+        }
+        if let Some(batch_id) = confirm {
+            self.undo_import(batch_id);
+        }
+        self.show_import_history &= open;
+    }
+
+    fn render_import_popup(&mut self, ctx: &egui::Context) {
+        let Some(dialog) = &mut self.import_dialog else {
+            return;
+        };
+        let mut open = true;
+        let mut confirmed = false;
+        let mut cancelled = false;
+
+        egui::Window::new(format!("Import {}", dialog.file_name))
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(true)
+            .default_width(600.0)
+            .default_height(500.0)
+            .show(ctx, |ui| {
+                let mut changed = false;
+
+                egui::Grid::new("import_options_grid")
+                    .num_columns(2)
+                    .spacing([10.0, 8.0])
+                    .show(ui, |ui| {
+                        ui.label("Encoding:");
+                        ui.horizontal(|ui| {
+                            let mut encoding_changed = false;
+                            egui::ComboBox::from_id_salt("import_encoding")
+                                .selected_text(dialog.encoding.label())
+                                .width(250.0)
+                                .show_ui(ui, |ui| {
+                                    for encoding in TextEncoding::ALL {
+                                        encoding_changed |= ui.selectable_value(&mut dialog.encoding, encoding, encoding.label()).changed();
+                                    }
                                 });
+                            if encoding_changed {
+                                dialog.decode();
+                            }
+                            ui.label(egui::RichText::new(format!("using {}", dialog.decoded.encoding))
+                                .color(colors::SUBTEXT));
                         });
-                    });
+                        ui.end_row();
 
-                    ui.add_space(20.0);
+                        ui.label("Split:");
+                        egui::ComboBox::from_id_salt("import_mode")
+                            .selected_text(dialog.options.mode.label())
+                            .width(250.0)
+                            .show_ui(ui, |ui| {
+                                for mode in ImportMode::ALL {
+                                    changed |= ui.selectable_value(&mut dialog.options.mode, mode, mode.label()).changed();
+                                }
+                            });
+                        ui.end_row();
 
-                    // Navigation
-                    ui.vertical_centered(|ui| {
-                        // Skip button
-                        let skip_btn = egui::Button::new(
-                            egui::RichText::new("Skip (keep both)")
-                        );
-                        if ui.add(skip_btn).clicked() {
-                            if self.current_pair_index < self.similar_pairs.len() - 1 {
-                                self.current_pair_index += 1;
+                        match dialog.options.mode {
+                            ImportMode::Delimiter => {
+                                ui.label("Delimiter:");
+                                changed |= ui.add(
+                                    egui::TextEdit::singleline(&mut dialog.options.delimiter)
+                                        .desired_width(250.0)
+                                ).changed();
+                                ui.end_row();
+                            }
+                            ImportMode::Regex => {
+                                ui.label("Pattern:");
+                                changed |= ui.add(
+                                    egui::TextEdit::singleline(&mut dialog.options.pattern)
+                                        .desired_width(350.0)
+                                ).changed();
+                                ui.end_row();
                             }
+                            _ => {}
                         }
 
-                        ui.add_space(12.0);
+                        ui.label("Tags:");
+                        ui.add(
+                            egui::TextEdit::singleline(&mut dialog.tags_text)
+                                .hint_text("Comma-separated, added to every prompt")
+                                .desired_width(250.0)
+                        );
+                        ui.end_row();
+                    });
 
-                        // Previous / Next
-                        ui.horizontal(|ui| {
-                            let can_prev = self.current_pair_index > 0;
-                            let can_next = self.current_pair_index < self.similar_pairs.len() - 1;
+                ui.add_space(8.0);
+                ui.checkbox(&mut dialog.options.apply_rules, "Tag prompts with the tag rules");
 
-                            if ui.add_enabled(can_prev, egui::Button::new("◀ Previous")).clicked() {
-                                self.current_pair_index -= 1;
-                            }
+                if dialog.options.mode != ImportMode::OnePerLine {
+                    ui.add_space(8.0);
+                    changed |= ui.checkbox(&mut dialog.options.join_lines, "Join multi-line prompts into one line").changed();
+                }
 
-                            ui.add_space(20.0);
+                if changed {
+                    dialog.reparse();
+                }
 
-                            if ui.add_enabled(can_next, egui::Button::new("Next ▶")).clicked() {
-                                self.current_pair_index += 1;
-                            }
+                let invalid_lines = &dialog.decoded.invalid_lines;
+                if !invalid_lines.is_empty() {
+                    ui.add_space(8.0);
+                    ui.label(egui::RichText::new(format!(
+                        "{} lines contain invalid bytes (shown as �): {}. Try another encoding.",
+                        invalid_lines.len(),
+                        list_line_numbers(invalid_lines)
+                    )).color(colors::PEACH));
+                }
+
+                ui.add_space(16.0);
+
+                match &dialog.records {
+                    Ok(records) => {
+                        let summary = if dialog.is_partial() {
+                            format!(
+                                "Parsed {} prompts from the first {} KB of {:.1} MB",
+                                records.len(),
+                                import::PREVIEW_BYTES / 1024,
+                                dialog.total_bytes as f64 / (1024.0 * 1024.0)
+                            )
+                        } else {
+                            format!("Parsed {} prompts", records.len())
+                        };
+                        ui.vertical_centered(|ui| {
+                            ui.label(egui::RichText::new(summary).color(colors::YELLOW));
                         });
+
+                        ui.add_space(8.0);
+
+                        egui::Frame::new()
+                            .fill(colors::BASE)
+                            .inner_margin(8.0)
+                            .corner_radius(6.0)
+                            .stroke(egui::Stroke::new(1.0, colors::SURFACE0))
+                            .show(ui, |ui| {
+                                ui.set_width(ui.available_width());
+                                egui::ScrollArea::vertical()
+                                    .max_height(300.0)
+                                    .show(ui, |ui| {
+                                        if records.is_empty() {
+                                            ui.vertical_centered(|ui| {
+                                                ui.label(egui::RichText::new("No prompts found").color(colors::SUBTEXT));
+                                            });
+                                        }
+                                        for (i, record) in records.iter().take(IMPORT_PREVIEW_LIMIT).enumerate() {
+                                            egui::Frame::new()
+                                                .fill(colors::MANTLE)
+                                                .inner_margin(8.0)
+                                                .corner_radius(4.0)
+                                                .show(ui, |ui| {
+                                                    ui.set_width(ui.available_width());
+                                                    ui.label(egui::RichText::new(format!("#{}", i + 1))
+                                                        .color(colors::SUBTEXT)
+                                                        .small());
+                                                    ui.label(&record.text);
+                                                    if let Some(negative) = &record.metadata.negative_prompt {
+                                                        ui.label(egui::RichText::new(format!("Negative: {}", negative))
+                                                            .color(colors::RED));
+                                                    }
+                                                    let settings = record.metadata.settings_text();
+                                                    if !settings.is_empty() {
+                                                        ui.label(egui::RichText::new(settings)
+                                                            .color(colors::SUBTEXT)
+                                                            .small());
+                                                    }
+                                                });
+                                            ui.add_space(4.0);
+                                        }
+                                        if records.len() > IMPORT_PREVIEW_LIMIT {
+                                            ui.label(egui::RichText::new(format!(
+                                                "... and {} more",
+                                                records.len() - IMPORT_PREVIEW_LIMIT
+                                            )).color(colors::SUBTEXT));
+                                        }
+                                    });
+                            });
+                    }
+                    Err(e) => {
+                        ui.label(egui::RichText::new(format!("Invalid pattern: {}", e)).color(colors::RED));
+                    }
+                }
+
+                ui.add_space(16.0);
+
+                ui.vertical_centered(|ui| {
+                    ui.horizontal(|ui| {
+                        let can_import = dialog.records.as_ref().is_ok_and(|r| !r.is_empty());
+
+                        let import_btn = egui::Button::new(
+                            egui::RichText::new("Import").color(colors::CRUST)
+                        ).fill(colors::GREEN);
+
+                        if ui.add_enabled(can_import, import_btn).clicked() {
+                            confirmed = true;
+                        }
+
+                        if ui.button("Cancel").clicked() {
+                            cancelled = true;
+                        }
                     });
                 });
+            });
+
+        if confirmed || cancelled || !open {
+            // Remember the split settings so a batch of similar files is quick to import
+            let Some(dialog) = self.import_dialog.take() else {
+                return;
+            };
+            self.import_options = dialog.options.clone();
+            if confirmed {
+                self.start_import(dialog);
+            }
         }
     }
 
-    fn update_replace_preview(&mut self) {
-        self.replace_preview.clear();
-
-        if self.find_text.is_empty() {
+    fn render_import_progress(&mut self, ctx: &egui::Context) {
+        let Some(job) = &self.import_job else {
             return;
-        }
+        };
 
-        let all_prompts = self.db.get_all().unwrap_or_default();
+        let bytes_read = job.progress.bytes_read.load(Ordering::Relaxed);
+        let records = job.progress.records.load(Ordering::Relaxed);
+        let added = job.progress.added.load(Ordering::Relaxed);
+        let fraction = if job.total_bytes > 0 {
+            bytes_read as f32 / job.total_bytes as f32
+        } else {
+            1.0
+        };
 
-        for prompt in all_prompts {
-            let contains_match = if self.case_sensitive {
-                prompt.text.contains(&self.find_text)
-            } else {
-                prompt.text.to_lowercase().contains(&self.find_text.to_lowercase())
-            };
+        egui::Window::new(format!("Importing {}", job.file_name))
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                ui.add(egui::ProgressBar::new(fraction.min(1.0)).show_percentage().desired_width(400.0));
+                ui.add_space(8.0);
+                ui.label(format!("{} prompts read, {} new", records, added));
+                ui.add_space(8.0);
+                ui.vertical_centered(|ui| {
+                    if job.is_cancelling() {
+                        ui.label(egui::RichText::new("Cancelling...").color(colors::SUBTEXT));
+                    } else if ui.button("Cancel").clicked() {
+                        job.cancel();
+                    }
+                });
+            });
 
-            if contains_match {
-                let replaced = if self.case_sensitive {
-                    prompt.text.replace(&self.find_text, &self.replace_text)
-                } else {
-                    // Case-insensitive replace
-                    let lower_find = self.find_text.to_lowercase();
-                    let text_lower = prompt.text.to_lowercase();
-                    let mut result = String::new();
-                    let mut last_end = 0;
-
-                    for (start, _) in text_lower.match_indices(&lower_find) {
-                        result.push_str(&prompt.text[last_end..start]);
-                        result.push_str(&self.replace_text);
-                        last_end = start + self.find_text.len();
-                    }
-                    result.push_str(&prompt.text[last_end..]);
-                    result
-                };
+        ctx.request_repaint_after(Duration::from_millis(100));
+    }
 
-                self.replace_preview.push(ReplacePreview {
-                    id: prompt.id,
-                    original: prompt.text,
-                    replaced,
-                });
+    fn preview_normalization(&mut self) {
+        match self.library.db().preview_normalize(&self.normalize_draft) {
+            Ok(report) => {
+                self.normalize_report = Some(report);
+                self.normalize_report_applied = false;
             }
+            Err(e) => self.set_status(format!("Database error: {}", e), true),
         }
     }
 
-    fn apply_replacements(&mut self) {
-        let count = self.replace_preview.len();
-        for preview in &self.replace_preview {
-            let _ = self.db.update_prompt(preview.id, &preview.replaced);
+    fn apply_normalization(&mut self) {
+        match self.library.db_mut().set_normalize_options(self.normalize_draft) {
+            Ok(report) => {
+                self.set_status(format!(
+                    "Normalization updated ({} duplicates merged)",
+                    report.removed_count()
+                ), false);
+                self.normalize_report = Some(report);
+                self.normalize_report_applied = true;
+                self.similar_pairs.clear();
+                self.current_pair_index = 0;
+                self.refresh_counts();
+            }
+            Err(e) => self.set_status(format!("Database error: {}", e), true),
         }
-
-        self.replace_preview.clear();
-        self.find_text.clear();
-        self.replace_text.clear();
-        self.show_find_replace = false;
-        self.refresh_counts();
-        self.set_status(format!("Replaced text in {} prompts", count), false);
     }
 
-    fn render_find_replace_popup(&mut self, ctx: &egui::Context) {
-        let mut open = self.show_find_replace;
+    fn render_normalize_popup(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_normalize_settings;
 
-        egui::Window::new("Find & Replace")
+        egui::Window::new("Normalization")
             .open(&mut open)
             .collapsible(false)
             .resizable(true)
             .default_width(500.0)
             .default_height(400.0)
             .show(ctx, |ui| {
-                // Grid for aligned Find/Replace fields
-                egui::Grid::new("find_replace_grid")
-                    .num_columns(2)
-                    .spacing([10.0, 8.0])
-                    .show(ui, |ui| {
-                        ui.label("Find:");
-                        let find_response = ui.add(
-                            egui::TextEdit::singleline(&mut self.find_text)
-                                .desired_width(350.0)
-                        );
-                        if find_response.changed() {
-                            self.update_replace_preview();
-                        }
-                        ui.end_row();
-
-                        ui.label("Replace:");
-                        let replace_response = ui.add(
-                            egui::TextEdit::singleline(&mut self.replace_text)
-                                .desired_width(350.0)
-                        );
-                        if replace_response.changed() {
-                            self.update_replace_preview();
-                        }
-                        ui.end_row();
-                    });
+                ui.label(egui::RichText::new(
+                    "Prompts with the same normalized text are treated as exact duplicates on import."
+                ).color(colors::SUBTEXT));
 
                 ui.add_space(8.0);
 
-                let checkbox_response = ui.checkbox(&mut self.case_sensitive, "Case sensitive");
-                if checkbox_response.changed() {
-                    self.update_replace_preview();
+                let draft = &mut self.normalize_draft;
+                let mut changed = false;
+                changed |= ui.checkbox(&mut draft.unicode_nfkc, "Unicode NFKC (full-width, ligatures)").changed();
+                changed |= ui.checkbox(&mut draft.case_fold, "Ignore case").changed();
+                changed |= ui.checkbox(&mut draft.collapse_whitespace, "Collapse whitespace").changed();
+                changed |= ui.checkbox(&mut draft.comma_spacing, "Normalize comma and punctuation spacing").changed();
+                changed |= ui.checkbox(&mut draft.strip_trailing_punctuation, "Strip trailing punctuation").changed();
+                changed |= ui.checkbox(&mut draft.sort_tags, "Ignore tag order (sort comma-separated tags)").changed();
+                if changed {
+                    self.normalize_report = None;
                 }
 
                 ui.add_space(16.0);
 
-                // Match count - centered
-                ui.vertical_centered(|ui| {
-                    ui.label(egui::RichText::new(format!(
-                        "Matches: {} prompts",
-                        self.replace_preview.len()
-                    )).color(colors::YELLOW));
-                });
+                if let Some(report) = &self.normalize_report {
+                    let summary = if self.normalize_report_applied {
+                        format!("Merged {} prompts into {} groups", report.removed_count(), report.collisions.len())
+                    } else {
+                        format!("Would merge {} prompts into {} groups", report.removed_count(), report.collisions.len())
+                    };
+                    ui.vertical_centered(|ui| {
+                        ui.label(egui::RichText::new(summary).color(colors::YELLOW));
+                    });
 
-                ui.add_space(8.0);
+                    ui.add_space(8.0);
 
-                // Preview list - centered container
-                ui.vertical_centered(|ui| {
                     egui::Frame::new()
                         .fill(colors::BASE)
                         .inner_margin(8.0)
@@ -814,60 +3759,491 @@ impl PromptDedupApp {
                             egui::ScrollArea::vertical()
                                 .max_height(200.0)
                                 .show(ui, |ui| {
-                                    if self.replace_preview.is_empty() {
+                                    if report.collisions.is_empty() {
                                         ui.vertical_centered(|ui| {
-                                            ui.label(egui::RichText::new("No matches found").color(colors::SUBTEXT));
+                                            ui.label(egui::RichText::new("No collisions").color(colors::SUBTEXT));
                                         });
-                                    } else {
-                                        for preview in &self.replace_preview {
-                                            egui::Frame::new()
-                                                .fill(colors::MANTLE)
-                                                .inner_margin(8.0)
-                                                .corner_radius(4.0)
-                                                .show(ui, |ui| {
-                                                    // Show truncated versions for long prompts
-                                                    let orig_display: String = preview.original.chars().take(80).collect();
-                                                    let repl_display: String = preview.replaced.chars().take(80).collect();
-
-                                                    let orig_suffix = if preview.original.len() > 80 { "..." } else { "" };
-                                                    let repl_suffix = if preview.replaced.len() > 80 { "..." } else { "" };
-
-                                                    ui.label(egui::RichText::new(format!("{}{}", orig_display, orig_suffix))
+                                    }
+                                    for collision in &report.collisions {
+                                        egui::Frame::new()
+                                            .fill(colors::MANTLE)
+                                            .inner_margin(8.0)
+                                            .corner_radius(4.0)
+                                            .show(ui, |ui| {
+                                                ui.label(egui::RichText::new(format_with_source(&collision.kept))
+                                                    .color(colors::GREEN));
+                                                for removed in &collision.removed {
+                                                    ui.label(egui::RichText::new(format!("✕ {}", format_with_source(removed)))
                                                         .color(colors::RED));
-                                                    ui.label(egui::RichText::new(format!("→ {}{}", repl_display, repl_suffix))
-                                                        .color(colors::GREEN));
-                                                });
-                                            ui.add_space(4.0);
-                                        }
+                                                }
+                                            });
+                                        ui.add_space(4.0);
                                     }
                                 });
                         });
-                });
 
-                ui.add_space(16.0);
+                    ui.add_space(16.0);
+                }
 
-                // Buttons - centered
                 ui.vertical_centered(|ui| {
                     ui.horizontal(|ui| {
-                        let can_apply = !self.replace_preview.is_empty();
+                        if ui.button("Preview").clicked() {
+                            self.preview_normalization();
+                        }
 
                         let apply_btn = egui::Button::new(
-                            egui::RichText::new("Apply All").color(colors::CRUST)
+                            egui::RichText::new("Apply").color(colors::CRUST)
                         ).fill(colors::GREEN);
 
-                        if ui.add_enabled(can_apply, apply_btn).clicked() {
-                            self.apply_replacements();
+                        if ui.add_enabled(self.can_edit(), apply_btn).clicked() {
+                            self.apply_normalization();
                         }
 
-                        if ui.button("Cancel").clicked() {
-                            self.show_find_replace = false;
+                        if ui.button("Close").clicked() {
+                            self.show_normalize_settings = false;
                         }
                     });
                 });
             });
 
-        self.show_find_replace = open;
+        self.show_normalize_settings &= open;
+    }
+}
+
+/// The running app, or the screen shown instead when the library can't be opened
+/// or a library from an older version was found on first launch
+enum AppShell {
+    Running(Box<PromptDedupApp>),
+    Failed(StartupScreen),
+    Legacy(LegacyScreen),
+}
+
+impl AppShell {
+    fn launch() -> Self {
+        let mut config = AppConfig::load();
+        let first_launch = config.last_library.is_none() && config.recent.is_empty();
+        let path = config.last_library.clone()
+            .filter(|path| path.is_file())
+            .unwrap_or_else(prompt_dedup::default_library_path);
+        let legacy = find_legacy_library();
+
+        if let Some(legacy) = legacy {
+            if first_launch && !path.exists() {
+                return AppShell::Legacy(LegacyScreen { legacy, config, error: None });
+            }
+            // Otherwise offer it under Open Recent
+            if !config.recent.contains(&legacy) {
+                config.recent.push(legacy);
+            }
+        }
+
+        AppShell::open(path, config)
+    }
+
+    fn open(path: PathBuf, config: AppConfig) -> Self {
+        match Library::open(&path) {
+            Ok(library) => AppShell::Running(Box::new(PromptDedupApp::new(library, path, config))),
+            Err(e) => AppShell::Failed(StartupScreen::new(path, e, config)),
+        }
+    }
+}
+
+impl eframe::App for AppShell {
+    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        match self {
+            AppShell::Running(app) => app.update(ctx, frame),
+            AppShell::Failed(screen) => {
+                if let Some(app) = screen.show(ctx) {
+                    *self = AppShell::Running(Box::new(app));
+                }
+            }
+            AppShell::Legacy(screen) => {
+                if let Some(shell) = screen.show(ctx) {
+                    *self = shell;
+                }
+            }
+        }
+    }
+}
+
+/// `prompts.db` left in the working folder or next to the executable by
+/// versions that didn't use the app data folder
+fn find_legacy_library() -> Option<PathBuf> {
+    let exe_dir = std::env::current_exe().ok().and_then(|exe| exe.parent().map(Path::to_path_buf));
+    let default = prompt_dedup::default_library_path().canonicalize().ok();
+    [Some(PathBuf::from(".")), exe_dir]
+        .into_iter()
+        .flatten()
+        .filter_map(|dir| dir.join("prompts.db").canonicalize().ok())
+        .find(|path| path.is_file() && Some(path) != default.as_ref())
+}
+
+/// Move a library file along with its SQLite journal files, copying when a
+/// rename can't cross to the other drive
+fn move_library(from: &Path, to: &Path) -> std::io::Result<()> {
+    if let Some(dir) = to.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    for suffix in ["", "-wal", "-shm"] {
+        let source = PathBuf::from(format!("{}{}", from.display(), suffix));
+        if !source.exists() {
+            continue;
+        }
+        let target = PathBuf::from(format!("{}{}", to.display(), suffix));
+        if std::fs::rename(&source, &target).is_err() {
+            std::fs::copy(&source, &target)?;
+            std::fs::remove_file(&source)?;
+        }
     }
+    Ok(())
+}
+
+/// First launch with a library from an older version lying around: move it
+/// into the app data folder, open it where it is, or start fresh
+struct LegacyScreen {
+    legacy: PathBuf,
+    config: AppConfig,
+    /// Why the last move failed
+    error: Option<String>,
+}
+
+impl LegacyScreen {
+    fn show(&mut self, ctx: &egui::Context) -> Option<AppShell> {
+        let mut choice = None;
+        let target = prompt_dedup::default_library_path();
+
+        egui::CentralPanel::default()
+            .frame(egui::Frame::new().fill(colors::CRUST).inner_margin(40.0))
+            .show(ctx, |ui| {
+                ui.vertical_centered(|ui| {
+                    egui::Frame::new()
+                        .fill(colors::BASE)
+                        .inner_margin(24.0)
+                        .corner_radius(8.0)
+                        .stroke(Stroke::new(1.0, colors::SURFACE0))
+                        .show(ui, |ui| {
+                            ui.set_max_width(640.0);
+                            ui.vertical(|ui| {
+                                ui.heading("Found a library from an older version");
+                                ui.add_space(8.0);
+                                ui.label(egui::RichText::new(self.legacy.display().to_string()).color(colors::SUBTEXT));
+                                ui.add_space(12.0);
+                                ui.label(format!(
+                                    "Older versions kept prompts.db next to the app. Libraries now live in {}.",
+                                    prompt_dedup::data_dir().display()
+                                ));
+                                if let Some(error) = &self.error {
+                                    ui.add_space(8.0);
+                                    ui.label(egui::RichText::new(format!("Couldn't move it: {}", error)).color(colors::RED));
+                                }
+                                ui.add_space(16.0);
+
+                                ui.horizontal_wrapped(|ui| {
+                                    let move_btn = egui::Button::new(
+                                        egui::RichText::new("Move to App Folder").color(colors::CRUST)
+                                    ).fill(colors::GREEN);
+                                    if ui.add(move_btn).on_hover_text(target.display().to_string()).clicked() {
+                                        choice = Some(LegacyChoice::Move);
+                                    }
+                                    if ui.button("Open Where It Is").clicked() {
+                                        choice = Some(LegacyChoice::Open);
+                                    }
+                                    if ui.button("Start a New Library").clicked() {
+                                        choice = Some(LegacyChoice::New);
+                                    }
+                                });
+                            });
+                        });
+                });
+            });
+
+        let path = match choice? {
+            LegacyChoice::Move => match move_library(&self.legacy, &target) {
+                Ok(()) => target,
+                Err(e) => {
+                    self.error = Some(e.to_string());
+                    return None;
+                }
+            },
+            LegacyChoice::Open => self.legacy.clone(),
+            LegacyChoice::New => {
+                // Still reachable from Open Recent
+                self.config.recent.push(self.legacy.clone());
+                target
+            }
+        };
+        Some(AppShell::open(path, std::mem::take(&mut self.config)))
+    }
+}
+
+enum LegacyChoice {
+    Move,
+    Open,
+    New,
+}
+
+/// Explains why the library didn't open and offers ways forward
+struct StartupScreen {
+    path: PathBuf,
+    error: prompt_dedup::Error,
+    config: AppConfig,
+    /// Result of the last integrity check
+    integrity: Option<Result<Vec<String>, String>>,
+}
+
+/// Problems listed from an integrity check before the rest is summarized
+const INTEGRITY_PREVIEW: usize = 12;
+
+impl StartupScreen {
+    fn new(path: PathBuf, error: prompt_dedup::Error, config: AppConfig) -> Self {
+        StartupScreen { path, error, config, integrity: None }
+    }
+
+    /// Best guess at what went wrong, in the user's terms
+    fn hint(&self) -> &'static str {
+        match self.error {
+            prompt_dedup::Error::NewerSchema { .. } => {
+                return "Update Prompt Deduplicator to open this library, or open another one.";
+            }
+            prompt_dedup::Error::BackupFailed(_) => {
+                return "The library was left unchanged. Make room on the disk or make its folder writable, then retry.";
+            }
+            _ => {}
+        }
+        let message = self.error.to_string().to_lowercase();
+        if message.contains("locked") || message.contains("busy") {
+            "Another program (or another copy of this app) is using the library. Close it and retry, or open it read-only."
+        } else if message.contains("readonly") || message.contains("read-only") || message.contains("permission") || message.contains("unable to open") {
+            "The file or its folder can't be written. Open it read-only, or move it somewhere writable."
+        } else if message.contains("malformed") || message.contains("not a database") || message.contains("corrupt") {
+            "The file looks damaged. Check it, then recover what can still be read into a new library."
+        } else {
+            "Try opening it again, opening it read-only, or checking it for damage."
+        }
+    }
+
+    /// Open `library` in the app and leave this screen
+    fn run(&mut self, library: Library, path: PathBuf, status: String) -> PromptDedupApp {
+        let config = std::mem::take(&mut self.config);
+        let mut app = PromptDedupApp::new(library, path, config);
+        if app.status_message.is_none() {
+            app.set_status(status, false);
+        }
+        app
+    }
+
+    fn try_open(&mut self, path: PathBuf) -> Option<PromptDedupApp> {
+        match Library::open(&path) {
+            Ok(library) => {
+                let status = format!("Opened library {}", library_name(&path));
+                Some(self.run(library, path, status))
+            }
+            Err(e) => {
+                self.path = path;
+                self.error = e;
+                self.integrity = None;
+                None
+            }
+        }
+    }
+
+    fn open_read_only(&mut self) -> Option<PromptDedupApp> {
+        match Library::open_read_only(&self.path) {
+            Ok(library) => {
+                let status = "Opened read-only, changes are disabled".to_string();
+                Some(self.run(library, self.path.clone(), status))
+            }
+            Err(e) => {
+                self.error = e;
+                None
+            }
+        }
+    }
+
+    /// `<name>-recovered.db` next to the damaged file, numbered if taken
+    fn recovery_path(&self) -> PathBuf {
+        let dir = self.path.parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        let name = library_name(&self.path);
+        (1..)
+            .map(|n| match n {
+                1 => dir.join(format!("{}-recovered.db", name)),
+                n => dir.join(format!("{}-recovered-{}.db", name, n)),
+            })
+            .find(|p| !p.exists())
+            .unwrap_or_default()
+    }
+
+    fn recover(&mut self) -> Option<PromptDedupApp> {
+        let target = self.recovery_path();
+        let report = match Library::recover(&self.path, &target) {
+            Ok(report) => report,
+            Err(e) => {
+                self.error = e;
+                return None;
+            }
+        };
+
+        let library = match Library::open(&target) {
+            Ok(library) => library,
+            Err(e) => {
+                self.error = e;
+                return None;
+            }
+        };
+        let copied = report.copied.iter()
+            .map(|(table, rows)| format!("{} {}", rows, table))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let mut status = format!("Recovered into {} ({})", library_name(&target), copied);
+        if !report.errors.is_empty() {
+            status.push_str(&format!(", {} tables were only partly readable", report.errors.len()));
+        }
+        Some(self.run(library, target, status))
+    }
+
+    fn show(&mut self, ctx: &egui::Context) -> Option<PromptDedupApp> {
+        let mut opened = None;
+
+        egui::CentralPanel::default()
+            .frame(egui::Frame::new().fill(colors::CRUST).inner_margin(40.0))
+            .show(ctx, |ui| {
+                ui.vertical_centered(|ui| {
+                    egui::Frame::new()
+                        .fill(colors::BASE)
+                        .inner_margin(24.0)
+                        .corner_radius(8.0)
+                        .stroke(Stroke::new(1.0, colors::SURFACE0))
+                        .show(ui, |ui| {
+                            ui.set_max_width(640.0);
+                            ui.vertical(|ui| {
+                                ui.heading("Couldn't open the prompt library");
+                                ui.add_space(8.0);
+                                ui.label(egui::RichText::new(self.path.display().to_string()).color(colors::SUBTEXT));
+                                ui.add_space(12.0);
+                                ui.label(egui::RichText::new(self.error.to_string()).color(colors::RED));
+                                ui.add_space(8.0);
+                                ui.label(self.hint());
+                                ui.add_space(16.0);
+
+                                ui.horizontal_wrapped(|ui| {
+                                    if ui.button("Retry").clicked() {
+                                        opened = self.try_open(self.path.clone());
+                                    }
+                                    if ui.button("Open Another...").clicked()
+                                        && let Some(path) = rfd::FileDialog::new()
+                                            .add_filter("Prompt library", &["db"])
+                                            .pick_file()
+                                    {
+                                        opened = self.try_open(path);
+                                    }
+                                    if ui.button("Create New...").clicked()
+                                        && let Some(path) = rfd::FileDialog::new()
+                                            .add_filter("Prompt library", &["db"])
+                                            .set_directory(prompt_dedup::data_dir())
+                                            .set_file_name("library.db")
+                                            .save_file()
+                                    {
+                                        opened = self.try_open(path);
+                                    }
+                                    let can_read = self.path.is_file();
+                                    if ui.add_enabled(can_read, egui::Button::new("Open Read-Only")).clicked() {
+                                        opened = self.open_read_only();
+                                    }
+                                    if ui.add_enabled(can_read, egui::Button::new("Check for Damage")).clicked() {
+                                        self.integrity = Some(Library::check_integrity(&self.path).map_err(|e| e.to_string()));
+                                    }
+                                });
+
+                                match &self.integrity {
+                                    None => {}
+                                    Some(Err(e)) => {
+                                        ui.add_space(16.0);
+                                        ui.label(format!("The integrity check couldn't run: {}", e));
+                                        ui.label(egui::RichText::new("Recovery can still copy out whatever is readable.")
+                                            .color(colors::SUBTEXT));
+                                    }
+                                    Some(Ok(problems)) if problems.is_empty() => {
+                                        ui.add_space(16.0);
+                                        ui.label(egui::RichText::new("No damage found. The file is probably locked or not writable.")
+                                            .color(colors::GREEN));
+                                    }
+                                    Some(Ok(problems)) => {
+                                        ui.add_space(16.0);
+                                        ui.label(format!("{} problems found:", problems.len()));
+                                        egui::ScrollArea::vertical().max_height(160.0).show(ui, |ui| {
+                                            for problem in problems.iter().take(INTEGRITY_PREVIEW) {
+                                                ui.label(egui::RichText::new(problem).color(colors::SUBTEXT).small());
+                                            }
+                                            if problems.len() > INTEGRITY_PREVIEW {
+                                                ui.label(egui::RichText::new(format!("... and {} more", problems.len() - INTEGRITY_PREVIEW))
+                                                    .color(colors::SUBTEXT).small());
+                                            }
+                                        });
+                                    }
+                                }
+
+                                if self.integrity.as_ref().is_some_and(|r| r.as_ref().map_or(true, |p| !p.is_empty())) {
+                                    ui.add_space(12.0);
+                                    ui.label(format!(
+                                        "Recovery copies every readable row into {} and leaves the original untouched.",
+                                        library_name(&self.recovery_path())
+                                    ));
+                                    ui.add_space(8.0);
+                                    let recover_btn = egui::Button::new(
+                                        egui::RichText::new("Recover into New Library").color(colors::CRUST)
+                                    ).fill(colors::GREEN);
+                                    if ui.add(recover_btn).clicked() {
+                                        opened = self.recover();
+                                    }
+                                }
+                            });
+                        });
+                });
+            });
+
+        opened
+    }
+}
+
+/// File name of a library without `.db`, e.g. "client-a"
+fn library_name(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+/// The first few line numbers, with "..." when there are more
+fn list_line_numbers(lines: &[usize]) -> String {
+    let listed: Vec<String> = lines.iter().take(10).map(|line| line.to_string()).collect();
+    let more = if lines.len() > 10 { ", ..." } else { "" };
+    format!("{}{}", listed.join(", "), more)
+}
+
+/// Prompt text followed by the file it came from, for listings
+fn format_with_source(prompt: &Prompt) -> String {
+    match &prompt.source_file {
+        Some(source) => format!("{}  ({})", prompt.text, source),
+        None => prompt.text.clone(),
+    }
+}
+
+/// `old` → `new` word by word, removed words struck through in red and added ones in green
+fn word_diff_label(ui: &mut egui::Ui, old: &str, new: &str) {
+    let ops = similarity::word_diff(old, new);
+    if ops.iter().all(|op| matches!(op, DiffOp::Same(_))) {
+        ui.label(egui::RichText::new("Only case or spacing changed").color(colors::SUBTEXT).small());
+        return;
+    }
+    ui.horizontal_wrapped(|ui| {
+        ui.spacing_mut().item_spacing.x = 4.0;
+        for op in ops {
+            match op {
+                DiffOp::Same(word) => ui.label(egui::RichText::new(word).small()),
+                DiffOp::Removed(word) => ui.label(egui::RichText::new(word).small().strikethrough().color(colors::RED)),
+                DiffOp::Added(word) => ui.label(egui::RichText::new(word).small().color(colors::GREEN)),
+            };
+        }
+    });
 }
 
 impl eframe::App for PromptDedupApp {
@@ -875,6 +4251,18 @@ impl eframe::App for PromptDedupApp {
         // Clear old status messages
         self.clear_old_status();
 
+        if self.title_pending {
+            self.title_pending = false;
+            let read_only = if self.library.is_read_only() { " (read-only)" } else { "" };
+            ctx.send_viewport_cmd(egui::ViewportCommand::Title(format!(
+                "Prompt Deduplicator — {}{}",
+                library_name(&self.library_path),
+                read_only
+            )));
+        }
+
+        self.render_menu_bar(ctx);
+
         // Keyboard shortcut: Cmd+R (Mac) / Ctrl+R (others)
         ctx.input(|i| {
             let modifier = if cfg!(target_os = "macos") {
@@ -901,6 +4289,46 @@ impl eframe::App for PromptDedupApp {
             self.render_find_replace_popup(ctx);
         }
 
+        // Import preview popup and background import progress
+        self.poll_import_job();
+        self.render_import_popup(ctx);
+        self.render_import_progress(ctx);
+
+        // Export options popup
+        if self.show_export_options {
+            self.render_export_options_popup(ctx);
+        }
+
+        // Wildcard export popup
+        if self.show_wildcard_export {
+            self.render_wildcard_export_popup(ctx);
+        }
+
+        // Split export popup
+        if self.show_split_export {
+            self.render_split_export_popup(ctx);
+        }
+
+        // A1111 styles export popup
+        if self.show_styles_export {
+            self.render_styles_export_popup(ctx);
+        }
+
+        // Tag rules popup
+        if self.show_tag_rules {
+            self.render_tag_rules_popup(ctx);
+        }
+
+        // Import history popup
+        if self.show_import_history {
+            self.render_import_history_popup(ctx);
+        }
+
+        // Normalization settings popup
+        if self.show_normalize_settings {
+            self.render_normalize_popup(ctx);
+        }
+
         // Status bar at bottom
         if let Some((message, is_error)) = &self.status_message {
             egui::TopBottomPanel::bottom("status_bar")
@@ -914,6 +4342,11 @@ impl eframe::App for PromptDedupApp {
                 });
         }
 
+        // Prompt detail panel next to Browse
+        if self.active_tab == Tab::Browse && self.detail.is_some() {
+            self.render_detail_panel(ctx);
+        }
+
         egui::CentralPanel::default()
             .frame(egui::Frame::new().fill(colors::CRUST).inner_margin(20.0))
             .show(ctx, |ui| {
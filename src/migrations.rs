@@ -0,0 +1,327 @@
+//! Schema upgrades keyed on `PRAGMA user_version`.
+//!
+//! Step `i` of [`MIGRATIONS`] takes a database from version `i` to `i + 1`.
+//! Each step runs in its own transaction together with the version bump, so
+//! a failed upgrade leaves the database at the last version that completed.
+//! Released steps must never change; add a new step instead.
+
+use rusqlite::{Connection, Result};
+use std::path::{Path, PathBuf};
+
+use crate::db::{self, NormalizeReport};
+use crate::error::Error;
+
+/// One schema change
+struct Migration {
+    description: &'static str,
+    /// Returns the prompts merged if the step rebuilt dedup keys
+    apply: fn(&Connection) -> Result<Option<NormalizeReport>>,
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration { description: "prompts, settings and wildcard tables", apply: baseline },
+    Migration { description: "tags and collections", apply: tags_and_collections },
+    Migration { description: "tag rules", apply: tag_rules },
+    Migration { description: "prompt metadata fields", apply: prompt_metadata },
+    Migration { description: "prompt sources", apply: prompt_sources },
+    Migration { description: "import batches", apply: import_batches },
+    Migration { description: "prompt versions", apply: prompt_versions },
+];
+
+/// Schema version this build creates and understands
+pub const SCHEMA_VERSION: i64 = MIGRATIONS.len() as i64;
+
+/// What happened when an older database was upgraded on open
+#[derive(Debug, Clone)]
+pub struct UpgradeReport {
+    pub from: i64,
+    pub to: i64,
+    /// Copy of the database as it was before upgrading
+    pub backup: Option<PathBuf>,
+    /// Descriptions of the steps that ran, oldest first
+    pub steps: Vec<&'static str>,
+    /// Prompts merged by a step that rebuilt dedup keys
+    pub merged: Option<NormalizeReport>,
+}
+
+pub fn user_version(conn: &Connection) -> Result<i64> {
+    conn.pragma_query_value(None, "user_version", |row| row.get(0))
+}
+
+/// Version of the schema on disk. Libraries from before versioning have no
+/// `user_version` but may already have the complete baseline schema.
+pub fn schema_version(conn: &Connection) -> Result<i64> {
+    let version = user_version(conn)?;
+    if version == 0 && has_baseline(conn)? {
+        return Ok(1);
+    }
+    Ok(version)
+}
+
+/// Nothing has been created in the database yet
+pub fn is_blank(conn: &Connection) -> Result<bool> {
+    conn.query_row("SELECT COUNT(*) = 0 FROM sqlite_master", [], |row| row.get(0))
+}
+
+fn has_baseline(conn: &Connection) -> Result<bool> {
+    for table in ["prompts", "settings", "wildcards", "wildcard_entries"] {
+        if !db::has_table(conn, table)? {
+            return Ok(false);
+        }
+    }
+    for column in ["is_template", "title", "normalized_text"] {
+        if !db::has_column(conn, "prompts", column)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Bring the database up to `SCHEMA_VERSION`, backing it up first when it
+/// holds data. Returns `None` when it was already current or brand new.
+pub fn upgrade(conn: &mut Connection) -> crate::Result<Option<UpgradeReport>> {
+    let stored = user_version(conn)?;
+    let version = schema_version(conn)?;
+    if version > SCHEMA_VERSION {
+        return Err(Error::NewerSchema { version, supported: SCHEMA_VERSION });
+    }
+    if version != stored {
+        conn.pragma_update(None, "user_version", version)?;
+    }
+    if version == SCHEMA_VERSION {
+        return Ok(None);
+    }
+
+    let is_new = is_blank(conn)?;
+    let backup = match conn.path().filter(|path| !is_new && !path.is_empty()) {
+        Some(path) => {
+            let backup = backup_path(Path::new(path), version);
+            conn.execute("VACUUM INTO ?1", [backup.to_string_lossy()])
+                .map_err(Error::BackupFailed)?;
+            Some(backup)
+        }
+        None => None,
+    };
+
+    let mut report = UpgradeReport { from: version, to: SCHEMA_VERSION, backup, steps: Vec::new(), merged: None };
+    for (step, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        let tx = conn.transaction()?;
+        if let Some(merged) = (migration.apply)(&tx)? {
+            report.merged = Some(merged);
+        }
+        tx.pragma_update(None, "user_version", step as i64 + 1)?;
+        tx.commit()?;
+        report.steps.push(migration.description);
+    }
+    Ok((!is_new).then_some(report))
+}
+
+/// `<name>.v<version>-backup.db` next to the database, numbered if taken
+fn backup_path(path: &Path, version: i64) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    (1..)
+        .map(|n| match n {
+            1 => path.with_file_name(format!("{}.v{}-backup.db", stem, version)),
+            n => path.with_file_name(format!("{}.v{}-backup-{}.db", stem, version, n)),
+        })
+        .find(|p| !p.exists())
+        .unwrap_or_default()
+}
+
+/// Version 1: the schema as it was before versioning. Older files may have
+/// any subset of it, so every part is created only when missing.
+fn baseline(conn: &Connection) -> Result<Option<NormalizeReport>> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS prompts (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            text TEXT NOT NULL UNIQUE,
+            source_file TEXT,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS settings (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS wildcards (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL UNIQUE,
+            source_file TEXT
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS wildcard_entries (
+            wildcard_id INTEGER NOT NULL REFERENCES wildcards(id) ON DELETE CASCADE,
+            prompt_id INTEGER NOT NULL REFERENCES prompts(id) ON DELETE CASCADE,
+            position INTEGER NOT NULL,
+            PRIMARY KEY (wildcard_id, prompt_id)
+        )",
+        [],
+    )?;
+
+    if !db::has_column(conn, "prompts", "is_template")? {
+        conn.execute("ALTER TABLE prompts ADD COLUMN is_template INTEGER NOT NULL DEFAULT 0", [])?;
+        db::mark_template_rows(conn)?;
+    }
+
+    if !db::has_column(conn, "prompts", "title")? {
+        conn.execute("ALTER TABLE prompts ADD COLUMN title TEXT", [])?;
+    }
+
+    // Databases created before normalization existed have no dedup key yet
+    if !db::has_column(conn, "prompts", "normalized_text")? {
+        conn.execute("ALTER TABLE prompts ADD COLUMN normalized_text TEXT", [])?;
+        let options = db::load_normalize_options(conn)?;
+        return db::rebuild_normalized_keys(conn, &options).map(Some);
+    }
+    Ok(None)
+}
+
+/// Version 2: many-to-many tags and named, ordered collections
+fn tags_and_collections(conn: &Connection) -> Result<Option<NormalizeReport>> {
+    conn.execute_batch(
+        "CREATE TABLE tags (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL UNIQUE COLLATE NOCASE
+        );
+        CREATE TABLE prompt_tags (
+            prompt_id INTEGER NOT NULL REFERENCES prompts(id) ON DELETE CASCADE,
+            tag_id INTEGER NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+            PRIMARY KEY (prompt_id, tag_id)
+        );
+        CREATE INDEX idx_prompt_tags_tag ON prompt_tags(tag_id);
+        CREATE TABLE collections (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        );
+        CREATE TABLE collection_prompts (
+            collection_id INTEGER NOT NULL REFERENCES collections(id) ON DELETE CASCADE,
+            prompt_id INTEGER NOT NULL REFERENCES prompts(id) ON DELETE CASCADE,
+            position INTEGER NOT NULL,
+            PRIMARY KEY (collection_id, prompt_id)
+        );",
+    )?;
+    Ok(None)
+}
+
+/// Version 3: keyword and regex rules that tag matching prompts
+fn tag_rules(conn: &Connection) -> Result<Option<NormalizeReport>> {
+    conn.execute_batch(
+        "CREATE TABLE tag_rules (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            kind TEXT NOT NULL,
+            pattern TEXT NOT NULL,
+            tag TEXT NOT NULL,
+            enabled INTEGER NOT NULL DEFAULT 1
+        );",
+    )?;
+    Ok(None)
+}
+
+/// Version 4: negative prompt, model, sampler settings, rating, favorite and notes
+fn prompt_metadata(conn: &Connection) -> Result<Option<NormalizeReport>> {
+    conn.execute_batch(
+        "ALTER TABLE prompts ADD COLUMN negative_prompt TEXT;
+        ALTER TABLE prompts ADD COLUMN model TEXT;
+        ALTER TABLE prompts ADD COLUMN sampler TEXT;
+        ALTER TABLE prompts ADD COLUMN steps INTEGER;
+        ALTER TABLE prompts ADD COLUMN cfg_scale REAL;
+        ALTER TABLE prompts ADD COLUMN seed INTEGER;
+        ALTER TABLE prompts ADD COLUMN rating INTEGER CHECK (rating BETWEEN 1 AND 5);
+        ALTER TABLE prompts ADD COLUMN favorite INTEGER NOT NULL DEFAULT 0;
+        ALTER TABLE prompts ADD COLUMN notes TEXT;",
+    )?;
+    Ok(None)
+}
+
+/// Version 5: every file and line a prompt was imported from, duplicates
+/// included. Existing prompts keep the one source file they had, plus those
+/// of the duplicates merged into them by the baseline step.
+fn prompt_sources(conn: &Connection) -> Result<Option<NormalizeReport>> {
+    conn.execute_batch(
+        "CREATE TABLE prompt_sources (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            prompt_id INTEGER NOT NULL REFERENCES prompts(id) ON DELETE CASCADE,
+            source_file TEXT NOT NULL,
+            line INTEGER,
+            batch_id INTEGER,
+            imported_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        );
+        CREATE INDEX idx_prompt_sources_prompt ON prompt_sources(prompt_id);
+        INSERT INTO prompt_sources (prompt_id, source_file, imported_at)
+            SELECT id, source_file, created_at FROM prompts WHERE source_file IS NOT NULL ORDER BY id;",
+    )?;
+    if db::has_table(conn, "pending_merges")? {
+        conn.execute(
+            "INSERT INTO prompt_sources (prompt_id, source_file, imported_at)
+             SELECT m.prompt_id, m.source_file, m.created_at FROM pending_merges m
+             JOIN prompts p ON p.id = m.prompt_id
+             WHERE m.source_file IS NOT NULL ORDER BY m.rowid",
+            [],
+        )?;
+    }
+    Ok(None)
+}
+
+/// Version 6: one row per import run, the prompts each one added and when a
+/// prompt was last edited. Imports recorded in `prompt_sources` become batches;
+/// a prompt belongs to the batch of its first source.
+fn import_batches(conn: &Connection) -> Result<Option<NormalizeReport>> {
+    conn.execute_batch(
+        "CREATE TABLE import_batches (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            source_file TEXT NOT NULL,
+            file_hash TEXT,
+            imported_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            records INTEGER NOT NULL DEFAULT 0,
+            added INTEGER NOT NULL DEFAULT 0,
+            undone_at DATETIME
+        );
+        ALTER TABLE prompts ADD COLUMN batch_id INTEGER REFERENCES import_batches(id) ON DELETE SET NULL;
+        ALTER TABLE prompts ADD COLUMN edited_at DATETIME;
+        CREATE INDEX idx_prompts_batch ON prompts(batch_id);
+        CREATE INDEX idx_import_batches_hash ON import_batches(file_hash);
+
+        INSERT INTO import_batches (id, source_file, imported_at, records)
+            SELECT batch_id, MIN(source_file), MIN(imported_at), COUNT(*) FROM prompt_sources
+            WHERE batch_id IS NOT NULL GROUP BY batch_id;
+        UPDATE prompts SET batch_id = (
+                SELECT s.batch_id FROM prompt_sources s WHERE s.prompt_id = prompts.id ORDER BY s.id LIMIT 1
+            );
+        UPDATE import_batches SET added = (SELECT COUNT(*) FROM prompts p WHERE p.batch_id = import_batches.id);",
+    )?;
+    Ok(None)
+}
+
+/// Version 7: every change to a prompt's text and why it was made. Duplicates
+/// merged by the baseline step become merge versions of the prompt they joined.
+fn prompt_versions(conn: &Connection) -> Result<Option<NormalizeReport>> {
+    conn.execute_batch(
+        "CREATE TABLE prompt_versions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            prompt_id INTEGER NOT NULL REFERENCES prompts(id) ON DELETE CASCADE,
+            old_text TEXT NOT NULL,
+            new_text TEXT NOT NULL,
+            reason TEXT NOT NULL,
+            detail TEXT,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        );
+        CREATE INDEX idx_prompt_versions_prompt ON prompt_versions(prompt_id);",
+    )?;
+    if db::has_table(conn, "pending_merges")? {
+        conn.execute_batch(
+            "INSERT INTO prompt_versions (prompt_id, old_text, new_text, reason)
+                SELECT m.prompt_id, m.old_text, p.text, 'merge' FROM pending_merges m
+                JOIN prompts p ON p.id = m.prompt_id ORDER BY m.rowid;
+            DROP TABLE pending_merges;",
+        )?;
+    }
+    Ok(None)
+}
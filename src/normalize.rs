@@ -0,0 +1,172 @@
+use unicode_normalization::UnicodeNormalization;
+
+/// Which steps of the normalization pipeline are applied when building the
+/// dedup key for a prompt
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NormalizeOptions {
+    pub unicode_nfkc: bool,
+    pub case_fold: bool,
+    pub collapse_whitespace: bool,
+    pub comma_spacing: bool,
+    pub strip_trailing_punctuation: bool,
+    pub sort_tags: bool,
+}
+
+impl Default for NormalizeOptions {
+    fn default() -> Self {
+        Self {
+            unicode_nfkc: true,
+            case_fold: true,
+            collapse_whitespace: true,
+            comma_spacing: true,
+            strip_trailing_punctuation: true,
+            sort_tags: false,
+        }
+    }
+}
+
+impl NormalizeOptions {
+    /// Settings keys paired with their current values, used to persist the options
+    pub fn settings(&self) -> [(&'static str, bool); 6] {
+        [
+            ("normalize.unicode_nfkc", self.unicode_nfkc),
+            ("normalize.case_fold", self.case_fold),
+            ("normalize.collapse_whitespace", self.collapse_whitespace),
+            ("normalize.comma_spacing", self.comma_spacing),
+            ("normalize.strip_trailing_punctuation", self.strip_trailing_punctuation),
+            ("normalize.sort_tags", self.sort_tags),
+        ]
+    }
+
+    /// Apply a single persisted setting, ignoring unknown keys
+    pub fn apply_setting(&mut self, key: &str, value: bool) {
+        match key {
+            "normalize.unicode_nfkc" => self.unicode_nfkc = value,
+            "normalize.case_fold" => self.case_fold = value,
+            "normalize.collapse_whitespace" => self.collapse_whitespace = value,
+            "normalize.comma_spacing" => self.comma_spacing = value,
+            "normalize.strip_trailing_punctuation" => self.strip_trailing_punctuation = value,
+            "normalize.sort_tags" => self.sort_tags = value,
+            _ => {}
+        }
+    }
+}
+
+const TRAILING_PUNCTUATION: &[char] = &['.', ',', ';', ':', '!', '?'];
+
+/// Build the dedup key for a prompt.
+/// Two prompts with the same key are treated as exact duplicates.
+pub fn normalize(text: &str, options: &NormalizeOptions) -> String {
+    let mut result: String = if options.unicode_nfkc {
+        text.nfkc().collect()
+    } else {
+        text.to_string()
+    };
+
+    if options.case_fold {
+        result = result.to_lowercase();
+    }
+
+    if options.collapse_whitespace {
+        result = result.split_whitespace().collect::<Vec<_>>().join(" ");
+    }
+
+    if options.comma_spacing || options.sort_tags {
+        let mut tags: Vec<&str> = result
+            .split(',')
+            .map(|tag| tag.trim())
+            .filter(|tag| !tag.is_empty())
+            .collect();
+
+        if options.sort_tags {
+            tags.sort_unstable();
+            tags.dedup();
+        }
+
+        result = tags.join(", ");
+    }
+
+    if options.comma_spacing {
+        result = remove_space_before_punctuation(&result);
+    }
+
+    if options.strip_trailing_punctuation {
+        result = result
+            .trim_end_matches(|c: char| c.is_whitespace() || TRAILING_PUNCTUATION.contains(&c))
+            .to_string();
+    }
+
+    // Prompts of only punctuation would all share the empty key, keep them apart
+    match result.trim() {
+        "" => text.trim().to_string(),
+        key => key.to_string(),
+    }
+}
+
+/// Turn "night ." into "night." and "close up : 1.2" into "close up: 1.2"
+fn remove_space_before_punctuation(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        if TRAILING_PUNCTUATION.contains(&c) {
+            let trimmed_len = result.trim_end().len();
+            result.truncate(trimmed_len);
+        }
+        result.push(c);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(text: &str) -> String {
+        normalize(text, &NormalizeOptions::default())
+    }
+
+    #[test]
+    fn folds_case_and_whitespace() {
+        assert_eq!(key("A  Red\tCat"), "a red cat");
+        assert_eq!(key("  a red cat \n"), "a red cat");
+    }
+
+    #[test]
+    fn normalizes_comma_and_punctuation_spacing() {
+        assert_eq!(key("red hair ,blue eyes,,  smile"), "red hair, blue eyes, smile");
+        assert_eq!(key("night ."), "night");
+        assert_eq!(key("close up : 1.2"), "close up: 1.2");
+    }
+
+    #[test]
+    fn keeps_weights() {
+        assert_eq!(key("(Red Hair:1.2), smile"), "(red hair:1.2), smile");
+        assert_ne!(key("(red hair:1.2)"), key("(red hair:1.3)"));
+    }
+
+    #[test]
+    fn strips_trailing_punctuation_only_when_asked() {
+        let options = NormalizeOptions { strip_trailing_punctuation: false, ..Default::default() };
+        assert_eq!(normalize("a cat!", &options), "a cat!");
+        assert_eq!(key("a cat!"), "a cat");
+    }
+
+    #[test]
+    fn sorts_tags() {
+        let options = NormalizeOptions { sort_tags: true, ..Default::default() };
+        assert_eq!(normalize("b, a, b", &options), "a, b");
+    }
+
+    #[test]
+    fn unicode_nfkc() {
+        assert_eq!(key("ｃａｔ"), "cat");
+        let options = NormalizeOptions { unicode_nfkc: false, ..Default::default() };
+        assert_eq!(normalize("ｃａｔ", &options), "ｃａｔ");
+    }
+
+    #[test]
+    fn punctuation_only_prompts_keep_distinct_keys() {
+        assert_eq!(key(","), ",");
+        assert_eq!(key(" ... "), "...");
+        assert_ne!(key(","), key("..."));
+    }
+}
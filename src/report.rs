@@ -0,0 +1,315 @@
+use serde_json::Value;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::error::{Error, Result};
+use crate::similarity::{self, DiffOp, SimilarPair};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReportFormat {
+    Html,
+    Markdown,
+}
+
+impl ReportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ReportFormat::Html => "html",
+            ReportFormat::Markdown => "md",
+        }
+    }
+}
+
+/// What to do with one similar pair
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    DeleteA,
+    DeleteB,
+    KeepBoth,
+}
+
+impl Action {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Action::DeleteA => "delete_a",
+            Action::DeleteB => "delete_b",
+            Action::KeepBoth => "keep_both",
+        }
+    }
+
+    fn describe(&self) -> &'static str {
+        match self {
+            Action::DeleteA => "Delete A, keep B",
+            Action::DeleteB => "Keep A, delete B",
+            Action::KeepBoth => "Keep both",
+        }
+    }
+
+    /// Accepts the exported names plus a few spellings reviewers tend to type
+    pub fn parse(value: &str) -> Option<Action> {
+        match value.trim().to_lowercase().replace([' ', '-'], "_").as_str() {
+            "delete_a" | "keep_b" | "a" => Some(Action::DeleteA),
+            "delete_b" | "keep_a" | "b" => Some(Action::DeleteB),
+            "keep_both" | "keep" | "skip" | "" => Some(Action::KeepBoth),
+            _ => None,
+        }
+    }
+}
+
+/// The action proposed for every pair, matching what "Remove All" would do
+pub const PROPOSED_ACTION: Action = Action::DeleteB;
+
+/// One reviewed pair read back from a decisions file
+#[derive(Debug, Clone)]
+pub struct Decision {
+    pub id_a: i64,
+    pub id_b: i64,
+    pub action: Action,
+    /// Prompt texts at the time of the report, when the file has them
+    pub text_a: Option<String>,
+    pub text_b: Option<String>,
+}
+
+impl Decision {
+    /// Keep the first prompt of a pair and delete the second, like "Remove All"
+    pub fn keep_first(pair: &SimilarPair) -> Decision {
+        Decision {
+            id_a: pair.id_a,
+            id_b: pair.id_b,
+            action: Action::DeleteB,
+            text_a: Some(pair.text_a.clone()),
+            text_b: Some(pair.text_b.clone()),
+        }
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '`' | '*' | '_' | '[' | ']' | '|' | '<' | '>' | '~') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped.replace('\n', " ")
+}
+
+/// Render both sides of a pair with the words unique to each side marked
+fn diff_sides(pair: &SimilarPair, format: ReportFormat) -> (String, String) {
+    let (mut left, mut right) = (Vec::new(), Vec::new());
+    for op in similarity::word_diff(&pair.text_a, &pair.text_b) {
+        match (op, format) {
+            (DiffOp::Same(w), ReportFormat::Html) => {
+                left.push(escape_html(&w));
+                right.push(escape_html(&w));
+            }
+            (DiffOp::Same(w), ReportFormat::Markdown) => {
+                left.push(escape_markdown(&w));
+                right.push(escape_markdown(&w));
+            }
+            (DiffOp::Removed(w), ReportFormat::Html) => left.push(format!("<del>{}</del>", escape_html(&w))),
+            (DiffOp::Removed(w), ReportFormat::Markdown) => left.push(format!("~~{}~~", escape_markdown(&w))),
+            (DiffOp::Added(w), ReportFormat::Html) => right.push(format!("<ins>{}</ins>", escape_html(&w))),
+            (DiffOp::Added(w), ReportFormat::Markdown) => right.push(format!("**{}**", escape_markdown(&w))),
+        }
+    }
+    (left.join(" "), right.join(" "))
+}
+
+/// Self-contained review report of the current pairs
+pub fn render_report(pairs: &[SimilarPair], threshold: f32, format: ReportFormat) -> String {
+    match format {
+        ReportFormat::Html => render_html(pairs, threshold),
+        ReportFormat::Markdown => render_markdown(pairs, threshold),
+    }
+}
+
+fn render_html(pairs: &[SimilarPair], threshold: f32) -> String {
+    let mut html = String::new();
+    html.push_str(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Deduplication Report</title>\n<style>\n\
+         body { font-family: sans-serif; background: #24273a; color: #cad3f5; margin: 2em; }\n\
+         table { border-collapse: collapse; width: 100%; }\n\
+         th, td { border: 1px solid #494d64; padding: 8px; vertical-align: top; text-align: left; }\n\
+         th { background: #363a4f; }\n\
+         del { background: #ed8796; color: #181926; text-decoration: line-through; }\n\
+         ins { background: #a6da95; color: #181926; text-decoration: none; }\n\
+         .score { color: #eed49f; white-space: nowrap; }\n\
+         .muted { color: #a5adce; }\n\
+         </style>\n</head>\n<body>\n<h1>Deduplication Report</h1>\n",
+    );
+    let _ = writeln!(
+        html,
+        "<p class=\"muted\">{} pairs &middot; metric: {} &middot; threshold: {:.0}%</p>",
+        pairs.len(),
+        similarity::METRIC_NAME,
+        threshold * 100.0
+    );
+    html.push_str(
+        "<p class=\"muted\">Words only in A are <del>struck</del>, words only in B are <ins>highlighted</ins>. \
+         Review the proposed actions in the accompanying decisions file.</p>\n",
+    );
+    html.push_str("<table>\n<tr><th>#</th><th>Prompt A</th><th>Prompt B</th><th>Similarity</th><th>Proposed action</th></tr>\n");
+
+    for (i, pair) in pairs.iter().enumerate() {
+        let (left, right) = diff_sides(pair, ReportFormat::Html);
+        let _ = writeln!(
+            html,
+            "<tr><td>{}</td><td><span class=\"muted\">#{}</span><br>{}</td><td><span class=\"muted\">#{}</span><br>{}</td><td class=\"score\">{:.0}%</td><td>{}</td></tr>",
+            i + 1,
+            pair.id_a,
+            left,
+            pair.id_b,
+            right,
+            pair.similarity * 100.0,
+            PROPOSED_ACTION.describe()
+        );
+    }
+
+    html.push_str("</table>\n</body>\n</html>\n");
+    html
+}
+
+fn render_markdown(pairs: &[SimilarPair], threshold: f32) -> String {
+    let mut md = String::from("# Deduplication Report\n\n");
+    let _ = writeln!(
+        md,
+        "{} pairs · metric: {} · threshold: {:.0}%\n",
+        pairs.len(),
+        similarity::METRIC_NAME,
+        threshold * 100.0
+    );
+    md.push_str("Words only in A are ~~struck~~, words only in B are **bold**. ");
+    md.push_str("Review the proposed actions in the accompanying decisions file.\n\n");
+    md.push_str("| # | Prompt A | Prompt B | Similarity | Proposed action |\n");
+    md.push_str("|---|---|---|---|---|\n");
+
+    for (i, pair) in pairs.iter().enumerate() {
+        let (left, right) = diff_sides(pair, ReportFormat::Markdown);
+        let _ = writeln!(
+            md,
+            "| {} | #{} {} | #{} {} | {:.0}% | {} |",
+            i + 1,
+            pair.id_a,
+            left,
+            pair.id_b,
+            right,
+            pair.similarity * 100.0,
+            PROPOSED_ACTION.describe()
+        );
+    }
+    md
+}
+
+/// Write the report and a decisions CSV next to it (`<name>.decisions.csv`)
+/// that reviewers edit and import back. Returns the decisions file path.
+pub fn export_report(
+    pairs: &[SimilarPair],
+    threshold: f32,
+    format: ReportFormat,
+    path: &Path,
+) -> io::Result<PathBuf> {
+    fs::write(path, render_report(pairs, threshold, format))?;
+
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let decisions_path = path.with_file_name(format!("{}.decisions.csv", stem));
+    let mut writer = csv::Writer::from_path(&decisions_path)?;
+    writer.write_record(["pair", "id_a", "id_b", "similarity", "action", "text_a", "text_b"])?;
+    for (i, pair) in pairs.iter().enumerate() {
+        writer.write_record([
+            (i + 1).to_string(),
+            pair.id_a.to_string(),
+            pair.id_b.to_string(),
+            format!("{:.2}", pair.similarity),
+            PROPOSED_ACTION.as_str().to_string(),
+            pair.text_a.clone(),
+            pair.text_b.clone(),
+        ])?;
+    }
+    writer.flush()?;
+
+    Ok(decisions_path)
+}
+
+/// Read a decisions file: CSV with `id_a`, `id_b` and `action` columns, or a
+/// JSON array of objects with the same keys
+pub fn read_decisions(path: &Path) -> Result<Vec<Decision>> {
+    let is_json = path.extension().is_some_and(|e| e.eq_ignore_ascii_case("json"));
+    if is_json {
+        let contents = fs::read_to_string(path).map_err(Error::Read)?;
+        read_decisions_json(&contents)
+    } else {
+        read_decisions_csv(path)
+    }
+    .map_err(Error::InvalidDecisions)
+}
+
+fn read_decisions_csv(path: &Path) -> Result<Vec<Decision>, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_path(path)
+        .map_err(|e| e.to_string())?;
+    let headers = reader.headers().map_err(|e| e.to_string())?.clone();
+    let column = |name: &str| headers.iter().position(|h| h.trim().eq_ignore_ascii_case(name));
+    let (Some(id_a_col), Some(id_b_col), Some(action_col)) = (column("id_a"), column("id_b"), column("action")) else {
+        return Err("Decisions file needs id_a, id_b and action columns".to_string());
+    };
+    let (text_a_col, text_b_col) = (column("text_a"), column("text_b"));
+
+    let mut decisions = Vec::new();
+    for (i, record) in reader.records().enumerate() {
+        let record = record.map_err(|e| e.to_string())?;
+        let line = i + 2;
+        let field = |col: usize| record.get(col).unwrap_or("").trim();
+        let id = |col: usize| field(col).parse::<i64>()
+            .map_err(|_| format!("Line {}: invalid id '{}'", line, field(col)));
+        let action = Action::parse(field(action_col))
+            .ok_or_else(|| format!("Line {}: unknown action '{}'", line, field(action_col)))?;
+
+        decisions.push(Decision {
+            id_a: id(id_a_col)?,
+            id_b: id(id_b_col)?,
+            action,
+            text_a: text_a_col.and_then(|c| record.get(c)).map(str::to_string),
+            text_b: text_b_col.and_then(|c| record.get(c)).map(str::to_string),
+        });
+    }
+    Ok(decisions)
+}
+
+fn read_decisions_json(contents: &str) -> Result<Vec<Decision>, String> {
+    let value: Value = serde_json::from_str(contents).map_err(|e| e.to_string())?;
+    let Value::Array(items) = value else {
+        return Err("Decisions file must be a JSON array".to_string());
+    };
+
+    items.iter()
+        .enumerate()
+        .map(|(i, item)| {
+            let id = |key: &str| item.get(key)
+                .and_then(Value::as_i64)
+                .ok_or_else(|| format!("Entry {}: missing {}", i + 1, key));
+            let action_text = item.get("action").and_then(Value::as_str).unwrap_or("");
+            let action = Action::parse(action_text)
+                .ok_or_else(|| format!("Entry {}: unknown action '{}'", i + 1, action_text))?;
+            let text = |key: &str| item.get(key).and_then(Value::as_str).map(str::to_string);
+
+            Ok(Decision {
+                id_a: id("id_a")?,
+                id_b: id("id_b")?,
+                action,
+                text_a: text("text_a"),
+                text_b: text("text_b"),
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,195 @@
+use regex::{Regex, RegexBuilder};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::db::Prompt;
+
+/// How a rule's pattern is matched
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuleKind {
+    /// `|`-separated words or phrases, matched as whole words ignoring case
+    Keywords,
+    /// A regular expression, matched ignoring case
+    Regex,
+}
+
+impl RuleKind {
+    pub const ALL: [RuleKind; 2] = [RuleKind::Keywords, RuleKind::Regex];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            RuleKind::Keywords => "Keywords",
+            RuleKind::Regex => "Regex",
+        }
+    }
+
+    /// Name stored in the database and used on the command line
+    pub fn key(&self) -> &'static str {
+        match self {
+            RuleKind::Keywords => "keywords",
+            RuleKind::Regex => "regex",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<RuleKind> {
+        RuleKind::ALL.into_iter().find(|kind| kind.key() == key)
+    }
+}
+
+/// Tags prompts whose text matches `pattern` with `tag`, e.g.
+/// `portrait|headshot` → `subject:portrait`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TagRule {
+    pub id: i64,
+    pub kind: RuleKind,
+    pub pattern: String,
+    pub tag: String,
+    pub enabled: bool,
+}
+
+impl TagRule {
+    pub fn compile(&self) -> Result<Regex, regex::Error> {
+        compile(self.kind, &self.pattern)
+    }
+}
+
+/// Build the matcher for a pattern, failing on an invalid regex or a
+/// keyword list with no words in it
+pub fn compile(kind: RuleKind, pattern: &str) -> Result<Regex, regex::Error> {
+    let source = match kind {
+        RuleKind::Keywords => {
+            let words: Vec<String> = pattern.split('|')
+                .map(str::trim)
+                .filter(|word| !word.is_empty())
+                .map(regex::escape)
+                .collect();
+            if words.is_empty() {
+                return Err(regex::Error::Syntax("no keywords given".to_string()));
+            }
+            format!(r"\b(?:{})\b", words.join("|"))
+        }
+        RuleKind::Regex => pattern.to_string(),
+    };
+    RegexBuilder::new(&source).case_insensitive(true).build()
+}
+
+/// The enabled rules of a library, compiled
+#[derive(Debug, Clone, Default)]
+pub struct RuleSet {
+    rules: Vec<(TagRule, Regex)>,
+}
+
+impl RuleSet {
+    /// Disabled rules are left out. Fails on the first pattern that doesn't compile.
+    pub fn new(rules: Vec<TagRule>) -> Result<RuleSet, regex::Error> {
+        let rules = rules.into_iter()
+            .filter(|rule| rule.enabled)
+            .map(|rule| rule.compile().map(|regex| (rule, regex)))
+            .collect::<Result<_, _>>()?;
+        Ok(RuleSet { rules })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Tags the rules give `text`, without repeats, in rule order
+    pub fn tags_for(&self, text: &str) -> Vec<&str> {
+        let mut tags: Vec<&str> = Vec::new();
+        for (rule, regex) in &self.rules {
+            if regex.is_match(text) && !tags.iter().any(|t| t.eq_ignore_ascii_case(&rule.tag)) {
+                tags.push(&rule.tag);
+            }
+        }
+        tags
+    }
+}
+
+/// How one rule did in a categorize run
+#[derive(Debug, Clone)]
+pub struct RuleOutcome {
+    pub rule: TagRule,
+    /// Prompts the rule matches
+    pub matched: usize,
+    /// Matched prompts that didn't have the tag yet
+    pub added: usize,
+}
+
+/// Prompts given more than one tag of the same `namespace:` by different rules,
+/// e.g. both `style:cyberpunk` and `style:watercolor`
+#[derive(Debug, Clone)]
+pub struct Conflict {
+    pub namespace: String,
+    pub tags: Vec<String>,
+    pub prompt_ids: Vec<i64>,
+}
+
+/// What running the rules over some prompts does, or would do on a dry run
+#[derive(Debug, Clone, Default)]
+pub struct CategorizeReport {
+    pub dry_run: bool,
+    /// Prompts the rules were run over
+    pub checked: usize,
+    pub rules: Vec<RuleOutcome>,
+    pub conflicts: Vec<Conflict>,
+    /// `(prompt id, tag)` pairs that are new
+    pub assignments: Vec<(i64, String)>,
+}
+
+impl CategorizeReport {
+    /// Prompts that get at least one new tag
+    pub fn tagged_prompts(&self) -> usize {
+        let mut ids: Vec<i64> = self.assignments.iter().map(|(id, _)| *id).collect();
+        ids.dedup();
+        ids.len()
+    }
+}
+
+/// Match `rules` against `prompts`. `existing` is each prompt's current tags,
+/// so only missing tags become assignments.
+pub fn categorize(rules: &RuleSet, prompts: &[Prompt], existing: &HashMap<i64, Vec<String>>) -> CategorizeReport {
+    let mut report = CategorizeReport {
+        checked: prompts.len(),
+        rules: rules.rules.iter()
+            .map(|(rule, _)| RuleOutcome { rule: rule.clone(), matched: 0, added: 0 })
+            .collect(),
+        ..Default::default()
+    };
+    let mut conflicts: BTreeMap<(String, Vec<String>), Vec<i64>> = BTreeMap::new();
+
+    for prompt in prompts {
+        let current = existing.get(&prompt.id).map(Vec::as_slice).unwrap_or_default();
+        let mut tags: Vec<&str> = Vec::new();
+        for ((rule, regex), outcome) in rules.rules.iter().zip(&mut report.rules) {
+            if !regex.is_match(&prompt.text) {
+                continue;
+            }
+            outcome.matched += 1;
+            if tags.iter().any(|t| t.eq_ignore_ascii_case(&rule.tag)) {
+                continue;
+            }
+            tags.push(&rule.tag);
+            if !current.iter().any(|t| t.eq_ignore_ascii_case(&rule.tag)) {
+                outcome.added += 1;
+                report.assignments.push((prompt.id, rule.tag.clone()));
+            }
+        }
+
+        let mut by_namespace: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for tag in tags {
+            if let Some((namespace, _)) = tag.split_once(':') {
+                by_namespace.entry(namespace.to_lowercase()).or_default().push(tag.to_string());
+            }
+        }
+        for (namespace, mut tags) in by_namespace {
+            if tags.len() > 1 {
+                tags.sort();
+                conflicts.entry((namespace, tags)).or_default().push(prompt.id);
+            }
+        }
+    }
+
+    report.conflicts = conflicts.into_iter()
+        .map(|((namespace, tags), prompt_ids)| Conflict { namespace, tags, prompt_ids })
+        .collect();
+    report
+}
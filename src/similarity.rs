@@ -59,6 +59,26 @@ pub fn find_similar_pairs(
     pairs
 }
 
+/// Keep the pairs whose negative prompts are also at least `threshold`
+/// similar, scoring each by the less similar of the two. Prompts missing from
+/// `negatives` count as having an empty negative prompt.
+pub fn with_similar_negatives(
+    pairs: Vec<SimilarPair>,
+    negatives: &HashMap<i64, String>,
+    threshold: f32,
+) -> Vec<SimilarPair> {
+    let negative = |id: i64| negatives.get(&id).map(String::as_str).unwrap_or("");
+    let mut pairs: Vec<SimilarPair> = pairs.into_iter()
+        .filter_map(|mut pair| {
+            let sim = jaccard_similarity(negative(pair.id_a), negative(pair.id_b));
+            pair.similarity = pair.similarity.min(sim);
+            (sim >= threshold).then_some(pair)
+        })
+        .collect();
+    pairs.sort_by(|a, b| b.similarity.partial_cmp(&a.similarity).unwrap());
+    pairs
+}
+
 /// Group similar prompts together (transitive grouping)
 pub fn group_similar_prompts(
     prompts: &[(i64, String)],
@@ -126,3 +146,128 @@ pub fn group_similar_prompts(
 
     groups
 }
+
+/// Number the connected groups formed by a list of pairs (transitive grouping).
+/// Returns a cluster ID, starting at 1, for every prompt that appears in a pair.
+pub fn clusters_from_pairs(pairs: &[SimilarPair]) -> HashMap<i64, usize> {
+    let mut adjacency: HashMap<i64, Vec<i64>> = HashMap::new();
+    for pair in pairs {
+        adjacency.entry(pair.id_a).or_default().push(pair.id_b);
+        adjacency.entry(pair.id_b).or_default().push(pair.id_a);
+    }
+
+    let mut ids: Vec<i64> = adjacency.keys().copied().collect();
+    ids.sort_unstable();
+
+    let mut clusters: HashMap<i64, usize> = HashMap::new();
+    let mut next_cluster = 1;
+
+    for id in ids {
+        if clusters.contains_key(&id) {
+            continue;
+        }
+
+        let mut stack = vec![id];
+        while let Some(current) = stack.pop() {
+            if clusters.insert(current, next_cluster).is_some() {
+                continue;
+            }
+            for neighbor in &adjacency[&current] {
+                if !clusters.contains_key(neighbor) {
+                    stack.push(*neighbor);
+                }
+            }
+        }
+        next_cluster += 1;
+    }
+
+    clusters
+}
+
+/// Name of the metric used by `jaccard_similarity`, for reports
+pub const METRIC_NAME: &str = "Jaccard similarity of word sets";
+
+/// One piece of a word-level diff between two prompts
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiffOp {
+    Same(String),
+    /// Only in the first prompt
+    Removed(String),
+    /// Only in the second prompt
+    Added(String),
+}
+
+/// Word-level diff using the longest common subsequence of words
+pub fn word_diff(a: &str, b: &str) -> Vec<DiffOp> {
+    let words_a: Vec<&str> = a.split_whitespace().collect();
+    let words_b: Vec<&str> = b.split_whitespace().collect();
+    let (n, m) = (words_a.len(), words_b.len());
+
+    // lcs[i][j] = LCS length of words_a[i..] and words_b[j..]
+    let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if words_a[i].eq_ignore_ascii_case(words_b[j]) {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut ops = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if words_a[i].eq_ignore_ascii_case(words_b[j]) {
+            ops.push(DiffOp::Same(words_a[i].to_string()));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            ops.push(DiffOp::Removed(words_a[i].to_string()));
+            i += 1;
+        } else {
+            ops.push(DiffOp::Added(words_b[j].to_string()));
+            j += 1;
+        }
+    }
+    ops.extend(words_a[i..].iter().map(|w| DiffOp::Removed(w.to_string())));
+    ops.extend(words_b[j..].iter().map(|w| DiffOp::Added(w.to_string())));
+    ops
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn same(word: &str) -> DiffOp {
+        DiffOp::Same(word.to_string())
+    }
+
+    fn removed(word: &str) -> DiffOp {
+        DiffOp::Removed(word.to_string())
+    }
+
+    fn added(word: &str) -> DiffOp {
+        DiffOp::Added(word.to_string())
+    }
+
+    #[test]
+    fn diffs_words_in_order() {
+        assert_eq!(
+            word_diff("a red cat sitting", "a small red cat  standing"),
+            [same("a"), added("small"), same("red"), same("cat"), removed("sitting"), added("standing")]
+        );
+    }
+
+    #[test]
+    fn matches_words_ignoring_case_and_keeps_the_first_spelling() {
+        assert_eq!(word_diff("A Red cat", "a red dog"), [same("A"), same("Red"), removed("cat"), added("dog")]);
+    }
+
+    #[test]
+    fn diffs_against_empty_text() {
+        assert_eq!(word_diff("", "a cat"), [added("a"), added("cat")]);
+        assert_eq!(word_diff("a cat", " "), [removed("a"), removed("cat")]);
+        assert!(word_diff("", "").is_empty());
+    }
+}
@@ -0,0 +1,23 @@
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// True when the prompt loads a LoRA or LyCORIS network, e.g. `<lora:detail:0.8>`
+pub fn has_lora(text: &str) -> bool {
+    static LORA: OnceLock<Regex> = OnceLock::new();
+    LORA.get_or_init(|| Regex::new(r"(?i)<(?:lora|lyco):[^<>]+>").unwrap())
+        .is_match(text)
+}
+
+/// True when the prompt gives part of itself an explicit attention weight, e.g. `(red hair:1.2)`
+pub fn has_weights(text: &str) -> bool {
+    static WEIGHT: OnceLock<Regex> = OnceLock::new();
+    WEIGHT.get_or_init(|| Regex::new(r"\([^()]*:\s*-?\d+(?:\.\d+)?\s*\)").unwrap())
+        .is_match(text)
+}
+
+/// Words in the prompt, counting comma-separated tags without spaces as words too
+pub fn word_count(text: &str) -> usize {
+    text.split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|word| !word.is_empty())
+        .count()
+}
@@ -0,0 +1,135 @@
+use regex::Regex;
+use serde_yaml::Value;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use crate::import::{self, TextEncoding};
+
+/// One sd-dynamic-prompts wildcard, e.g. `__colors__` or `__clothing/tops__`
+#[derive(Debug, Clone)]
+pub struct WildcardSet {
+    /// Name without the surrounding underscores
+    pub name: String,
+    /// File the wildcard was read from, relative to the imported folder
+    pub source_file: String,
+    pub entries: Vec<String>,
+}
+
+/// True when the prompt uses Dynamic Prompts syntax (`{a|b}` variants or
+/// `__name__` wildcard references) and expands to several literal prompts
+pub fn is_template(text: &str) -> bool {
+    static TEMPLATE: OnceLock<Regex> = OnceLock::new();
+    TEMPLATE
+        .get_or_init(|| Regex::new(r"\{[^{}]*\|[^{}]*\}|__[\w\-./*]+__").unwrap())
+        .is_match(text)
+}
+
+/// Read every `.txt`, `.yaml` and `.yml` file under `root` as wildcards.
+/// Files that fail to parse are returned as errors alongside the sets that did load.
+pub fn read_folder(root: &Path) -> io::Result<(Vec<WildcardSet>, Vec<String>)> {
+    let mut sets = Vec::new();
+    let mut errors = Vec::new();
+    read_dir_recursive(root, root, &mut sets, &mut errors)?;
+    sets.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok((sets, errors))
+}
+
+fn read_dir_recursive(
+    root: &Path,
+    dir: &Path,
+    sets: &mut Vec<WildcardSet>,
+    errors: &mut Vec<String>,
+) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            read_dir_recursive(root, &path, sets, errors)?;
+            continue;
+        }
+
+        let extension = path.extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let relative = path.strip_prefix(root).unwrap_or(&path);
+        let source_file = relative.to_string_lossy().replace('\\', "/");
+
+        let bytes = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                errors.push(format!("{}: {}", source_file, e));
+                continue;
+            }
+        };
+        let text = import::decode(&bytes, TextEncoding::Auto).text;
+
+        match extension.as_str() {
+            "txt" => {
+                let name = relative.with_extension("").to_string_lossy().replace('\\', "/");
+                sets.push(WildcardSet {
+                    name,
+                    source_file,
+                    entries: parse_txt(&text),
+                });
+            }
+            "yaml" | "yml" => match serde_yaml::from_str::<Value>(&text) {
+                Ok(value) => collect_yaml(&value, "", &source_file, sets),
+                Err(e) => errors.push(format!("{}: {}", source_file, e)),
+            },
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// One entry per line; blank lines and `#` comments are skipped
+fn parse_txt(text: &str) -> Vec<String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Nested YAML keys become path segments: `clothing: { tops: [...] }` is `__clothing/tops__`
+fn collect_yaml(value: &Value, prefix: &str, source_file: &str, sets: &mut Vec<WildcardSet>) {
+    match value {
+        Value::Mapping(map) => {
+            for (key, child) in map {
+                let Some(key) = yaml_scalar(key) else {
+                    continue;
+                };
+                let name = if prefix.is_empty() {
+                    key
+                } else {
+                    format!("{}/{}", prefix, key)
+                };
+                collect_yaml(child, &name, source_file, sets);
+            }
+        }
+        Value::Sequence(items) if !prefix.is_empty() => {
+            let entries = items.iter()
+                .filter_map(yaml_scalar)
+                .map(|entry| entry.trim().to_string())
+                .filter(|entry| !entry.is_empty())
+                .collect();
+            sets.push(WildcardSet {
+                name: prefix.to_string(),
+                source_file: source_file.to_string(),
+                entries,
+            });
+        }
+        Value::Tagged(tagged) => collect_yaml(&tagged.value, prefix, source_file, sets),
+        _ => {}
+    }
+}
+
+fn yaml_scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
@@ -0,0 +1,70 @@
+use std::path::Path;
+
+use prompt_dedup::db::EditReason;
+use prompt_dedup::normalize::NormalizeOptions;
+use prompt_dedup::{Error, ImportOptions, Library, PromptFilter, TextEncoding};
+
+fn import(library: &mut Library, dir: &Path, name: &str, text: &str) {
+    let path = dir.join(name);
+    std::fs::write(&path, text).unwrap();
+    library.import_file(&path, TextEncoding::Auto, &ImportOptions::default()).unwrap();
+}
+
+fn id_of(library: &Library, text: &str) -> i64 {
+    library.query(&PromptFilter::default()).unwrap()
+        .into_iter()
+        .find(|prompt| prompt.text == text)
+        .map(|prompt| prompt.id)
+        .unwrap()
+}
+
+/// (old, new, reason) of each version, newest first
+fn history(library: &Library, id: i64) -> Vec<(String, String, EditReason)> {
+    library.db().prompt_versions(id).unwrap()
+        .into_iter()
+        .map(|version| (version.old_text, version.new_text, version.reason))
+        .collect()
+}
+
+#[test]
+fn merging_edited_prompts_keeps_only_a_merge_version_of_the_other() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut library = Library::open(dir.path().join("library.db")).unwrap();
+    import(&mut library, dir.path(), "first.txt", "a red cat\n");
+    import(&mut library, dir.path(), "second.txt", "a blue dog\n");
+    let cat = id_of(&library, "a red cat");
+    let dog = id_of(&library, "a blue dog");
+
+    library.edit_prompt(cat, "a red kitten").unwrap();
+    library.edit_prompt(dog, "a blue puppy").unwrap();
+    assert!(matches!(library.edit_prompt(dog, "A red kitten"), Err(Error::DuplicateText(id)) if id == cat));
+    library.merge_prompt(dog, cat).unwrap();
+
+    assert_eq!(history(&library, cat), [
+        ("a blue puppy".to_string(), "a red kitten".to_string(), EditReason::Merge),
+        ("a red cat".to_string(), "a red kitten".to_string(), EditReason::Manual),
+    ]);
+    assert!(history(&library, dog).is_empty());
+    let sources: Vec<String> = library.db().prompt_sources(cat).unwrap()
+        .into_iter()
+        .map(|source| source.source_file)
+        .collect();
+    assert_eq!(sources, ["first.txt", "second.txt"]);
+}
+
+#[test]
+fn rebuilding_keys_records_merged_prompts() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut library = Library::open(dir.path().join("library.db")).unwrap();
+    import(&mut library, dir.path(), "tags.txt", "red, cat\ncat, red\n");
+    let first = id_of(&library, "red, cat");
+
+    let options = NormalizeOptions { sort_tags: true, ..Default::default() };
+    let report = library.db_mut().set_normalize_options(options).unwrap();
+    assert_eq!(report.removed_count(), 1);
+    assert_eq!(library.count().unwrap(), 1);
+    assert_eq!(history(&library, first), [
+        ("cat, red".to_string(), "red, cat".to_string(), EditReason::Merge),
+    ]);
+    assert_eq!(library.db().prompt_sources(first).unwrap().len(), 2);
+}
@@ -0,0 +1,113 @@
+use prompt_dedup::{Database, Library, SCHEMA_VERSION};
+use rusqlite::Connection;
+
+/// A library as the first release left it: no `user_version`, no dedup keys
+fn v0_fixture(path: &std::path::Path) {
+    let conn = Connection::open(path).unwrap();
+    conn.execute_batch(
+        "CREATE TABLE prompts (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            text TEXT NOT NULL UNIQUE,
+            source_file TEXT,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        );
+        INSERT INTO prompts (text, source_file) VALUES
+            ('A red cat', 'first.txt'),
+            ('a red  cat.', 'second.txt'),
+            ('a blue dog', 'first.txt');",
+    )
+    .unwrap();
+}
+
+fn user_version(path: &std::path::Path) -> i64 {
+    Connection::open(path).unwrap()
+        .pragma_query_value(None, "user_version", |row| row.get(0))
+        .unwrap()
+}
+
+#[test]
+fn upgrades_v0_library_with_backup_and_merge_report() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("library.db");
+    v0_fixture(&path);
+    assert_eq!(Library::pending_upgrade(&path).unwrap(), Some(0));
+
+    let mut db = Database::open(&path.to_string_lossy()).unwrap();
+    let upgrade = db.take_upgrade_report().unwrap();
+    assert_eq!((upgrade.from, upgrade.to), (0, SCHEMA_VERSION));
+    assert_eq!(upgrade.steps.len(), SCHEMA_VERSION as usize);
+    let backup = upgrade.backup.unwrap();
+    assert_eq!(backup, dir.path().join("library.v0-backup.db"));
+    assert!(backup.is_file());
+
+    let merged = db.take_migration_report().unwrap();
+    assert_eq!(merged.collisions.len(), 1);
+    assert_eq!(merged.collisions[0].kept.id, 1);
+    let removed: Vec<i64> = merged.collisions[0].removed.iter().map(|p| p.id).collect();
+    assert_eq!(removed, [2]);
+    drop(db);
+
+    assert_eq!(user_version(&path), SCHEMA_VERSION);
+    assert_eq!(Library::pending_upgrade(&path).unwrap(), None);
+    // The backup still has all three prompts
+    let count: i64 = Connection::open(&backup).unwrap()
+        .query_row("SELECT COUNT(*) FROM prompts", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(count, 3);
+}
+
+#[test]
+fn new_and_current_libraries_need_no_upgrade() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("library.db");
+    assert_eq!(Library::pending_upgrade(&path).unwrap(), None);
+
+    std::fs::write(&path, b"").unwrap();
+    assert_eq!(Library::pending_upgrade(&path).unwrap(), None);
+
+    let mut db = Database::open(&path.to_string_lossy()).unwrap();
+    assert!(db.take_upgrade_report().is_none());
+    drop(db);
+    assert_eq!(user_version(&path), SCHEMA_VERSION);
+    assert_eq!(Library::pending_upgrade(&path).unwrap(), None);
+    assert!(!dir.path().join("library.v0-backup.db").exists());
+}
+
+#[test]
+fn refuses_newer_schema() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("library.db");
+    Database::open(&path.to_string_lossy()).unwrap();
+    Connection::open(&path).unwrap().pragma_update(None, "user_version", SCHEMA_VERSION + 1).unwrap();
+
+    assert!(matches!(
+        Database::open(&path.to_string_lossy()),
+        Err(prompt_dedup::Error::NewerSchema { .. })
+    ));
+}
+
+#[test]
+fn upgrade_keeps_sources_and_history_of_merged_duplicates() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("library.db");
+    v0_fixture(&path);
+
+    let library = Library::open(&path).unwrap();
+    let sources: Vec<String> = library.db().prompt_sources(1).unwrap()
+        .into_iter()
+        .map(|source| source.source_file)
+        .collect();
+    assert_eq!(sources, ["first.txt", "second.txt"]);
+
+    let versions = library.db().prompt_versions(1).unwrap();
+    assert_eq!(versions.len(), 1);
+    assert_eq!(versions[0].old_text, "a red  cat.");
+    assert_eq!(versions[0].new_text, "A red cat");
+    assert_eq!(versions[0].reason, prompt_dedup::db::EditReason::Merge);
+    assert!(library.db().prompt_versions(3).unwrap().is_empty());
+
+    let parked: bool = Connection::open(&path).unwrap()
+        .query_row("SELECT COUNT(*) > 0 FROM sqlite_master WHERE name = 'pending_merges'", [], |row| row.get(0))
+        .unwrap();
+    assert!(!parked);
+}
@@ -0,0 +1,76 @@
+use std::path::Path;
+
+use prompt_dedup::{ImportOptions, Library, PromptFilter, PromptMetadata, TextEncoding};
+
+fn import(library: &mut Library, dir: &Path, name: &str, text: &str) -> i64 {
+    let path = dir.join(name);
+    std::fs::write(&path, text).unwrap();
+    let summary = library.import_file(&path, TextEncoding::Auto, &ImportOptions::default()).unwrap();
+    summary.batch_id.unwrap()
+}
+
+fn id_of(library: &Library, text: &str) -> Option<i64> {
+    library.query(&PromptFilter::default()).unwrap()
+        .into_iter()
+        .find(|prompt| prompt.text == text)
+        .map(|prompt| prompt.id)
+}
+
+fn source_files(library: &Library, id: i64) -> Vec<String> {
+    library.db().prompt_sources(id).unwrap()
+        .into_iter()
+        .map(|source| source.source_file)
+        .collect()
+}
+
+#[test]
+fn undo_keeps_edited_prompts_and_prompts_imported_again() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut library = Library::open(dir.path().join("library.db")).unwrap();
+    let first = import(&mut library, dir.path(), "first.txt", "a red cat\na blue dog\na green frog\n");
+    let second = import(&mut library, dir.path(), "second.txt", "a blue dog\na white horse\n");
+
+    let frog = id_of(&library, "a green frog").unwrap();
+    library.edit_prompt(frog, "a green tree frog").unwrap();
+    let dog = id_of(&library, "a blue dog").unwrap();
+    assert_eq!(source_files(&library, dog), ["first.txt", "second.txt"]);
+
+    let batch = library.db().import_batches().unwrap().into_iter().find(|b| b.id == first).unwrap();
+    assert_eq!((batch.remaining, batch.edited, batch.seen_later), (3, 1, 1));
+
+    let summary = library.undo_import(first).unwrap();
+    assert_eq!((summary.removed, summary.kept_edited, summary.kept_seen_later), (1, 1, 1));
+
+    assert_eq!(id_of(&library, "a red cat"), None);
+    // The edited prompt keeps the source it was imported from
+    assert_eq!(source_files(&library, frog), ["first.txt"]);
+    // The prompt the second import found too now belongs to it, with only its source
+    assert_eq!(source_files(&library, dog), ["second.txt"]);
+    let dog_prompt = library.db().get_prompt(dog).unwrap().unwrap();
+    assert_eq!(dog_prompt.source_file.as_deref(), Some("second.txt"));
+
+    let summary = library.undo_import(second).unwrap();
+    assert_eq!((summary.removed, summary.kept_edited, summary.kept_seen_later), (2, 0, 0));
+    assert_eq!(library.count().unwrap(), 1);
+    assert!(library.undo_import(second).is_err());
+}
+
+#[test]
+fn saving_the_same_text_or_metadata_is_not_an_edit() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut library = Library::open(dir.path().join("library.db")).unwrap();
+    let batch = import(&mut library, dir.path(), "first.txt", "a red cat\na blue dog\n");
+
+    let cat = id_of(&library, "a red cat").unwrap();
+    library.edit_prompt(cat, "a red cat").unwrap();
+    assert!(library.db().prompt_versions(cat).unwrap().is_empty());
+
+    let dog = id_of(&library, "a blue dog").unwrap();
+    library.db().set_title(dog, "Dog").unwrap();
+    let metadata = PromptMetadata { rating: Some(5), favorite: true, ..Default::default() };
+    library.db().set_metadata(dog, &metadata).unwrap();
+
+    let summary = library.undo_import(batch).unwrap();
+    assert_eq!((summary.removed, summary.kept_edited), (2, 0));
+    assert_eq!(library.count().unwrap(), 0);
+}